@@ -12,7 +12,7 @@ fn run(mut cx: FunctionContext) -> JsResult<JsNumber> {
     thread::spawn(|| {
         let app = MainApp::new();
         let (rt, _control) = Runtime::new(app, pipe);
-        rt.run_blocking();
+        rt.run_blocking().unwrap();
     });
     Ok(cx.number(port))
 }
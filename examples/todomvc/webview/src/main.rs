@@ -11,6 +11,6 @@ fn main() {
         .size(1200, 900)
         .run(move |pipe| {
             let (rt, _control) = Runtime::new(MainApp::new(), pipe);
-            rt.run_blocking();
+            rt.run_blocking().unwrap();
         });
 }
@@ -210,6 +210,13 @@ impl ToTokens for HtmlElement {
         };
         for attr in &self.attributes {
             match attr {
+                Attribute::Html(attr) if attr.key == "key" => {
+                    // `key` is not forwarded to the DOM but identifies the element while diffing
+                    let value = &attr.value;
+                    ret.extend(quote! {
+                        .key(#value)
+                    })
+                },
                 Attribute::Html(attr) => {
                     let name = &attr.key;
                     let value = &attr.value;
@@ -1,7 +1,13 @@
 "use strict";
 
+import HistoryPanel from './history.js'
+
 const decoder = new TextDecoder();
 
+// Version of the binary patch format.
+// Must match `PATCH_FORMAT_VERSION` in src/vdom/serialize.rs
+const PATCH_FORMAT_VERSION = 5;
+
 function loadCss(css) {
     var s = document.createElement("style");
     s.innerHTML = css;
@@ -60,6 +66,34 @@ function addEvent(app, id, elem, evt) {
     }, {'passive': !evt.prevent_default});
 }
 
+// Hashes a 32-bit word into a FNV-1a hash
+function checksumWord(hash, word) {
+    return Math.imul(hash ^ word, 0x01000193) >>> 0;
+}
+
+// Computes the checksum of the structure of a DOM subtree in the same way as the backend:
+// text nodes, elements along with the lower 32 bits of their ids and their nesting are covered.
+// Other nodes such as comments are not part of the patched DOM and are skipped.
+function checksumNode(node, hash) {
+    if (node.nodeType === Node.TEXT_NODE) {
+        return checksumWord(hash, 1);
+    }
+    if (node.nodeType !== Node.ELEMENT_NODE) {
+        return hash;
+    }
+    hash = checksumWord(hash, 2);
+    if (node.hasAttribute("__id__")) {
+        let id = Number(node.getAttribute("__id__"));
+        hash = checksumWord(checksumWord(hash, 1), id % 2**32);
+    } else {
+        hash = checksumWord(hash, 0);
+    }
+    for (const child of node.childNodes) {
+        hash = checksumNode(child, hash);
+    }
+    return checksumWord(hash, 3);
+}
+
 class EventHandler {
     constructor(name, no_propagate, prevent_default) {
         this.name = name;
@@ -95,8 +129,8 @@ export default class App {
             root_element.appendChild(elem);
         }
 
-        this.pipe.onPatch = (e) => {
-            self.onPatch(e);
+        this.pipe.onPatch = (e, received) => {
+            self.onPatch(e, received);
         }
         this.pipe.onRunJsMsg = (id, js) => {
             self.onRunJsMsg(id, js);
@@ -104,15 +138,27 @@ export default class App {
         this.pipe.onLoadCss = loadCss;
         this.pipe.onInjectEvent = injectEvent;
         this.pipe.onDialog = (dialog) => { self.onDialog(dialog); };
+        this.pipe.onHistory = (update) => { self.onHistory(update); };
 
         this.afterRender = [];
         this.blobs = {}
+        this.history = null;
+        // server-rendered nodes may only be hydrated by the first patch
+        this.patched = false;
     }
 
     onDialog(dialog) {
         this.dialog_handler(this, dialog);
     }
 
+    onHistory(update) {
+        // the panel is only created if the backend keeps a history
+        if (this.history == null) {
+            this.history = new HistoryPanel(this.pipe);
+        }
+        this.history.update(update);
+    }
+
     getBlob(blob_id) {
         return this.blobs[blob_id];
     }
@@ -133,18 +179,45 @@ export default class App {
         this.pipe.socket.send(data);
     }
 
-    onPatch(patch_data) {
+    // Applies a patch on the next animation frame and acknowledges it to the backend,
+    // which paces its renders using the reported durations.
+    // If the patch cannot be applied, the failure is reported instead and the backend
+    // renders the DOM from scratch.
+    // `received` is the time the pipe received the patch, before decompressing it.
+    // The instructions are parsed by `patch.apply()`, thus `decode_us` only covers
+    // decompression and `apply_us` includes parsing.
+    onPatch(patch_data, received) {
+        if (received === undefined) {
+            received = performance.now();
+        }
         let patch = new Patch(patch_data, this.root_element.firstElementChild, this);
+        let prepared = performance.now();
         let self = this;
         window.requestAnimationFrame(() => {
-            patch.apply();
+            let before = performance.now();
+            try {
+                patch.apply();
+            } catch (e) {
+                console.error("Failed to apply patch: " + e.message);
+                self.pipe.sendPatchFailed(patch.frame === null ? 0 : patch.frame, e.message);
+                return;
+            }
+            self.patched = true;
             for (const cb of self.afterRender) {
                 cb(self);
             }
+            let after = performance.now();
+            self.pipe.sendApplied(patch.frame, {
+                "decode_us": Math.round((prepared - received) * 1000),
+                "apply_us": Math.round((after - before) * 1000),
+            });
         });
     }
 
     close() {
+        if (this.history != null) {
+            this.history.close();
+        }
         this.pipe.close();
     }
 
@@ -163,8 +236,10 @@ export class Patch {
         this.buffer = patch;
         this.patch = new DataView(patch);
         this.offset = 0;
+        this.strings = [];
         this.element = element;
         this.app = app;
+        this.frame = null;
         this.current_elem_rendered = false;
         this.elements_rendered = [];
         this.blobs_changed = [];
@@ -187,6 +262,11 @@ export class Patch {
             16: Patch.prototype.addJsEvent,
             17: Patch.prototype.replaceJsEvent,
             18: Patch.prototype.addChildren,
+            19: Patch.prototype.insertChild,
+            20: Patch.prototype.moveChild,
+            21: Patch.prototype.removeChild,
+            22: Patch.prototype.hydrate,
+            23: Patch.prototype.verifyChecksum,
         }
     }
 
@@ -196,7 +276,27 @@ export class Patch {
         return ret;
     }
 
+    // Reads an unsigned LEB128 varint
+    popVarint() {
+        let ret = 0;
+        let scale = 1;
+        while (true) {
+            let x = this.patch.getUint8(this.offset);
+            this.offset += 1;
+            ret += (x & 0x7f) * scale;
+            if (x < 0x80) {
+                return ret;
+            }
+            scale *= 128;
+        }
+    }
+
     apply() {
+        let version = this.popU8();
+        if (version !== PATCH_FORMAT_VERSION) {
+            throw new Error("Unsupported patch format version: " + version);
+        }
+        this.frame = this.popVarint();
         while (this.offset < this.patch.byteLength) {
             let x = this.popU8();
             let fun = this.patch_funs[x];
@@ -241,12 +341,7 @@ export class Patch {
     }
 
     deserializeNode() {
-        let x = this.popU8();
-        if (x === 0) {
-            return this.deserializeElement();
-        } else if (x === 1) {
-            return this.deserializeText();
-        }
+        return this.createNode(this.readNode());
     }
 
     appendSibling() {
@@ -295,28 +390,27 @@ export class Patch {
 
     nextNode() {
         this.addToRendered();
-        let len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
+        let len = this.popVarint();
         for (let k = 0; k < len; ++k) {
             this.element = this.element.nextSibling;
         }
     }
 
     removeAttribute() {
-        let attr = this.deserializeString();
+        let attr = this.deserializeInterned();
         this.element.removeAttribute(attr);
         this.current_elem_rendered = true;
     }
 
     addAttribute() {
-        let key = this.deserializeString();
+        let key = this.deserializeInterned();
         let value = this.deserializeString();
         this.updateAttribute(this.element, key, value);
         this.current_elem_rendered = true;
     }
 
     removeJsEvent() {
-        let attr = this.deserializeString();
+        let attr = this.deserializeInterned();
         let attr_key = '__' + attr;
         let attr_value = this.element[attr_key];
         this.element.removeEventListener(attr, attr_value);
@@ -325,7 +419,7 @@ export class Patch {
     }
 
     addJsEvent() {
-        let key = this.deserializeString();
+        let key = this.deserializeInterned();
         let fun = this.deserializeEventFunction();
         if (key == "render") {
             this.element["__has_render_event"] = true;
@@ -336,7 +430,7 @@ export class Patch {
     }
 
     replaceJsEvent() {
-        let key = this.deserializeString();
+        let key = this.deserializeInterned();
         let fun = this.deserializeEventFunction();
         let key_attr = '__' + key;
         let attr_value = this.element[key_attr];
@@ -347,81 +441,200 @@ export class Patch {
     }
 
     addChildren() {
-        let len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
+        let len = this.popVarint();
         for (var k = 0; k < len; ++k) {
             let elem = this.deserializeNode();
             this.element.appendChild(elem);
         }
     }
 
-    updateAttribute(elem, key, value) {
-        if (key == "checked" && elem instanceof HTMLInputElement) {
-            elem.checked = (value == 'true');
-        } else {
-            elem.setAttribute(key, value);
+    insertChild() {
+        let idx = this.popVarint();
+        let new_elem = this.deserializeNode();
+        let before = this.element.childNodes[idx];
+        this.element.insertBefore(new_elem, before ? before : null);
+    }
+
+    moveChild() {
+        let from = this.popVarint();
+        let to = this.popVarint();
+        let child = this.element.childNodes[from];
+        this.element.removeChild(child);
+        let before = this.element.childNodes[to];
+        this.element.insertBefore(child, before ? before : null);
+    }
+
+    removeChild() {
+        let idx = this.popVarint();
+        this.element.removeChild(this.element.childNodes[idx]);
+    }
+
+    // Compares the structure of the DOM with the checksum computed by the backend,
+    // see `Node::checksum()` in src/patch/model.rs.
+    verifyChecksum() {
+        let expected = this.popVarint();
+        let actual = checksumNode(this.app.root_element.firstElementChild, 0x811c9dc5);
+        if (actual !== expected) {
+            throw new Error("Checksum mismatch: expected " + expected.toString(16)
+                + ", found " + actual.toString(16));
         }
     }
 
-    deserializeElement() {
-        let tag = this.deserializeString();
-        
-        let hasNamespace = this.popU8() > 0;
-        if (hasNamespace) {
-            var elem = document.createElementNS(this.deserializeString(), tag);
+    // Attaches to server-rendered nodes instead of replacing them.
+    // Only the first patch of an application hydrates, afterwards this is the same as `replace()`.
+    hydrate() {
+        let desc = this.readNode();
+        if (this.app.patched) {
+            let new_elem = this.createNode(desc);
+            this.element.parentNode.replaceChild(new_elem, this.element);
+            this.element = new_elem;
         } else {
-            var elem = document.createElement(tag);
+            this.element = this.hydrateNode(desc, this.element);
         }
+    }
 
-        let id = this.deserializeId();
-        if (id !== null) {
-            elem.setAttribute("__id__", id);
+    // Matches a node description against an existing node, assigns ids and installs listeners.
+    // Nodes which do not match are replaced. Returns the resulting node.
+    hydrateNode(desc, node) {
+        if (desc.text !== undefined) {
+            if (node.nodeType !== Node.TEXT_NODE) {
+                return this.replaceNode(desc, node);
+            }
+            if (node.nodeValue !== desc.text) {
+                node.nodeValue = desc.text;
+            }
+            return node;
         }
+        if (!this.matchesElement(desc, node)) {
+            return this.replaceNode(desc, node);
+        }
+        // comments separate adjacent text nodes, but must not be visited by later patches
+        for (const child of Array.from(node.childNodes)) {
+            if (child.nodeType === Node.COMMENT_NODE) {
+                node.removeChild(child);
+            }
+        }
+        if (node.childNodes.length !== desc.children.length) {
+            return this.replaceNode(desc, node);
+        }
+        for (const attr of Array.from(node.attributes)) {
+            if (!desc.attrs.some((x) => x.key === attr.name)) {
+                node.removeAttribute(attr.name);
+            }
+        }
+        this.initElement(node, desc);
+        let children = Array.from(node.childNodes);
+        for (var k = 0; k < children.length; ++k) {
+            this.hydrateNode(desc.children[k], children[k]);
+        }
+        this.pushRendered(node);
+        return node;
+    }
 
-        // attributes
-        let attr_len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
-        for (var k = 0; k < attr_len; ++k) {
-            let key = this.deserializeString();
-            let value = this.deserializeString();
-            this.updateAttribute(elem, key, value);
+    matchesElement(desc, node) {
+        if (node.nodeType !== Node.ELEMENT_NODE) {
+            return false;
+        }
+        if (desc.namespace === null) {
+            return node.namespaceURI === "http://www.w3.org/1999/xhtml"
+                && node.localName === desc.tag.toLowerCase();
         }
+        return node.namespaceURI === desc.namespace && node.localName === desc.tag;
+    }
 
-        // event listeners
-        let events_len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
+    replaceNode(desc, node) {
+        let new_elem = this.createNode(desc);
+        node.parentNode.replaceChild(new_elem, node);
+        return new_elem;
+    }
+
+    // Reads a node without creating it, such that it can be matched against existing nodes.
+    readNode() {
+        let x = this.popU8();
+        if (x === 1) {
+            return {"text": this.deserializeString()};
+        }
+        let desc = {"tag": this.deserializeInterned()};
+        let hasNamespace = this.popU8() > 0;
+        desc.namespace = hasNamespace ? this.deserializeInterned() : null;
+        desc.id = this.deserializeId();
+        desc.attrs = [];
+        let attr_len = this.popVarint();
+        for (var k = 0; k < attr_len; ++k) {
+            let key = this.deserializeInterned();
+            desc.attrs.push({"key": key, "value": this.deserializeString()});
+        }
+        desc.events = [];
+        let events_len = this.popVarint();
         for (var k = 0; k < events_len; ++k) {
-            let evt = this.deserializeEventHandler();
-            addEvent(this.app, id, elem, evt);
+            desc.events.push(this.deserializeEventHandler());
         }
-
-        // js events
-        let js_events_len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
-        let push_to_rendered = false;
+        desc.js_events = [];
+        let js_events_len = this.popVarint();
         for (var k = 0; k < js_events_len; ++k) {
-            let key = this.deserializeString();
-            if (key == "render") {
-                elem["__has_render_event"] = true;
-                push_to_rendered = true;
-            }
-            let fun = this.deserializeEventFunction();
-            elem['__' + key] = fun;
-            elem.addEventListener(key, fun);
+            let key = this.deserializeInterned();
+            desc.js_events.push({"key": key, "fun": this.deserializeEventFunction()});
         }
-
-        // children
-        let children_len = this.patch.getUint32(this.offset, true);
-        this.offset += 4;
+        desc.children = [];
+        let children_len = this.popVarint();
         for (var k = 0; k < children_len; ++k) {
-            elem.appendChild(this.deserializeNode());
+            desc.children.push(this.readNode());
         }
+        return desc;
+    }
 
-        if (push_to_rendered) {
+    // Creates a node from a description returned by `readNode()`
+    createNode(desc) {
+        if (desc.text !== undefined) {
+            return document.createTextNode(desc.text);
+        }
+        let elem;
+        if (desc.namespace !== null) {
+            elem = document.createElementNS(desc.namespace, desc.tag);
+        } else {
+            elem = document.createElement(desc.tag);
+        }
+        this.initElement(elem, desc);
+        for (const child of desc.children) {
+            elem.appendChild(this.createNode(child));
+        }
+        this.pushRendered(elem);
+        return elem;
+    }
+
+    // Assigns the id, attributes and listeners of a node description to an element
+    initElement(elem, desc) {
+        if (desc.id !== null) {
+            elem.setAttribute("__id__", desc.id);
+        }
+        for (const attr of desc.attrs) {
+            this.updateAttribute(elem, attr.key, attr.value);
+        }
+        for (const evt of desc.events) {
+            addEvent(this.app, desc.id, elem, evt);
+        }
+        for (const evt of desc.js_events) {
+            if (evt.key == "render") {
+                elem["__has_render_event"] = true;
+            }
+            elem['__' + evt.key] = evt.fun;
+            elem.addEventListener(evt.key, evt.fun);
+        }
+    }
+
+    // Schedules the `render` event of an element, once its children have been created
+    pushRendered(elem) {
+        if (elem["__has_render_event"]) {
             this.elements_rendered.push(elem);
-        }        
+        }
+    }
 
-        return elem;
+    updateAttribute(elem, key, value) {
+        if (key == "checked" && elem instanceof HTMLInputElement) {
+            elem.checked = (value == 'true');
+        } else {
+            elem.setAttribute(key, value);
+        }
     }
 
     deserializeText() {
@@ -456,27 +669,39 @@ export class Patch {
     }
 
     deserializeString() {
-        let len = this.patch.getUint32(this.offset, true);
-        let view = new Uint8Array(this.buffer, this.offset + 4, len);
-        this.offset += len + 4;
+        let len = this.popVarint();
+        let view = new Uint8Array(this.buffer, this.offset, len);
+        this.offset += len;
         return decoder.decode(view);
     }
 
+    // Reads a string from the string table of this patch.
+    // A reference of 0 denotes a new string, which is added to the table.
+    deserializeInterned() {
+        let idx = this.popVarint();
+        if (idx === 0) {
+            let ret = this.deserializeString();
+            this.strings.push(ret);
+            return ret;
+        }
+        return this.strings[idx - 1];
+    }
+
     deserializeEventHandler() {
         let no_prop = this.patch.getUint8(this.offset) > 0;
         let prevent_default = this.patch.getUint8(this.offset + 1) > 0;
         this.offset += 2;
-        let name = this.deserializeString();
+        let name = this.deserializeInterned();
         return new EventHandler(name, no_prop, prevent_default);
     }
 
     addBlob() {
         let id = this.deserializeId();
         let hash = this.deserializeU64();
-        let mime_type = this.deserializeString();
-        let len = this.patch.getUint32(this.offset, true);
-        let view = new Uint8Array(this.buffer, this.offset + 4, len);
-        this.offset += len + 4;
+        let mime_type = this.deserializeInterned();
+        let len = this.popVarint();
+        let view = new Uint8Array(this.buffer, this.offset, len);
+        this.offset += len;
         let blob = {'blob': new Blob([view], {"type": mime_type}), 'hash': hash, 'changed': null, 'added': null};
 
         let changed = this.app.blobs.hasOwnProperty(id);
@@ -0,0 +1,84 @@
+"use strict";
+
+// Overlay panel listing the message history of the backend.
+// Only shown if the runtime keeps a history, see `Runtime::history()`.
+// Toggled by pressing Ctrl+Shift+H. Clicking an entry with a snapshot
+// restores the application state after processing its message.
+export default class HistoryPanel {
+    constructor(pipe) {
+        this.pipe = pipe;
+        this.entries = [];
+        this.current = null;
+
+        this.element = document.createElement("div");
+        this.element.style.cssText = "position: fixed; top: 0; right: 0; bottom: 0; width: 360px;"
+            + "overflow-y: auto; z-index: 2147483647; background: rgba(30, 30, 30, 0.92);"
+            + "color: #ddd; font: 12px monospace; display: none;";
+        document.body.appendChild(this.element);
+
+        let self = this;
+        this.onKeyDown = (e) => {
+            if (e.ctrlKey && e.shiftKey && e.code === "KeyH") {
+                e.preventDefault();
+                self.toggle();
+            }
+        };
+        window.addEventListener("keydown", this.onKeyDown);
+    }
+
+    // Applies a `HistoryUpdate` as sent by the backend
+    update(update) {
+        this.entries = this.entries.filter((x) => x.id >= update.first);
+        for (const entry of update.entries) {
+            this.entries.push(entry);
+        }
+        this.current = update.current;
+        if (this.isVisible()) {
+            this.render();
+        }
+    }
+
+    isVisible() {
+        return this.element.style.display !== "none";
+    }
+
+    toggle() {
+        this.element.style.display = this.isVisible() ? "none" : "block";
+        if (this.isVisible()) {
+            this.render();
+        }
+    }
+
+    render() {
+        this.element.innerHTML = "";
+        let title = document.createElement("div");
+        title.style.cssText = "padding: 6px; font-weight: bold; border-bottom: 1px solid #555;";
+        title.textContent = "Message history (" + this.entries.length + ")";
+        this.element.appendChild(title);
+        let self = this;
+        for (const entry of this.entries) {
+            let row = document.createElement("div");
+            row.style.cssText = "padding: 4px 6px; border-bottom: 1px solid #333; white-space: pre-wrap;"
+                + "word-break: break-all;";
+            if (entry.id === this.current) {
+                row.style.background = "#264f78";
+            }
+            let updated = entry.render ? "render" : "no render";
+            if (entry.invalidated > 0) {
+                updated += ", " + entry.invalidated + " component(s)";
+            }
+            row.textContent = "#" + entry.id + " +" + entry.time_ms + "ms [" + updated + "]\n" + entry.message;
+            if (entry.snapshot) {
+                row.style.cursor = "pointer";
+                row.title = "Restore the state after this message";
+                row.onclick = () => { self.pipe.sendTimeTravel(entry.id); };
+            }
+            this.element.appendChild(row);
+        }
+    }
+
+    close() {
+        window.removeEventListener("keydown", this.onKeyDown);
+        this.element.remove();
+    }
+}
@@ -0,0 +1,68 @@
+"use strict";
+
+// Version of the protocol spoken between frontend and backend.
+// Must match `PROTOCOL_VERSION` in src/pipe.rs
+export const PROTOCOL_VERSION = 7;
+
+// Optional protocol capabilities supported by this frontend
+export const CAPABILITIES = [];
+
+// Capability to compress messages using raw deflate.
+// Must match `CAPABILITY_DEFLATE` in src/pipe.rs
+export const CAPABILITY_DEFLATE = "deflate";
+
+// Once deflate has been negotiated, binary messages start with one of these header bytes
+export const FRAME_RAW_PATCH = 0;
+export const FRAME_DEFLATE_PATCH = 1;
+export const FRAME_DEFLATE_JSON = 2;
+
+// Close code used by the backend if the handshake has failed
+export const CLOSE_PROTOCOL_ERROR = 1002;
+
+// Serializes the handshake of the frontend.
+// Pipes may pass additional capabilities they support.
+export function serializeHandshake(capabilities = []) {
+    return JSON.stringify({
+        "version": PROTOCOL_VERSION,
+        "capabilities": CAPABILITIES.concat(capabilities)
+    });
+}
+
+// Returns true if the backend agreed on using the given capability
+export function supports(handshake, capability) {
+    return handshake.capabilities.indexOf(capability) >= 0;
+}
+
+// Returns true if the browser is able to decompress raw deflate data.
+// Some browsers only support the "gzip" and "deflate" formats of `DecompressionStream`.
+export function supportsInflate() {
+    if (typeof DecompressionStream === "undefined") {
+        return false;
+    }
+    try {
+        new DecompressionStream("deflate-raw");
+        return true;
+    } catch (e) {
+        return false;
+    }
+}
+
+// Decompresses raw deflate data and resolves to an ArrayBuffer
+export function inflate(data) {
+    let stream = new Blob([data]).stream().pipeThrough(new DecompressionStream("deflate-raw"));
+    return new Response(stream).arrayBuffer();
+}
+
+// Parses the handshake reply of the backend.
+// Throws an error in case frontend and backend do not agree on the protocol.
+export function parseHandshake(data) {
+    let handshake = JSON.parse(data);
+    if (handshake.hasOwnProperty("error")) {
+        throw new Error("Greenhorn handshake failed: " + handshake.error);
+    }
+    if (handshake.version !== PROTOCOL_VERSION) {
+        throw new Error("Greenhorn protocol version mismatch: frontend speaks version "
+            + PROTOCOL_VERSION + ", backend speaks version " + handshake.version);
+    }
+    return handshake;
+}
@@ -1,6 +1,12 @@
 "use strict";
 
 export function setupWorker(import_path) {
+    // hold back messages, e.g. the handshake, until the wasm module has been imported
+    self.greenhorn_pending = [];
+    self.onmessage = (event) => {
+        self.greenhorn_pending.push(event.data);
+    }
+
     self.greenhorn_push_string = (arg) => {
         self.postMessage(arg, null);
     }
@@ -21,5 +27,9 @@ export function onImportWorker(wasm_module) {
         let msg = event.data;
         wasm_module.greenhorn_send_to_wasm(msg);
     }
+    for (let msg of self.greenhorn_pending) {
+        wasm_module.greenhorn_send_to_wasm(msg);
+    }
+    self.greenhorn_pending = [];
 }
 
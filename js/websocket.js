@@ -1,32 +1,47 @@
 "use strict";
 
 import serializeEvent from './event.js'
+import {serializeHandshake, parseHandshake, supports, supportsInflate, inflate, CLOSE_PROTOCOL_ERROR,
+    CAPABILITY_DEFLATE, FRAME_RAW_PATCH, FRAME_DEFLATE_PATCH, FRAME_DEFLATE_JSON} from './protocol.js'
 
 
 export default class Pipe {
     constructor(url) {
         this.url = url;
         this.setupSocket();
-        this.onPatch = (patch_data) => {};
+        this.onPatch = (patch_data, received) => {};
         this.onServiceMsg = (id, service_msg) => {};
         this.onRunJsMsg = (id, run_js_msg) => {};
         this.onLoadCss = (css) => {};
         this.onInjectEvent = (event, prop, default_action) => {};
         this.onDialog = (dialog) => {}
+        this.onHistory = (update) => {};
     }
 
     setupSocket() {
         let self = this;
         this.connected = false;
+        this.handshake = null;
+        this.deflate = false;
+        // decompression is asynchronous, thus messages are queued to retain their order
+        this.queue = Promise.resolve();
         this.socket = new WebSocket(this.url);
         this.socket.binaryType = "arraybuffer";
         this.socket.onopen = (e) => { 
-            self.connected = true;
+            let capabilities = supportsInflate() ? [CAPABILITY_DEFLATE] : [];
+            self.socket.send(serializeHandshake(capabilities));
         };
         this.socket.onerror = (e) => {
             self.retryConnect();
         }
         this.socket.onclose = (e) => { 
+            if (e.code === CLOSE_PROTOCOL_ERROR) {
+                // reconnecting does not help, the frontend must be reloaded
+                console.error("Greenhorn protocol error: " + e.reason);
+                self.socket = null;
+                self.connected = false;
+                return;
+            }
             self.retryConnect();
         };
         this.socket.onmessage = (e) => { self.onMessage(e); };
@@ -45,15 +60,75 @@ export default class Pipe {
         }, 30);
     }
 
-    sendApplied() {
+    // Acknowledges the patch of the given frame along with its decode and apply durations in microseconds
+    sendApplied(frame, timing) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let reply = JSON.stringify({"FrameApplied": [frame, timing]});
+        this.socket.send(reply);
+    }
+
+    // Reports that the patch of the given frame could not be applied
+    sendPatchFailed(frame, reason) {
         if (this.socket == null || !this.connected) {
             return;
         }
-        let reply = JSON.stringify({"FrameApplied": []}); 
+        let reply = JSON.stringify({"PatchFailed": [frame, reason]});
         this.socket.send(reply);
     }
 
     onMessage(event) {
+        // the first message is the reply to our handshake
+        if (this.handshake == null) {
+            try {
+                this.handshake = parseHandshake(event.data);
+                this.deflate = supports(this.handshake, CAPABILITY_DEFLATE);
+                this.connected = true;
+            } catch (e) {
+                console.error(e.message);
+                this.close();
+            }
+            return;
+        }
+
+        let received = performance.now();
+        if (this.deflate) {
+            let socket = this.socket;
+            this.queue = this.queue
+                .then(() => this.decompress(event.data))
+                .then((data) => {
+                    // drop messages of a closed connection
+                    if (this.socket === socket) {
+                        this.dispatch(data, received);
+                    }
+                })
+                .catch((e) => console.error(e));
+        } else {
+            this.dispatch(event.data, received);
+        }
+    }
+
+    // Strips the frame header of binary messages and decompresses them if required.
+    // Resolves to either a patch as ArrayBuffer or a JSON string.
+    decompress(data) {
+        if (!(data instanceof ArrayBuffer)) {
+            return Promise.resolve(data);
+        }
+        let header = new Uint8Array(data, 0, 1)[0];
+        let payload = data.slice(1);
+        if (header === FRAME_RAW_PATCH) {
+            return Promise.resolve(payload);
+        } else if (header === FRAME_DEFLATE_PATCH) {
+            return inflate(payload);
+        } else if (header === FRAME_DEFLATE_JSON) {
+            return inflate(payload).then((x) => new TextDecoder().decode(x));
+        }
+        return Promise.reject(new Error("Invalid frame header: " + header));
+    }
+
+    dispatch(data, received) {
+
         // conclusion on performance testing:
         // JSON.parse is much faster then msgpack.decode()
         // json serialization on server is approx 2x slower
@@ -62,19 +137,16 @@ export default class Pipe {
         // we are better off just using json
 
         // in case we get binary data it must be a Patch
-        if (event.data instanceof ArrayBuffer) {
-            let data = new Uint8Array(event.data);
-            this.onPatch(data.buffer);
-            this.sendApplied();
+        if (data instanceof ArrayBuffer) {
+            this.onPatch(data, received);
             return;
         }
 
         // in case we get text data in can be any type of message
-        let msg = JSON.parse(event.data);
+        let msg = JSON.parse(data);
         if (msg.hasOwnProperty("Patch")) {
             let data = new Uint8Array(msg.Patch);
-            this.onPatch(data.buffer);
-            this.sendApplied();
+            this.onPatch(data.buffer, received);
         } else if (msg.hasOwnProperty("Service")) {
             let service_msg = msg.Service;
             let id = service_msg[0];
@@ -88,7 +160,7 @@ export default class Pipe {
                 this.onLoadCss(service_msg[1].LoadCss);
             }
         } else if (msg.hasOwnProperty("LoadCss")) {
-            this.onloadCss(msg.LoadCss);
+            this.onLoadCss(msg.LoadCss);
         } else if (msg.hasOwnProperty("RunJs")) {
             (function() {
                 eval(msg.RunJs);
@@ -100,6 +172,8 @@ export default class Pipe {
             this.onInjectEvent(event, prop, default_action);
         } else if (msg.hasOwnProperty("Dialog")) {
             this.onDialog(msg.Dialog);
+        } else if (msg.hasOwnProperty("History")) {
+            this.onHistory(msg.History);
         }
     }
 
@@ -139,4 +213,14 @@ export default class Pipe {
         let serialized = JSON.stringify(msg);
         this.socket.send(serialized);
     }
+
+    sendTimeTravel(id) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let msg = {
+            "TimeTravel": id
+        };
+        this.socket.send(JSON.stringify(msg));
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 "use strict";
 
 import serializeEvent from './event.js'
+import {serializeHandshake, parseHandshake} from './protocol.js'
 
 
 export default class WorkerPipe {
@@ -9,18 +10,31 @@ export default class WorkerPipe {
         let self = this;
         this.worker.onmessage = (evt) => { self.onMessage(evt) };
 
-        this.onPatch = (patch_data) => {};
+        this.onPatch = (patch_data, received) => {};
         this.onServiceMsg = (id, service_msg) => {};
         this.onRunJsMsg = (id, run_js_msg) => {};
         this.onLoadCss = (css) => {};
         this.onInjectEvent = (event, prop, default_action) => {};
+        this.onHistory = (update) => {};
+
+        this.handshake = null;
+        this.worker.postMessage(serializeHandshake());
     }
 
     onMessage(event) {
         console.log(event);
+        // the first message is the reply to our handshake
+        if (this.handshake == null) {
+            try {
+                this.handshake = parseHandshake(event.data);
+            } catch (e) {
+                console.error(e.message);
+                this.worker.terminate();
+            }
+            return;
+        }
         if (event.data instanceof ArrayBuffer) {
-            this.onPatch(event.data);
-            this.sendApplied();
+            this.onPatch(event.data, performance.now());
             return;
         }
         let msg = JSON.parse(event.data);
@@ -49,11 +63,20 @@ export default class WorkerPipe {
             this.onInjectEvent(event, prop, default_action);
         } else if (msg.hasOwnProperty("Dialog")) {
             this.spawnDialog(msg.Dialog);
+        } else if (msg.hasOwnProperty("History")) {
+            this.onHistory(msg.History);
         }
     }
 
-    sendApplied() {
-        let reply = JSON.stringify({"FrameApplied": []}); 
+    // Acknowledges the patch of the given frame along with its decode and apply durations in microseconds
+    sendApplied(frame, timing) {
+        let reply = JSON.stringify({"FrameApplied": [frame, timing]});
+        this.worker.postMessage(reply);
+    }
+
+    // Reports that the patch of the given frame could not be applied
+    sendPatchFailed(frame, reason) {
+        let reply = JSON.stringify({"PatchFailed": [frame, reason]});
         this.worker.postMessage(reply);
     }
 
@@ -89,4 +112,11 @@ export default class WorkerPipe {
         let serialized = JSON.stringify(msg);
         this.worker.postMessage(serialized);
     }
+
+    sendTimeTravel(id) {
+        let msg = {
+            "TimeTravel": id
+        };
+        this.worker.postMessage(JSON.stringify(msg));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+// Bundles the frontend sources in js/ into web_view/res/bundle.js without any dependencies.
+//
+// The bundle exposes the exports of js/web_view.js as global `greenhorn` variable, just like
+// the webpack configuration does. It is embedded into the web_view crate and served by the
+// `HttpServer`, hence it must be rebuilt whenever the sources in js/ change:
+//
+//     node scripts/bundle.js
+//
+// Only the module syntax used in js/ is supported: default and named imports of relative
+// modules, `export default class/function` and `export class/function/const`.
+"use strict";
+
+const fs = require("fs");
+const path = require("path");
+
+const ROOT = path.join(__dirname, "..");
+const SRC = path.join(ROOT, "js");
+const ENTRY = "web_view.js";
+const OUT = path.join(ROOT, "web_view", "res", "bundle.js");
+
+const IMPORT = /^import\s+([\s\S]*?)\s+from\s+['"]\.\/(.+?)['"];?[ \t]*$/gm;
+const EXPORT_DEFAULT = /^export\s+default\s+(class|function)\s+([A-Za-z_$][\w$]*)/gm;
+const EXPORT_NAMED = /^export\s+(class|function|const|let)\s+([A-Za-z_$][\w$]*)/gm;
+
+// Translates the import clause `spec` of module `name` into a variable declaration
+function translateImport(spec, name) {
+    const module = "__require(" + JSON.stringify(name) + ")";
+    spec = spec.trim();
+    if (spec.startsWith("{")) {
+        const bindings = spec.slice(1, -1).split(",")
+            .map((x) => x.trim())
+            .filter((x) => x.length > 0)
+            .map((x) => x.replace(/\s+as\s+/, ": "));
+        return "const {" + bindings.join(", ") + "} = " + module + ";";
+    }
+    return "const " + spec + " = " + module + ".default;";
+}
+
+// Translates a module into a function populating its exports
+function translateModule(name, source, pending) {
+    const exports = [];
+    source = source.replace(/^"use strict";\s*/, "");
+    source = source.replace(IMPORT, (_, spec, dependency) => {
+        pending.push(dependency);
+        return translateImport(spec, dependency);
+    });
+    source = source.replace(EXPORT_DEFAULT, (_, kind, ident) => {
+        exports.push(["default", ident]);
+        return kind + " " + ident;
+    });
+    source = source.replace(EXPORT_NAMED, (_, kind, ident) => {
+        exports.push([ident, ident]);
+        return kind + " " + ident;
+    });
+    if (/^\s*(import|export)\s/m.test(source)) {
+        throw new Error("Unsupported module syntax in " + name);
+    }
+    const assignments = exports
+        .map(([exported, local]) => "    __exports." + exported + " = " + local + ";")
+        .join("\n");
+    return JSON.stringify(name) + ": function (__exports, __require) {\n"
+        + source.trim() + "\n\n" + assignments + "\n}";
+}
+
+function bundle() {
+    const modules = [];
+    const done = new Set();
+    const pending = [ENTRY];
+    while (pending.length > 0) {
+        const name = pending.shift();
+        if (done.has(name)) {
+            continue;
+        }
+        done.add(name);
+        const source = fs.readFileSync(path.join(SRC, name), "utf8");
+        modules.push(translateModule(name, source, pending));
+    }
+    const output = "// Generated from js/ by scripts/bundle.js, do not edit.\n"
+        + "var greenhorn = (function () {\n"
+        + "\"use strict\";\n"
+        + "var __modules = {\n" + modules.join(",\n") + "\n};\n"
+        + "var __cache = {};\n"
+        + "function __require(name) {\n"
+        + "    if (!__cache.hasOwnProperty(name)) {\n"
+        + "        __cache[name] = {};\n"
+        + "        __modules[name](__cache[name], __require);\n"
+        + "    }\n"
+        + "    return __cache[name];\n"
+        + "}\n"
+        + "return __require(" + JSON.stringify(ENTRY) + ");\n"
+        + "})();\n";
+    // the bundle is embedded into a <script> element by the web_view crate
+    if (/<\/script/i.test(output)) {
+        throw new Error("The bundle must not contain </script");
+    }
+    return output;
+}
+
+fs.writeFileSync(OUT, bundle());
@@ -187,6 +187,8 @@ impl<T: Send + 'static> Context<T> {
 
     /// Spawn a [`Service`](../service/trait.Service.html) using a mapping function to map
     /// the services data items to the current message type
+    ///
+    /// Once the application quits, the service is sent `RxServiceMessage::Stop`.
     pub fn run_service<S, F>(&self, service: S, fun: F)
     where
         S: 'static + Service,
@@ -197,17 +199,23 @@ impl<T: Send + 'static> Context<T> {
     }
 
     /// Spawns a future. The result of the future will be used to `update()` the application.
+    ///
+    /// The future is dropped if it is still pending once the application quits.
     pub fn spawn<Fut: 'static + Send + Future<Output = T>>(&self, fut: Fut) {
         self.tx.send(ContextMsg::Future(Box::pin(fut), false));
     }
 
     /// Spawns a future which contains blocking operations. This future might be spawned on
     /// a different thread-pool to avoid stalling non-blocking futures.
+    ///
+    /// Once the application quits, the future is dropped at its next suspension point.
     pub fn spawn_blocking<Fut: 'static + Send + Future<Output = T>>(&self, fut: Fut) {
         self.tx.send(ContextMsg::Future(Box::pin(fut), true));
     }
 
     /// Subscribe to a stream. Each item the stream issues will be used to `udpate()` the application.
+    ///
+    /// The stream is dropped once the application quits.
     pub fn subscribe<S: 'static + Send + Stream<Item = T>>(&self, stream: S) {
         self.tx.send(ContextMsg::Stream(Box::pin(stream)));
     }
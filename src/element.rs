@@ -19,6 +19,7 @@ pub(crate) struct Element<T: 'static + Send> {
     pub(crate) children: Option<Vec<Node<T>>>, // child nodes
     pub(crate) namespace: Option<String>, // an optional namespace. If None the HTML namespace is assumed
     pub(crate) rpc: Option<Rpc<T>>,       // An RPC message handler for this node
+    pub(crate) key: Option<String>,       // identifies the element among its siblings when diffing
 }
 
 impl<T: 'static + Send> Element<T> {
@@ -47,6 +48,7 @@ impl<T: 'static + Send> Element<T> {
             children,
             namespace: self.namespace.clone(),
             rpc: self.rpc.clone(),
+            key: self.key.clone(),
         })
     }
 }
@@ -104,6 +106,7 @@ pub(crate) trait ElementMap<T: 'static + Send>: Debug {
     fn take_namespace(&mut self) -> Option<String>;
     fn take_js_events(&mut self) -> Vec<Attr>;
     fn take_rpc(&mut self) -> Option<Rpc<T>>;
+    fn take_key(&mut self) -> Option<String>;
 }
 
 impl<T: 'static + Send> ElementMap<T> for MappedElement<T> {
@@ -131,6 +134,9 @@ impl<T: 'static + Send> ElementMap<T> for MappedElement<T> {
     fn take_rpc(&mut self) -> Option<Rpc<T>> {
         self.inner.take_rpc()
     }
+    fn take_key(&mut self) -> Option<String> {
+        self.inner.take_key()
+    }
 }
 
 impl<T: 'static + Send> ElementMap<T> for Element<T> {
@@ -158,6 +164,9 @@ impl<T: 'static + Send> ElementMap<T> for Element<T> {
     fn take_rpc(&mut self) -> Option<Rpc<T>> {
         self.rpc.take()
     }
+    fn take_key(&mut self) -> Option<String> {
+        self.key.take()
+    }
 }
 
 pub(crate) struct ElementMapDirect<T: 'static + Send, U: 'static + Send> {
@@ -228,6 +237,10 @@ impl<T: 'static + Send, U: 'static + Send> ElementMap<U> for ElementMapDirect<T,
     fn take_rpc(&mut self) -> Option<Rpc<U>> {
         self.inner.rpc.take().map(|x| x.map(self.fun.clone()))
     }
+
+    fn take_key(&mut self) -> Option<String> {
+        self.inner.key.take()
+    }
 }
 
 pub(crate) struct ElementRemap<T, U> {
@@ -286,4 +299,8 @@ impl<T: 'static + Send, U: 'static + Send> ElementMap<U> for ElementRemap<T, U>
     fn take_rpc(&mut self) -> Option<Rpc<U>> {
         self.inner.take_rpc().map(|x| x.map(self.fun.clone()))
     }
+
+    fn take_key(&mut self) -> Option<String> {
+        self.inner.take_key()
+    }
 }
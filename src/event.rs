@@ -108,6 +108,7 @@ pub struct Emission {
 pub(crate) trait SubscriptionMap<T>: Send {
     fn call(&self, value: Box<dyn Any>) -> T;
     fn id(&self) -> Id;
+    fn value_type(&self) -> &'static str;
 }
 
 struct MappedSubscription<U, T> {
@@ -125,6 +126,10 @@ impl<U: 'static, T: 'static> SubscriptionMap<T> for MappedSubscription<U, T> {
     fn id(&self) -> Id {
         self.child.id()
     }
+
+    fn value_type(&self) -> &'static str {
+        self.child.value_type()
+    }
 }
 
 struct SubscriptionHandler<T, V, F: Send + Fn(V) -> T> {
@@ -145,6 +150,10 @@ impl<T: 'static, V: 'static, F: Send + Fn(V) -> T> SubscriptionMap<T>
     fn id(&self) -> Id {
         self.id
     }
+
+    fn value_type(&self) -> &'static str {
+        std::any::type_name::<V>()
+    }
 }
 
 /// Represents the result of an `Event.subscribe()` call.
@@ -174,6 +183,11 @@ impl<T: 'static> Subscription<T> {
     pub(crate) fn id(&self) -> Id {
         self.0.lock().unwrap().id()
     }
+
+    /// Returns the name of the type of the values emitted by the subscribed event.
+    pub(crate) fn value_type(&self) -> &'static str {
+        self.0.lock().unwrap().value_type()
+    }
 }
 
 impl<T: 'static> Debug for Subscription<T> {
@@ -0,0 +1,648 @@
+//! This module implements a minimal HTTP server, which serves the frontend of an application
+//! to a browser.
+//!
+//! The [HttpServer](struct.HttpServer.html) serves a generated index page, the JavaScript bundle
+//! of the greenhorn frontend as well as static asset directories. Requests to `/ws` are upgraded
+//! to a WebSocket connection and served by a [WebSocketPipe](../websockets/struct.WebSocketPipe.html),
+//! such that both the frontend and the application share the same port.
+//!
+
+use crate::websockets::{accept_tls, Access, Connection, TlsConfig, WebSocketPipe};
+use async_std::fs;
+use async_std::io;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::prelude::*;
+use instant::Instant;
+use log::error;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The JavaScript bundle of the greenhorn frontend, as served on `/bundle.js`.
+pub const BUNDLE_JS: &str = include_str!("../web_view/res/bundle.js");
+
+/// Path on which connections are upgraded to WebSockets
+pub const WS_PATH: &str = "/ws";
+
+/// Characters which are percent-encoded in the value of a query parameter
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Maximum size of the head of an HTTP request
+const MAX_REQUEST_HEAD_LEN: usize = 8192;
+
+/// Maximum time until the head of an HTTP request must have been received
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 5000;
+
+/// The `HttpServer` type serves the frontend of an application to a browser.
+///
+/// The following paths are served:
+///  * `/` and `/index.html`: A generated index page, which connects to the application.
+///    Additional CSS and JS may be injected using `css()` and `js()`.
+///  * `/bundle.js`: The JavaScript bundle of the greenhorn frontend.
+///  * `/ws`: Upgraded to the WebSocket connection to the application.
+///  * Static directories registered with `static_dir()`.
+///
+/// Access to the application may be restricted using `access()`. Note that a token required
+/// by [Access](../websockets/struct.Access.html) is embedded in the index page. Thus, it should
+/// be combined with an `Origin` allowlist.
+///
+/// Once configured, the server is started using `start()`, which returns the
+/// [WebSocketPipe](../websockets/struct.WebSocketPipe.html) to run the application on.
+/// Only a single frontend is connected at a time. Once it disconnects, e.g. because the page
+/// was reloaded, the next frontend connecting to `/ws` is served and re-synchronized.
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::prelude::*;
+/// # use greenhorn::http::HttpServer;
+/// # use std::net::SocketAddr;
+/// # use std::str::FromStr;
+/// #
+/// # struct MyApp;
+/// # impl Render for MyApp {
+/// #     type Message = ();
+/// #     fn render(&self) -> Node<Self::Message> {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// # impl App for MyApp {
+/// #     fn update(&mut self, msg: Self::Message, ctx: Context<Self::Message>) -> Updated {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// #
+/// let addr = SocketAddr::from_str("127.0.0.1:8080").unwrap();
+/// let pipe = HttpServer::listen_to_addr(addr)
+///     .title("My App")
+///     .css("body { margin: 0; }")
+///     .static_dir("/assets", "./assets")
+///     .start();
+/// let (runtime, _control) = Runtime::new(MyApp, pipe);
+/// runtime.run_blocking().unwrap();
+/// ```
+pub struct HttpServer {
+    listener: TcpListener,
+    title: String,
+    css: Vec<String>,
+    js: Vec<String>,
+    prerendered: String,
+    static_dirs: Vec<(String, PathBuf)>,
+    access: Access,
+    tls: Option<TlsConfig>,
+    request_timeout: Duration,
+}
+
+impl HttpServer {
+    /// Starts listening to a given `SocketAddr`
+    pub fn listen_to_addr(addr: SocketAddr) -> Self {
+        let try_socket = task::block_on(async { TcpListener::bind(&addr).await });
+        let listener = try_socket.expect("Failed to bind");
+        Self::listen_to_socket(listener)
+    }
+
+    /// Start listening to `TcpListener`.
+    pub fn listen_to_socket(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            title: "".to_string(),
+            css: vec![],
+            js: vec![],
+            prerendered: "".to_string(),
+            static_dirs: vec![],
+            access: Access::new(),
+            tls: None,
+            request_timeout: Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS),
+        }
+    }
+
+    /// Sets the title of the index page.
+    pub fn title<T: Into<String>>(mut self, title: T) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Injects a stylesheet into the index page.
+    pub fn css<T: Into<String>>(mut self, css: T) -> Self {
+        self.css.push(css.into());
+        self
+    }
+
+    /// Injects a script into the index page.
+    pub fn js<T: Into<String>>(mut self, js: T) -> Self {
+        self.js.push(js.into());
+        self
+    }
+
+    /// Embeds prerendered HTML into the body of the index page, which is shown until the first
+    /// frame of the application has been rendered.
+    ///
+    /// The HTML is typically generated using [render_to_string()](../ssr/fn.render_to_string.html)
+    /// and should consist of a single root element, which is replaced by the application.
+    /// Use [RuntimeBuilder::hydrate()](../runtime/struct.RuntimeBuilder.html#method.hydrate) to
+    /// attach to the prerendered HTML instead.
+    pub fn prerender<T: Into<String>>(mut self, html: T) -> Self {
+        self.prerendered = html.into();
+        self
+    }
+
+    /// Serves the files in directory `dir` under the path `prefix`.
+    ///
+    /// For example, `static_dir("/assets", "./static")` serves `./static/logo.svg` as `/assets/logo.svg`.
+    pub fn static_dir<T: Into<String>, P: Into<PathBuf>>(mut self, prefix: T, dir: P) -> Self {
+        let prefix = prefix.into();
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        self.static_dirs.push((prefix, dir.into()));
+        self
+    }
+
+    /// Only connects frontends which are granted `access`.
+    pub fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Only accepts TLS encrypted connections, i.e. serves the frontend using `https://`.
+    ///
+    /// The index page then connects to the application using a `wss://` URL.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sets the maximum time until the head of an HTTP request must have been received
+    /// after a connection was accepted. Defaults to 5s.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Returns the local address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Returns the port the server is listening on
+    pub fn port(&self) -> u16 {
+        self.local_addr().port()
+    }
+
+    /// Generates the HTML of the index page.
+    pub fn index_html(&self) -> String {
+        let ws_path = match self.access.token() {
+            Some(token) => format!(
+                "{}?token={}",
+                WS_PATH,
+                utf8_percent_encode(token, QUERY_VALUE)
+            ),
+            None => WS_PATH.to_string(),
+        };
+        // a JSON string is a valid JS string literal, but must not terminate the script element
+        let ws_path = serde_json::to_string(&ws_path)
+            .unwrap()
+            .replace("</", "<\\/");
+        let js_main = format!(
+            "window.onload = function() {{
+            let protocol = window.location.protocol === \"https:\" ? \"wss://\" : \"ws://\";
+            let pipe = new greenhorn.Pipe(protocol + window.location.host + {});
+            let app = new greenhorn.App(pipe, document.body);
+            window.app = app;
+        }}",
+            ws_path
+        );
+        let mut additional = Vec::new();
+        for x in &self.js {
+            additional.push(format!("<script>{}</script>", x));
+        }
+        for x in &self.css {
+            additional.push(format!("<style>{}</style>", x));
+        }
+        let additional = additional.join("\n");
+        format!(
+            "<!DOCTYPE html>
+        <html>
+            <head> <meta charset=\"UTF-8\">
+                <title>{}</title>
+                <script src=\"/bundle.js\"></script>
+                <script>{}</script>
+                {}
+            </head>
+            <body>{}</body>
+        </html>",
+            escape_html(&self.title),
+            js_main,
+            additional,
+            self.prerendered
+        )
+    }
+
+    /// Starts serving HTTP requests in the background and returns the pipe
+    /// connected to the frontend.
+    pub fn start(self) -> WebSocketPipe {
+        let local_addr = self.local_addr();
+        let (connections_tx, connections_rx) = unbounded();
+        let site = Arc::new(Site {
+            index: self.index_html(),
+            static_dirs: self.static_dirs,
+            request_timeout: self.request_timeout,
+        });
+        let listener = self.listener;
+        let tls = self.tls;
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(stream) = incoming.next().await {
+                match stream {
+                    Ok(stream) => {
+                        task::spawn(handle_connection(
+                            stream,
+                            tls.clone(),
+                            site.clone(),
+                            connections_tx.clone(),
+                        ));
+                    }
+                    Err(err) => error!("Could not accept connection: {}", err),
+                }
+            }
+        });
+        WebSocketPipe::from_connections(connections_rx, local_addr, self.access)
+    }
+}
+
+/// The content served by an `HttpServer`
+struct Site {
+    index: String,
+    static_dirs: Vec<(String, PathBuf)>,
+    request_timeout: Duration,
+}
+
+/// Head of a parsed HTTP request
+struct Request {
+    method: String,
+    path: String,
+}
+
+/// Outcome of reading the head of an HTTP request
+enum RequestHead {
+    /// The parsed request along with all data read from the stream
+    Complete(Request, Vec<u8>),
+    /// The request is malformed or too large
+    Invalid,
+    /// The request was not received in time
+    TimedOut,
+}
+
+/// An HTTP response, which is sent before closing the connection
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Cow<'static, [u8]>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Cow<'static, [u8]>) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: Cow::Owned(status.as_bytes().to_vec()),
+        }
+    }
+}
+
+impl Site {
+    /// Looks up the content for a given request
+    async fn respond(&self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::error("405 Method Not Allowed");
+        }
+        let path = request.path.split('?').next().unwrap();
+        match path {
+            "/" | "/index.html" => Response::ok(
+                "text/html; charset=utf-8",
+                Cow::Owned(self.index.as_bytes().to_vec()),
+            ),
+            "/bundle.js" => Response::ok(
+                "application/javascript; charset=utf-8",
+                Cow::Borrowed(BUNDLE_JS.as_bytes()),
+            ),
+            _ => self.respond_static(path).await,
+        }
+    }
+
+    /// Serves a file from one of the static directories
+    async fn respond_static(&self, path: &str) -> Response {
+        for (prefix, dir) in &self.static_dirs {
+            let relative = match path.strip_prefix(prefix.as_str()) {
+                Some(relative) if relative.starts_with('/') || prefix == "/" => relative,
+                _ => continue,
+            };
+            let relative = match percent_decode_str(relative).decode_utf8() {
+                Ok(relative) => relative,
+                Err(_) => return Response::error("404 Not Found"),
+            };
+            let relative = Path::new(relative.trim_start_matches('/'));
+            // do not allow escaping from the static directory
+            if !relative
+                .components()
+                .all(|x| matches!(x, Component::Normal(_)))
+            {
+                return Response::error("404 Not Found");
+            }
+            let file = dir.join(relative);
+            if let Ok(data) = fs::read(&file).await {
+                return Response::ok(content_type(&file), Cow::Owned(data));
+            }
+        }
+        Response::error("404 Not Found")
+    }
+}
+
+/// Handles a single connection. Either upgrades it to a WebSocket connection
+/// or responds to the HTTP request and closes the connection.
+async fn handle_connection(
+    stream: TcpStream,
+    tls: Option<TlsConfig>,
+    site: Arc<Site>,
+    connections: UnboundedSender<Connection>,
+) {
+    let mut stream = match accept_tls(stream, tls.as_ref()).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            error!("Error during TLS handshake: {}", err);
+            return;
+        }
+    };
+    let (request, head) = match read_request(&mut stream, site.request_timeout).await {
+        Ok(RequestHead::Complete(request, head)) => (request, head),
+        Ok(RequestHead::Invalid) => {
+            let _ = write_response(&mut stream, Response::error("400 Bad Request"), false).await;
+            return;
+        }
+        Ok(RequestHead::TimedOut) => {
+            let response = Response::error("408 Request Timeout");
+            let _ = write_response(&mut stream, response, false).await;
+            return;
+        }
+        Err(err) => {
+            error!("Could not read request: {}", err);
+            return;
+        }
+    };
+    if request.path.split('?').next() == Some(WS_PATH) {
+        // the WebSocket handshake is performed by the pipe, thus the request is replayed to it
+        let _ = connections.unbounded_send(stream.replay(head));
+        return;
+    }
+    let response = site.respond(&request).await;
+    let with_body = request.method != "HEAD";
+    if let Err(err) = write_response(&mut stream, response, with_body).await {
+        error!("Could not send response: {}", err);
+    }
+}
+
+/// Reads and parses the head of an HTTP request.
+async fn read_request(stream: &mut Connection, timeout: Duration) -> io::Result<RequestHead> {
+    let mut buf = vec![0; MAX_REQUEST_HEAD_LEN];
+    let mut len = 0;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let n = match io::timeout(remaining, stream.read(&mut buf[len..])).await {
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::TimedOut => return Ok(RequestHead::TimedOut),
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            return Ok(RequestHead::Invalid);
+        }
+        len += n;
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut request = httparse::Request::new(&mut headers);
+        match request.parse(&buf[..len]) {
+            Ok(httparse::Status::Complete(_)) => {
+                let request = Request {
+                    method: request.method.unwrap().to_string(),
+                    path: request.path.unwrap().to_string(),
+                };
+                buf.truncate(len);
+                return Ok(RequestHead::Complete(request, buf));
+            }
+            // wait for the rest of the request head to arrive
+            Ok(httparse::Status::Partial) if len < buf.len() => {}
+            _ => return Ok(RequestHead::Invalid),
+        }
+    }
+}
+
+async fn write_response(
+    stream: &mut Connection,
+    response: Response,
+    with_body: bool,
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    if with_body {
+        stream.write_all(&response.body).await?;
+    }
+    stream.flush().await
+}
+
+/// Guesses the content type of a file based on its extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
+    match extension.to_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::{Pipe, RxMsg, PROTOCOL_VERSION};
+    use crate::websockets::tests::{client_handshake, connect_tls, test_tls_config};
+    use async_tungstenite::client_async;
+    use std::io::{Read, Write};
+    use url::Url;
+
+    fn listen() -> HttpServer {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        HttpServer::listen_to_socket(TcpListener::from(listener))
+    }
+
+    fn get(port: u16, path: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_index_and_bundle() {
+        let server = listen()
+            .title("<Test>")
+            .css("body { color: red; }")
+            .prerender("<div>Loading</div>");
+        let port = server.port();
+        let _pipe = server.start();
+
+        let index = get(port, "/");
+        assert!(index.starts_with("HTTP/1.1 200 OK"));
+        assert!(index.contains("<title>&lt;Test&gt;</title>"));
+        assert!(index.contains("<style>body { color: red; }</style>"));
+        assert!(index.contains("<script src=\"/bundle.js\"></script>"));
+        assert!(index.contains("<body><div>Loading</div></body>"));
+
+        let bundle = get(port, "/bundle.js");
+        assert!(bundle.starts_with("HTTP/1.1 200 OK"));
+        assert!(bundle.ends_with(BUNDLE_JS));
+
+        assert!(get(port, "/missing").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_index_html_token() {
+        let index = listen().index_html();
+        assert!(index.contains("window.location.host + \"/ws\");"));
+
+        let access = Access::new().require_token("a+b </script>");
+        let index = listen().access(access).index_html();
+        assert!(index.contains("window.location.host + \"/ws?token=a%2Bb%20%3C%2Fscript%3E\");"));
+    }
+
+    #[test]
+    fn test_bundle_is_up_to_date() {
+        // the bundle must be rebuilt using `node scripts/bundle.js` whenever js/ changes
+        let version = format!("const PROTOCOL_VERSION = {};", PROTOCOL_VERSION);
+        assert!(BUNDLE_JS.contains(&version));
+        assert!(BUNDLE_JS.contains("socket.send(serializeHandshake("));
+    }
+
+    #[test]
+    fn test_serve_static_dir() {
+        let dir = std::env::temp_dir().join(format!("greenhorn-http-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("img").join("logo.svg"), "<svg></svg>").unwrap();
+        std::fs::write(dir.join("img").join("my logo.svg"), "<svg></svg>").unwrap();
+
+        let server = listen().static_dir("/assets/", &dir);
+        let port = server.port();
+        let _pipe = server.start();
+
+        let response = get(port, "/assets/img/logo.svg");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Type: image/svg+xml"));
+        assert!(response.ends_with("<svg></svg>"));
+        assert!(get(port, "/assets/img/my%20logo.svg").starts_with("HTTP/1.1 200 OK"));
+        assert!(get(port, "/assets/../img/logo.svg").starts_with("HTTP/1.1 404"));
+        assert!(get(port, "/assets/%2E%2E/img/logo.svg").starts_with("HTTP/1.1 404"));
+        assert!(get(port, "/assetsimg/logo.svg").starts_with("HTTP/1.1 404"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_request_head_in_fragments() {
+        let server = listen().request_timeout(Duration::from_millis(200));
+        let port = server.port();
+        let _pipe = server.start();
+
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(b"GET /missing HTTP/1.1\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"Host: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        // an incomplete head is answered once the request timeout expires
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408"));
+
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(b"GET\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn test_tls() {
+        let server = listen().tls(test_tls_config());
+        let port = server.port();
+        let (_sender, mut receiver) = server.start().split();
+        task::block_on(async move {
+            let mut stream = connect_tls(port).await;
+            let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+            let url = Url::parse(&format!("wss://localhost:{}/ws", port)).unwrap();
+            let (mut ws, _) = client_async(url, connect_tls(port).await).await.unwrap();
+            client_handshake(&mut ws).await;
+            assert!(matches!(receiver.next().await, Some(RxMsg::Connected())));
+        });
+    }
+
+    #[test]
+    fn test_upgrade_websocket() {
+        let server = listen();
+        let port = server.port();
+        let (_sender, mut receiver) = server.start().split();
+        task::block_on(async move {
+            let url = Url::parse(&format!("ws://127.0.0.1:{}/ws", port)).unwrap();
+            let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let (mut ws, _) = client_async(url, TcpStream::from(stream)).await.unwrap();
+            client_handshake(&mut ws).await;
+            assert!(matches!(receiver.next().await, Some(RxMsg::Connected())));
+        });
+    }
+}
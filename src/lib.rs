@@ -108,9 +108,25 @@ pub mod service;
 /// Virtual DOM implementation with diffing and patch generation
 mod vdom;
 
+pub mod patch;
+
+pub mod ssr;
+
+/// Renders nodes without a runtime, used for server-side rendering and snapshots
+mod standalone;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub mod websockets;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod http;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod testing;
+
 #[cfg(target_arch = "wasm32")]
 pub mod wasm_pipe;
 
@@ -152,18 +168,28 @@ pub mod prelude {
     pub use crate::Id;
     pub use crate::{App, Render};
 
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use crate::http::HttpServer;
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use crate::server::Server;
     #[cfg(not(target_arch = "wasm32"))]
     pub use crate::websockets::WebSocketPipe;
 
     pub use crate::blob::Blob;
     pub use crate::node_builder::{ElementBuilder, NodeBuilder};
-    pub use crate::runtime::{Runtime, RuntimeControl};
+    pub use crate::runtime::{Runtime, RuntimeBuilder, RuntimeControl};
     pub use serde_json::Value as JsonValue;
 }
 
 pub use crate::component::{Component, Updated};
-pub use crate::runtime::{Runtime, RuntimeControl};
+pub use crate::runtime::{
+    Runtime, RuntimeBuilder, RuntimeControl, RuntimeError, SendFailurePolicy,
+};
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::http::HttpServer;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::server::Server;
 #[cfg(not(target_arch = "wasm32"))]
 pub use crate::websockets::WebSocketPipe;
 
@@ -368,6 +394,23 @@ pub trait App: Render {
     /// Shall be called upon application startup.
     /// A parent component is required to call this function of all child components.
     fn mount(&mut self, _ctx: Context<Self::Message>) {}
+
+    /// Called once a frontend has (re-)connected to the application.
+    ///
+    /// Only called if the underlying [`Pipe`](pipe/trait.Pipe.html) supports reconnecting frontends.
+    /// The runtime re-synchronizes the frontend with the full application state afterwards.
+    fn on_connect(&mut self, _ctx: Context<Self::Message>) {}
+
+    /// Called once the frontend has disconnected from the application.
+    ///
+    /// The application keeps running and may be reconnected later on.
+    fn on_disconnect(&mut self, _ctx: Context<Self::Message>) {}
+
+    /// Called once the runtime quits, before services are stopped and the pipe is closed.
+    ///
+    /// Messages to the frontend, e.g. `Context::run_js()`, are still sent. Futures, streams and
+    /// services spawned from this function are not executed anymore.
+    fn on_quit(&mut self, _ctx: Context<Self::Message>) {}
 }
 
 use proc_macro_hack::proc_macro_hack;
@@ -392,6 +435,9 @@ use proc_macro_hack::proc_macro_hack;
 /// }
 /// ```
 ///
+/// The `key` attribute is not rendered to the DOM. Instead it assigns a key to the element,
+/// refer to [`ElementBuilder::key()`](node_builder/struct.ElementBuilder.html#method.key).
+///
 #[proc_macro_hack(support_nested)]
 pub use html_macro::html;
 
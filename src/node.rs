@@ -4,7 +4,10 @@ use crate::element::{Element, ElementMapDirect, ElementRemap, MappedElement};
 use crate::event::Subscription;
 use crate::node_builder::NodeBuilder;
 use crate::Id;
+use std::any::TypeId;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::{Debug, Error, Formatter};
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex};
 
 /// Represents a DOM node which might emit a message of type `T`.
@@ -20,6 +23,7 @@ use std::sync::{Arc, Mutex};
 ///  * [Component](../component/struct.Component.html) instances
 ///  * Event subscriptions
 ///  * Blobs
+///  * Memoized nodes, see [Node::memo()](#method.memo)
 ///
 /// Furthermore, `Node`s can be constructed using the `html!()` and `svg!()` macros.
 /// Nodes may not necessarily lead to a node rendered in the DOM but merely represent
@@ -77,6 +81,7 @@ pub(crate) enum NodeItems<T: 'static + Send> {
     Blob(Blob),
     EventSubscription(Id, Subscription<T>),
     FlatMap(Vec<Node<T>>),
+    Memo(u64, Box<dyn FnOnce() -> Node<T>>),
 }
 
 impl<T: 'static + Send> Debug for Node<T> {
@@ -89,6 +94,7 @@ impl<T: 'static + Send> Debug for Node<T> {
             NodeItems::EventSubscription(_, subs) => subs.fmt(f),
             NodeItems::Blob(blob) => blob.fmt(f),
             NodeItems::FlatMap(nodes) => nodes.iter().map(|x| x.fmt(f)).collect(),
+            NodeItems::Memo(key, _) => write!(f, "Memo({})", key),
         }
     }
 }
@@ -113,6 +119,51 @@ impl<T: 'static + Send> Node<T> {
         Node(NodeItems::Text(data.to_string()))
     }
 
+    /// Produce a memoized node.
+    ///
+    /// `fun` is only called if no memoized node with an equal `key` was rendered
+    /// in the previous frame. Otherwise, the previously rendered DOM is reused, along
+    /// with its listeners, rpcs and subscriptions.
+    /// This is much lighter than wrapping small pieces of DOM, such as table rows, into a
+    /// [Component](../component/struct.Component.html).
+    ///
+    /// The `key` must reflect everything `fun` depends on. It is combined with the type of
+    /// `fun`, thus equal keys used at different call sites do not collide.
+    /// However, memoized nodes created at the same call site with equal keys are considered equal,
+    /// even if `fun` captures different values or the nodes are mapped differently. All of them
+    /// reuse the same rendered DOM in the next frame.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use greenhorn::node::Node;
+    /// #
+    /// #[derive(Hash, Clone)]
+    /// struct Row {
+    ///     name: String,
+    ///     value: u32,
+    /// }
+    ///
+    /// fn render_rows(rows: &[Row]) -> Node<()> {
+    ///     let rows = rows.iter().map(|row| {
+    ///         let row = row.clone();
+    ///         Node::memo(row.clone(), move || {
+    ///             Node::html().elem("tr")
+    ///                 .add(Node::html().elem("td").add(row.name))
+    ///                 .add(Node::html().elem("td").add(row.value.to_string()))
+    ///                 .build()
+    ///         })
+    ///     });
+    ///     Node::html().elem("table").add(rows).build()
+    /// }
+    /// ```
+    pub fn memo<K: Hash, F: 'static + FnOnce() -> Node<T>>(key: K, fun: F) -> Self {
+        let mut hasher = DefaultHasher::new();
+        TypeId::of::<F>().hash(&mut hasher);
+        key.hash(&mut hasher);
+        Node(NodeItems::Memo(hasher.finish(), Box::new(fun)))
+    }
+
     /// Maps the message type of the node to a new message type
     ///
     /// When nesting different `Render` implementations or components, the message types need
@@ -175,6 +226,9 @@ impl<T: 'static + Send> Node<T> {
             NodeItems::FlatMap(mut nodes) => {
                 NodeItems::FlatMap(nodes.drain(..).map(|x| x.map_shared(fun.clone())).collect())
             }
+            NodeItems::Memo(key, inner) => {
+                NodeItems::Memo(key, Box::new(move || inner().map_shared(fun)))
+            }
         };
         Node(ret)
     }
@@ -206,6 +260,7 @@ impl<T: 'static + Send> Node<T> {
                     children,
                     namespace: elem.namespace,
                     rpc: None,
+                    key: elem.key,
                 }))
             }
             NodeItems::EventSubscription(_, _) => panic!(),
@@ -213,6 +268,9 @@ impl<T: 'static + Send> Node<T> {
             NodeItems::FlatMap(mut nodes) => Node(NodeItems::FlatMap(
                 nodes.drain(..).map(|x| x.empty_map()).collect(),
             )),
+            NodeItems::Memo(key, inner) => {
+                Node(NodeItems::Memo(key, Box::new(move || inner().empty_map())))
+            }
         }
     }
 
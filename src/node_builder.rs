@@ -113,6 +113,7 @@ pub struct ElementBuilder<T: 'static + Send> {
     pub(crate) classes: Vec<String>,
     pub(crate) html_id: Option<String>,
     pub(crate) rpc: Option<Rpc<T>>,
+    pub(crate) key: Option<String>,
 }
 
 impl<T: 'static + Send> ElementBuilder<T> {
@@ -128,6 +129,7 @@ impl<T: 'static + Send> ElementBuilder<T> {
             classes: vec![],
             html_id: None,
             rpc: None,
+            key: None,
         }
     }
 
@@ -215,6 +217,17 @@ impl<T: 'static + Send> ElementBuilder<T> {
         self
     }
 
+    /// Assigns a key to this element, which identifies it among its siblings.
+    ///
+    /// If all children of an element are keyed, the differ matches old and new
+    /// children by key instead of by position. Thus, inserting, removing or re-ordering
+    /// keyed children moves the existing DOM nodes instead of re-emitting them.
+    /// Keys must be unique among the siblings of an element.
+    pub fn key<S: ToString>(mut self, key: S) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
     pub fn class<S: Into<String>>(mut self, class: S) -> Self {
         self.classes.push(class.into());
         self
@@ -244,6 +257,7 @@ impl<T: 'static + Send> ElementBuilder<T> {
             children: Some(self.children),
             namespace: self.namespace,
             rpc: self.rpc,
+            key: self.key,
         }))
     }
 }
@@ -513,4 +527,15 @@ mod tests {
             panic!();
         }
     }
+
+    #[test]
+    fn test_key() {
+        let node = builder().elem("li").key(12).attr("foo", "bar").build();
+        if let NodeItems::Element(elem) = node.0 {
+            assert_eq!(elem.key.as_deref(), Some("12"));
+            assert_eq!(elem.attrs.as_ref().unwrap().len(), 1);
+        } else {
+            panic!()
+        }
+    }
 }
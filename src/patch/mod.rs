@@ -0,0 +1,477 @@
+//! This module allows inspecting patches on the Rust side.
+//!
+//! Patches are sent to the frontend in a compact binary format, which is usually only
+//! interpreted by the javascript frontend. A serialized patch may be decoded into a list
+//! of [Instruction](enum.Instruction.html)s using [DecodedPatch::decode()](struct.DecodedPatch.html#method.decode).
+//! The `Display` implementation of a [DecodedPatch](struct.DecodedPatch.html) prints its
+//! instructions in a readable form, which is helpful when debugging a misbehaving frontend.
+//!
+//! Decoded patches may be applied to a [Document](struct.Document.html), which models the
+//! DOM of the frontend in the same way as the javascript implementation does.
+//!
+//! # Example
+//!
+//! ```
+//! # use greenhorn::patch::{DecodedPatch, Document};
+//! # fn print(data: &[u8]) {
+//! let patch = DecodedPatch::decode(data).unwrap();
+//! println!("{}", patch);
+//! let mut document = Document::new();
+//! document.apply(&patch).unwrap();
+//! println!("{}", document);
+//! # }
+//! ```
+//!
+
+mod model;
+
+pub(crate) use model::Checksum;
+pub use model::{ApplyError, BlobData, Document, Element, EventListener, Node};
+
+use crate::vdom::PATCH_FORMAT_VERSION;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// An instruction of a decoded patch.
+///
+/// While applying a patch, the frontend keeps a pointer to a DOM node. Instructions either
+/// move this pointer or modify the node it points to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// Appends a node to the parent of the current node and moves the pointer to it
+    AppendSibling(Node),
+    /// Replaces the current node
+    Replace(Node),
+    /// Replaces the current node with a text node
+    ChangeText(String),
+    /// Moves the pointer to the parent node
+    Ascend,
+    /// Moves the pointer to the first child node
+    Descend,
+    /// Removes all children of the current node
+    RemoveChildren,
+    /// Removes all following siblings of the current node
+    TruncateSiblings,
+    /// Moves the pointer by the given number of siblings
+    NextNode(u64),
+    RemoveAttribute(String),
+    AddAttribute(String, String),
+    ReplaceAttribute(String, String),
+    AddBlob(u64, BlobData),
+    RemoveBlob(u64),
+    RemoveJsEvent(String),
+    AddJsEvent(String, String),
+    ReplaceJsEvent(String, String),
+    /// Appends nodes to the children of the current node
+    AddChildren(Vec<Node>),
+    /// Inserts a node before the child at the given index
+    InsertChild(u64, Node),
+    /// Moves the child at index `from` to index `to`
+    MoveChild(u64, u64),
+    /// Removes the child at the given index
+    RemoveChild(u64),
+    /// Attaches to the current node if it was rendered on the server, otherwise same as `Replace`
+    Hydrate(Node),
+    /// Verifies that the checksum of the DOM matches, see [Node::checksum()](enum.Node.html#method.checksum)
+    Checksum(u32),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::AppendSibling(node) => write!(f, "AppendSibling {}", node),
+            Instruction::Replace(node) => write!(f, "Replace {}", node),
+            Instruction::ChangeText(text) => write!(f, "ChangeText {:?}", text),
+            Instruction::Ascend => write!(f, "Ascend"),
+            Instruction::Descend => write!(f, "Descend"),
+            Instruction::RemoveChildren => write!(f, "RemoveChildren"),
+            Instruction::TruncateSiblings => write!(f, "TruncateSiblings"),
+            Instruction::NextNode(n) => write!(f, "NextNode {}", n),
+            Instruction::RemoveAttribute(key) => write!(f, "RemoveAttribute {}", key),
+            Instruction::AddAttribute(key, value) => {
+                write!(f, "AddAttribute {}={:?}", key, value)
+            }
+            Instruction::ReplaceAttribute(key, value) => {
+                write!(f, "ReplaceAttribute {}={:?}", key, value)
+            }
+            Instruction::AddBlob(id, blob) => write!(
+                f,
+                "AddBlob #{} {} ({} bytes, hash {:x})",
+                id,
+                blob.mime_type,
+                blob.data.len(),
+                blob.hash
+            ),
+            Instruction::RemoveBlob(id) => write!(f, "RemoveBlob #{}", id),
+            Instruction::RemoveJsEvent(key) => write!(f, "RemoveJsEvent {}", key),
+            Instruction::AddJsEvent(key, code) => write!(f, "AddJsEvent {} {:?}", key, code),
+            Instruction::ReplaceJsEvent(key, code) => {
+                write!(f, "ReplaceJsEvent {} {:?}", key, code)
+            }
+            Instruction::AddChildren(children) => {
+                write!(f, "AddChildren")?;
+                for child in children {
+                    write!(f, " {}", child)?;
+                }
+                Ok(())
+            }
+            Instruction::InsertChild(idx, node) => write!(f, "InsertChild {} {}", idx, node),
+            Instruction::MoveChild(from, to) => write!(f, "MoveChild {} -> {}", from, to),
+            Instruction::RemoveChild(idx) => write!(f, "RemoveChild {}", idx),
+            Instruction::Hydrate(node) => write!(f, "Hydrate {}", node),
+            Instruction::Checksum(checksum) => write!(f, "Checksum {:08x}", checksum),
+        }
+    }
+}
+
+/// A patch decoded from its binary representation.
+///
+/// Its `Display` implementation prints one instruction per line, indented according
+/// to the depth of the pointer relative to where the patch started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPatch {
+    /// Number of the frame, which is acknowledged by the frontend once the patch was applied
+    pub frame: u64,
+    pub instructions: Vec<Instruction>,
+}
+
+impl DecodedPatch {
+    /// Decodes a patch as sent to the frontend in a `TxMsg::Patch` message.
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder {
+            data,
+            offset: 0,
+            strings: Vec::new(),
+        };
+        let version = decoder.u8()?;
+        if version != PATCH_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let frame = decoder.varint()?;
+        let mut instructions = Vec::new();
+        while decoder.offset < data.len() {
+            instructions.push(decoder.instruction()?);
+        }
+        Ok(Self {
+            frame,
+            instructions,
+        })
+    }
+}
+
+impl Display for DecodedPatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut depth: usize = 0;
+        for instruction in &self.instructions {
+            if *instruction == Instruction::Ascend {
+                depth = depth.saturating_sub(1);
+            }
+            writeln!(f, "{:indent$}{}", "", instruction, indent = 2 * depth)?;
+            if *instruction == Instruction::Descend {
+                depth += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error raised if a patch could not be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The patch was encoded with a different version of the format
+    UnsupportedVersion(u8),
+    /// The patch ended in the middle of an instruction
+    UnexpectedEnd,
+    /// An unknown instruction or node type was encountered at the given offset
+    InvalidOpcode(u8, usize),
+    /// A string was not valid UTF-8
+    InvalidString,
+    /// An interned string referred to an entry not contained in the string table
+    InvalidStringRef(u64),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported patch format version: {}", version)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of patch"),
+            DecodeError::InvalidOpcode(opcode, offset) => {
+                write!(f, "Invalid opcode {} at offset {}", opcode, offset)
+            }
+            DecodeError::InvalidString => write!(f, "Invalid UTF-8 string"),
+            DecodeError::InvalidStringRef(idx) => write!(f, "Invalid string reference: {}", idx),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Reads a binary patch as written by `vdom::serialize`.
+struct Decoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+    strings: Vec<String>,
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let ret = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, DecodeError> {
+        Ok(self.u8()? > 0)
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut data = [0; 8];
+        data.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(data))
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut ret = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                ret |= ((byte & 0x7f) as u64) << shift;
+            }
+            if byte < 0x80 {
+                return Ok(ret);
+            }
+            shift += 7;
+        }
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        let len = self.varint()?;
+        // the length cannot exceed the remaining data, which prevents huge allocations
+        if len > (self.data.len() - self.offset) as u64 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        Ok(len as usize)
+    }
+
+    fn data(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let len = self.len()?;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.data()?).map_err(|_| DecodeError::InvalidString)
+    }
+
+    fn interned(&mut self) -> Result<String, DecodeError> {
+        let idx = self.varint()?;
+        if idx == 0 {
+            let ret = self.string()?;
+            self.strings.push(ret.clone());
+            return Ok(ret);
+        }
+        self.strings
+            .get((idx - 1) as usize)
+            .cloned()
+            .ok_or(DecodeError::InvalidStringRef(idx))
+    }
+
+    fn id(&mut self) -> Result<Option<u64>, DecodeError> {
+        if self.bool()? {
+            Ok(Some(self.u64()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, DecodeError> {
+        if self.bool()? {
+            Ok(Some(self.string()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn node(&mut self) -> Result<Node, DecodeError> {
+        let offset = self.offset;
+        match self.u8()? {
+            0 => Ok(Node::Element(self.element()?)),
+            1 => Ok(Node::Text(self.string()?)),
+            x => Err(DecodeError::InvalidOpcode(x, offset)),
+        }
+    }
+
+    fn nodes(&mut self) -> Result<Vec<Node>, DecodeError> {
+        let len = self.len()?;
+        let mut ret = Vec::with_capacity(len);
+        for _ in 0..len {
+            ret.push(self.node()?);
+        }
+        Ok(ret)
+    }
+
+    fn element(&mut self) -> Result<Element, DecodeError> {
+        let tag = self.interned()?;
+        let namespace = if self.bool()? {
+            Some(self.interned()?)
+        } else {
+            None
+        };
+        let id = self.id()?;
+        let mut elem = Element::new(tag);
+        elem.namespace = namespace;
+        elem.id = id;
+        for _ in 0..self.len()? {
+            let key = self.interned()?;
+            let value = self.string()?;
+            elem.attributes.insert(key, value);
+        }
+        for _ in 0..self.len()? {
+            let no_propagate = self.bool()?;
+            let prevent_default = self.bool()?;
+            let name = self.interned()?;
+            elem.events.push(EventListener {
+                name,
+                no_propagate,
+                prevent_default,
+            });
+        }
+        for _ in 0..self.len()? {
+            let key = self.interned()?;
+            let value = self.string()?;
+            elem.js_events.insert(key, value);
+        }
+        elem.children = self.nodes()?;
+        Ok(elem)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let offset = self.offset;
+        let ret = match self.u8()? {
+            1 => Instruction::AppendSibling(self.node()?),
+            3 => Instruction::Replace(self.node()?),
+            4 => Instruction::ChangeText(self.string()?),
+            5 => Instruction::Ascend,
+            6 => Instruction::Descend,
+            7 => Instruction::RemoveChildren,
+            8 => Instruction::TruncateSiblings,
+            9 => Instruction::NextNode(self.varint()?),
+            10 => Instruction::RemoveAttribute(self.interned()?),
+            11 => Instruction::AddAttribute(self.interned()?, self.string()?),
+            12 => Instruction::ReplaceAttribute(self.interned()?, self.string()?),
+            13 => {
+                let id = self.id()?.unwrap_or_default();
+                let hash = self.u64()?;
+                let mime_type = self.interned()?;
+                let data = self.data()?;
+                let on_add = self.optional_string()?;
+                let on_change = self.optional_string()?;
+                let blob = BlobData {
+                    hash,
+                    mime_type,
+                    data,
+                    on_add,
+                    on_change,
+                };
+                Instruction::AddBlob(id, blob)
+            }
+            14 => Instruction::RemoveBlob(self.id()?.unwrap_or_default()),
+            15 => Instruction::RemoveJsEvent(self.interned()?),
+            16 => Instruction::AddJsEvent(self.interned()?, self.string()?),
+            17 => Instruction::ReplaceJsEvent(self.interned()?, self.string()?),
+            18 => Instruction::AddChildren(self.nodes()?),
+            19 => Instruction::InsertChild(self.varint()?, self.node()?),
+            20 => Instruction::MoveChild(self.varint()?, self.varint()?),
+            21 => Instruction::RemoveChild(self.varint()?),
+            22 => Instruction::Hydrate(self.node()?),
+            23 => Instruction::Checksum(self.varint()? as u32),
+            x => return Err(DecodeError::InvalidOpcode(x, offset)),
+        };
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(DecodedPatch::decode(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(
+            DecodedPatch::decode(&[1, 5]),
+            Err(DecodeError::UnsupportedVersion(1))
+        );
+        assert_eq!(
+            DecodedPatch::decode(&[PATCH_FORMAT_VERSION, 0, 5, 2]),
+            Err(DecodeError::InvalidOpcode(2, 3))
+        );
+        // string length exceeds the patch
+        assert_eq!(
+            DecodedPatch::decode(&[PATCH_FORMAT_VERSION, 0, 4, 10, b'a']),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            DecodedPatch::decode(&[PATCH_FORMAT_VERSION, 0, 10, 1]),
+            Err(DecodeError::InvalidStringRef(1))
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut elem = Element::new("div");
+        elem.id = Some(1);
+        elem.children.push(Node::Text("a".into()));
+        let node = Node::Element(elem);
+        let checksum = node.checksum();
+
+        let patch = |instructions| DecodedPatch {
+            frame: 1,
+            instructions,
+        };
+        let mut document = Document::new();
+        let replace = vec![Instruction::Replace(node), Instruction::Checksum(checksum)];
+        document.apply(&patch(replace)).unwrap();
+
+        // the structure of the document differs after removing the text
+        let remove = vec![Instruction::RemoveChildren, Instruction::Checksum(checksum)];
+        let err = document.apply(&patch(remove)).unwrap_err();
+        assert_eq!(err.instruction, 1);
+    }
+
+    #[test]
+    fn test_node_offset_overflow() {
+        let patch = DecodedPatch {
+            frame: 1,
+            instructions: vec![Instruction::NextNode(u64::MAX), Instruction::NextNode(1)],
+        };
+        let mut data = vec![PATCH_FORMAT_VERSION, 1, 9];
+        data.extend_from_slice(&[0xff; 9]);
+        data.extend_from_slice(&[1, 9, 1]);
+        assert_eq!(DecodedPatch::decode(&data), Ok(patch.clone()));
+        // the pointer overflows instead of panicking
+        assert!(Document::new().apply(&patch).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let data = [PATCH_FORMAT_VERSION, 7, 6, 9, 2, 11, 0, 1, b'a', 1, b'b', 5];
+        let patch = DecodedPatch::decode(&data).unwrap();
+        assert_eq!(patch.frame, 7);
+        assert_eq!(
+            patch.to_string(),
+            "Descend\n  NextNode 2\n  AddAttribute a=\"b\"\nAscend\n"
+        );
+    }
+}
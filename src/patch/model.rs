@@ -0,0 +1,497 @@
+use crate::patch::{DecodedPatch, Instruction};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+#[cfg(test)]
+use crate::runtime::RenderResult;
+#[cfg(test)]
+use crate::vdom::VNode;
+#[cfg(test)]
+use crate::{App, Id};
+
+/// Offset basis and prime of the 32-bit FNV-1a hash used for DOM checksums
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Markers of the structural elements covered by a DOM checksum
+const CHECKSUM_TEXT: u32 = 1;
+const CHECKSUM_ELEMENT: u32 = 2;
+const CHECKSUM_END: u32 = 3;
+
+/// A node of a [Document](struct.Document.html).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Node {
+    /// Converts a VDOM into the DOM the frontend shows after applying it.
+    ///
+    /// Components are resolved using `rendered`. The ids of the elements are
+    /// translated using `translations`, which maps new ids to the ids known to the frontend.
+    #[cfg(test)]
+    pub(crate) fn from_vnode<A: App>(
+        vnode: &VNode,
+        rendered: &RenderResult<A>,
+        translations: &HashMap<Id, Id>,
+    ) -> Node {
+        match vnode {
+            VNode::Element(elem) => {
+                let id = translations.get(&elem.id).unwrap_or(&elem.id);
+                let mut ret = Element::new(elem.tag.clone());
+                ret.namespace = elem.namespace.clone();
+                ret.id = if id.is_empty() { None } else { Some(id.data()) };
+                for attr in &elem.attr {
+                    ret.attributes.insert(attr.key.clone(), attr.value.clone());
+                }
+                for evt in &elem.events {
+                    ret.events.push(EventListener {
+                        name: evt.name.clone(),
+                        no_propagate: evt.no_propagate,
+                        prevent_default: evt.prevent_default,
+                    });
+                }
+                for evt in &elem.js_events {
+                    ret.js_events.insert(evt.key.clone(), evt.value.clone());
+                }
+                ret.children = elem
+                    .children
+                    .iter()
+                    .map(|x| Node::from_vnode(x, rendered, translations))
+                    .collect();
+                Node::Element(ret)
+            }
+            VNode::Text(text) => Node::Text(text.clone()),
+            VNode::Placeholder(id, _) => {
+                let vdom = rendered.get_component_vdom(*id).unwrap();
+                Node::from_vnode(vdom, rendered, translations)
+            }
+        }
+    }
+
+    /// Computes a checksum of the structure of this subtree.
+    ///
+    /// The checksum covers the node types, the element ids and the nesting of the nodes,
+    /// which is what the frontend relies on to apply patches. It is computed in the same way as
+    /// by the javascript frontend when verifying an [Instruction::Checksum](enum.Instruction.html#variant.Checksum).
+    pub fn checksum(&self) -> u32 {
+        let mut checksum = Checksum::new();
+        self.hash_structure(&mut checksum);
+        checksum.value()
+    }
+
+    fn hash_structure(&self, checksum: &mut Checksum) {
+        match self {
+            Node::Element(elem) => {
+                checksum.element(elem.id);
+                for child in &elem.children {
+                    child.hash_structure(checksum);
+                }
+                checksum.end();
+            }
+            Node::Text(_) => checksum.text(),
+        }
+    }
+
+    /// Returns the element if this node is an element.
+    pub fn as_element(&self) -> Option<&Element> {
+        match self {
+            Node::Element(elem) => Some(elem),
+            Node::Text(_) => None,
+        }
+    }
+
+    /// Concatenates the text of this node and all its descendants.
+    pub fn text(&self) -> String {
+        match self {
+            Node::Element(elem) => elem.text(),
+            Node::Text(text) => text.clone(),
+        }
+    }
+
+    /// Appends all elements in this subtree matching `predicate` to `ret`, in document order.
+    fn collect<'a, F: Fn(&Element) -> bool>(&'a self, predicate: &F, ret: &mut Vec<&'a Element>) {
+        if let Node::Element(elem) = self {
+            if predicate(elem) {
+                ret.push(elem);
+            }
+            for child in &elem.children {
+                child.collect(predicate, ret);
+            }
+        }
+    }
+}
+
+/// The 32-bit FNV-1a hash of the structure of a DOM, refer to `Node::checksum()`.
+///
+/// Nodes are added in document order: each element is started using `element()` and ended
+/// using `end()` after its children.
+pub(crate) struct Checksum(u32);
+
+impl Checksum {
+    pub(crate) fn new() -> Self {
+        Checksum(FNV_OFFSET_BASIS)
+    }
+
+    /// Adds the start of an element. Only the lower 32 bits of the id are covered, since
+    /// the frontend represents ids as javascript numbers.
+    pub(crate) fn element(&mut self, id: Option<u64>) {
+        self.word(CHECKSUM_ELEMENT);
+        match id {
+            Some(id) => {
+                self.word(1);
+                self.word(id as u32);
+            }
+            None => self.word(0),
+        }
+    }
+
+    /// Adds the end of the most recently started element.
+    pub(crate) fn end(&mut self) {
+        self.word(CHECKSUM_END);
+    }
+
+    pub(crate) fn text(&mut self) {
+        self.word(CHECKSUM_TEXT);
+    }
+
+    pub(crate) fn value(&self) -> u32 {
+        self.0
+    }
+
+    fn word(&mut self, word: u32) {
+        self.0 = (self.0 ^ word).wrapping_mul(FNV_PRIME);
+    }
+}
+
+impl Display for Node {
+    /// Formats the node similar to HTML, but also shows event listeners
+    /// as `@name` and javascript events as `js:name`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Element(elem) => elem.fmt(f),
+            Node::Text(text) => {
+                let escaped = text
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                f.write_str(&escaped)
+            }
+        }
+    }
+}
+
+/// An element of a [Document](struct.Document.html).
+///
+/// Attributes and javascript events are kept in sorted order, as their order has
+/// no effect on the DOM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub tag: String,
+    pub namespace: Option<String>,
+    /// The id used to identify the element when an event is fired
+    pub id: Option<u64>,
+    pub attributes: BTreeMap<String, String>,
+    pub events: Vec<EventListener>,
+    pub js_events: BTreeMap<String, String>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Creates an empty element
+    pub fn new<T: Into<String>>(tag: T) -> Self {
+        Self {
+            tag: tag.into(),
+            namespace: None,
+            id: None,
+            attributes: BTreeMap::new(),
+            events: Vec::new(),
+            js_events: BTreeMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Concatenates the text of all descendants of this element.
+    pub fn text(&self) -> String {
+        self.children.iter().map(|x| x.text()).collect()
+    }
+
+    /// Returns true if the `class` attribute of this element contains `class`.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attributes
+            .get("class")
+            .map(|x| x.split_whitespace().any(|x| x == class))
+            .unwrap_or(false)
+    }
+
+    /// Returns the event listener registered for the event `name`, if any.
+    pub fn listener(&self, name: &str) -> Option<&EventListener> {
+        self.events.iter().find(|x| x.name == name)
+    }
+}
+
+impl Display for Element {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}", self.tag)?;
+        for (key, value) in &self.attributes {
+            write!(f, " {}={:?}", key, value)?;
+        }
+        for evt in &self.events {
+            write!(f, " @{}", evt.name)?;
+        }
+        for key in self.js_events.keys() {
+            write!(f, " js:{}", key)?;
+        }
+        write!(f, ">")?;
+        for child in &self.children {
+            child.fmt(f)?;
+        }
+        write!(f, "</{}>", self.tag)
+    }
+}
+
+/// An event listener registered on an [Element](struct.Element.html), which forwards
+/// events to the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventListener {
+    pub name: String,
+    pub no_propagate: bool,
+    pub prevent_default: bool,
+}
+
+/// A blob which was sent to the frontend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobData {
+    pub hash: u64,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    /// Javascript code run once the blob was added
+    pub on_add: Option<String>,
+    /// Javascript code run once the blob has changed
+    pub on_change: Option<String>,
+}
+
+/// Models the DOM of a frontend, which patches are applied to.
+///
+/// The document represents the element the application is mounted to. Initially, it
+/// does not contain any nodes and the first patch replaces its first child.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub children: Vec<Node>,
+    pub blobs: HashMap<u64, BlobData>,
+}
+
+impl Document {
+    /// Creates an empty document
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the root node of the application, if any.
+    pub fn root(&self) -> Option<&Node> {
+        self.children.first()
+    }
+
+    /// Returns all elements matching `predicate` in document order.
+    pub fn find_all<F: Fn(&Element) -> bool>(&self, predicate: F) -> Vec<&Element> {
+        let mut ret = Vec::new();
+        for child in &self.children {
+            child.collect(&predicate, &mut ret);
+        }
+        ret
+    }
+
+    /// Applies a patch in the same way as the javascript frontend does.
+    ///
+    /// In case of an error, the document may have been partially modified.
+    pub fn apply(&mut self, patch: &DecodedPatch) -> Result<(), ApplyError> {
+        // path of child indices from the document to the current node
+        let mut pointer = vec![0];
+        for (idx, instruction) in patch.instructions.iter().enumerate() {
+            self.apply_instruction(&mut pointer, instruction)
+                .map_err(|err| ApplyError {
+                    instruction: idx,
+                    reason: err,
+                })?;
+        }
+        Ok(())
+    }
+
+    fn apply_instruction(
+        &mut self,
+        pointer: &mut Vec<usize>,
+        instruction: &Instruction,
+    ) -> Result<(), String> {
+        match instruction {
+            Instruction::AppendSibling(node) => {
+                let (parent, _) = split(pointer)?;
+                let siblings = self.children_mut(parent)?;
+                siblings.push(node.clone());
+                let idx = siblings.len() - 1;
+                *pointer.last_mut().unwrap() = idx;
+            }
+            // the document never contains server-rendered nodes
+            Instruction::Replace(node) | Instruction::Hydrate(node) => {
+                self.replace(pointer, node.clone())?
+            }
+            Instruction::ChangeText(text) => self.replace(pointer, Node::Text(text.clone()))?,
+            Instruction::Ascend => {
+                pointer.pop();
+                if pointer.is_empty() {
+                    return Err("Cannot ascend above the document".into());
+                }
+            }
+            Instruction::Descend => pointer.push(0),
+            Instruction::NextNode(n) => {
+                let idx = pointer
+                    .last_mut()
+                    .ok_or_else(|| "Pointer does not refer to a node".to_string())?;
+                *idx = usize::try_from(*n)
+                    .ok()
+                    .and_then(|n| idx.checked_add(n))
+                    .ok_or_else(|| format!("Node offset out of range: {}", n))?;
+            }
+            Instruction::RemoveChildren => self.element_mut(pointer)?.children.clear(),
+            Instruction::TruncateSiblings => {
+                let (parent, idx) = split(pointer)?;
+                self.children_mut(parent)?.truncate(idx + 1);
+            }
+            Instruction::RemoveAttribute(key) => {
+                self.element_mut(pointer)?.attributes.remove(key);
+            }
+            Instruction::AddAttribute(key, value) | Instruction::ReplaceAttribute(key, value) => {
+                let elem = self.element_mut(pointer)?;
+                elem.attributes.insert(key.clone(), value.clone());
+            }
+            Instruction::AddBlob(id, blob) => {
+                self.blobs.insert(*id, blob.clone());
+            }
+            Instruction::RemoveBlob(id) => {
+                self.blobs.remove(id);
+            }
+            Instruction::RemoveJsEvent(key) => {
+                self.element_mut(pointer)?.js_events.remove(key);
+            }
+            Instruction::AddJsEvent(key, value) | Instruction::ReplaceJsEvent(key, value) => {
+                let elem = self.element_mut(pointer)?;
+                elem.js_events.insert(key.clone(), value.clone());
+            }
+            Instruction::AddChildren(nodes) => {
+                let elem = self.element_mut(pointer)?;
+                elem.children.extend(nodes.iter().cloned());
+            }
+            Instruction::InsertChild(idx, node) => {
+                let children = &mut self.element_mut(pointer)?.children;
+                let idx = (*idx as usize).min(children.len());
+                children.insert(idx, node.clone());
+            }
+            Instruction::MoveChild(from, to) => {
+                let children = &mut self.element_mut(pointer)?.children;
+                let from = *from as usize;
+                if from >= children.len() {
+                    return Err(format!("No child at index {}", from));
+                }
+                let child = children.remove(from);
+                let to = (*to as usize).min(children.len());
+                children.insert(to, child);
+            }
+            Instruction::RemoveChild(idx) => {
+                let children = &mut self.element_mut(pointer)?.children;
+                let idx = *idx as usize;
+                if idx >= children.len() {
+                    return Err(format!("No child at index {}", idx));
+                }
+                children.remove(idx);
+            }
+            Instruction::Checksum(expected) => {
+                let actual = self
+                    .root()
+                    .map(Node::checksum)
+                    .unwrap_or_else(|| Checksum::new().value());
+                if actual != *expected {
+                    return Err(format!(
+                        "Checksum mismatch: expected {:08x}, found {:08x}",
+                        expected, actual
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the node at `pointer`. The first child of an empty node may be replaced as well.
+    fn replace(&mut self, pointer: &[usize], node: Node) -> Result<(), String> {
+        let (parent, idx) = split(pointer)?;
+        let siblings = self.children_mut(parent)?;
+        if idx < siblings.len() {
+            siblings[idx] = node;
+        } else if idx == 0 {
+            siblings.push(node);
+        } else {
+            return Err(format!("No node at index {}", idx));
+        }
+        Ok(())
+    }
+
+    /// Returns the children of the node at `path`, which refers to the document if empty.
+    fn children_mut(&mut self, path: &[usize]) -> Result<&mut Vec<Node>, String> {
+        let mut children = &mut self.children;
+        for idx in path {
+            match children.get_mut(*idx) {
+                Some(Node::Element(elem)) => children = &mut elem.children,
+                Some(Node::Text(_)) => return Err("Text nodes do not have children".into()),
+                None => return Err(format!("No node at index {}", idx)),
+            }
+        }
+        Ok(children)
+    }
+
+    fn element_mut(&mut self, path: &[usize]) -> Result<&mut Element, String> {
+        let (parent, idx) = split(path)?;
+        match self.children_mut(parent)?.get_mut(idx) {
+            Some(Node::Element(elem)) => Ok(elem),
+            Some(Node::Text(_)) => Err("Expected an element but found a text node".into()),
+            None => Err(format!("No node at index {}", idx)),
+        }
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            child.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a path into the path of the parent and the index of the node within the parent.
+fn split(path: &[usize]) -> Result<(&[usize], usize), String> {
+    match path.split_last() {
+        Some((idx, parent)) => Ok((parent, *idx)),
+        None => Err("Pointer does not refer to a node".into()),
+    }
+}
+
+/// Error raised if a patch could not be applied to a [Document](struct.Document.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplyError {
+    /// Index of the instruction which failed
+    pub instruction: usize,
+    pub reason: String,
+}
+
+impl Display for ApplyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to apply instruction {}: {}",
+            self.instruction, self.reason
+        )
+    }
+}
+
+impl Error for ApplyError {}
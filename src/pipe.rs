@@ -7,16 +7,120 @@
 //! A `Sender` can be used to send [TxMsg message](enum.TxMsg.html), i.e. message from backend to the frontend.
 //! A `Receiver` can be used to receive [RxMsg message](enum.RxMsg.html), i.e. message from frontend to the backend.
 //!
+//! Before any of these messages are exchanged, frontend and backend perform a [Handshake](struct.Handshake.html)
+//! to ensure that both speak the same protocol version.
+//!
 
 use futures::Sink;
 use futures::Stream;
 
 use crate::context::EventPropagate;
 use crate::dom::DomEvent;
+use crate::runtime::history::HistoryUpdate;
 use crate::service::{RxServiceMessage, TxServiceMessage};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// Version of the protocol spoken between frontend and backend.
+///
+/// Must be incremented whenever the JSON representation of `TxMsg` or `RxMsg`
+/// or the binary patch format changes. Must match `PROTOCOL_VERSION` in `js/protocol.js`.
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// Optional protocol capabilities supported by the backend, regardless of the `Pipe`.
+pub const CAPABILITIES: &[&str] = &[];
+
+/// Capability to compress messages using raw deflate.
+///
+/// Once negotiated, binary messages are prefixed with a header byte, which is one of
+/// `FRAME_RAW_PATCH`, `FRAME_DEFLATE_PATCH` or `FRAME_DEFLATE_JSON`.
+/// Text messages remain uncompressed JSON.
+pub const CAPABILITY_DEFLATE: &str = "deflate";
+
+/// Header of a binary message containing an uncompressed patch
+pub const FRAME_RAW_PATCH: u8 = 0;
+
+/// Header of a binary message containing a deflate compressed patch
+pub const FRAME_DEFLATE_PATCH: u8 = 1;
+
+/// Header of a binary message containing a deflate compressed JSON message
+pub const FRAME_DEFLATE_JSON: u8 = 2;
+
+/// First message exchanged after a frontend has connected.
+///
+/// The frontend sends its handshake first. The backend replies with its
+/// own protocol version and the capabilities supported by both sides.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Handshake {
+    /// Parses the handshake sent by the frontend and negotiates the protocol.
+    ///
+    /// Returns the handshake to reply with, which contains the capabilities supported
+    /// by both frontend and backend.
+    pub fn negotiate(data: &str) -> Result<Handshake, HandshakeError> {
+        Self::negotiate_with(data, CAPABILITIES)
+    }
+
+    /// Same as [negotiate()](#method.negotiate) but with the `capabilities` supported by a specific `Pipe`.
+    pub fn negotiate_with(data: &str, capabilities: &[&str]) -> Result<Handshake, HandshakeError> {
+        let frontend: Handshake =
+            serde_json::from_str(data).map_err(|err| HandshakeError::Invalid(err.to_string()))?;
+        if frontend.version != PROTOCOL_VERSION {
+            return Err(HandshakeError::VersionMismatch {
+                backend: PROTOCOL_VERSION,
+                frontend: frontend.version,
+            });
+        }
+        let capabilities = frontend
+            .capabilities
+            .into_iter()
+            .filter(|x| capabilities.contains(&x.as_str()))
+            .collect();
+        Ok(Handshake {
+            version: PROTOCOL_VERSION,
+            capabilities,
+        })
+    }
+
+    /// Returns true if the given capability was negotiated
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|x| x == capability)
+    }
+}
+
+/// Error raised if frontend and backend fail to agree on a protocol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HandshakeError {
+    /// The frontend speaks a different version of the protocol, e.g. because of a stale JS bundle
+    VersionMismatch { backend: u32, frontend: u32 },
+    /// The first message received from the frontend was not a valid handshake
+    Invalid(String),
+    /// The frontend did not send a handshake in time
+    Timeout,
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::VersionMismatch { backend, frontend } => write!(
+                f,
+                "Protocol version mismatch: backend speaks version {}, frontend speaks version {}",
+                backend, frontend
+            ),
+            HandshakeError::Invalid(err) => write!(f, "Invalid handshake: {}", err),
+            HandshakeError::Timeout => write!(f, "No handshake received from frontend"),
+        }
+    }
+}
+
+impl Error for HandshakeError {}
 
 /// Serializable message type to be sent from the backend to the frontend
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,16 +132,58 @@ pub enum TxMsg {
     Service(u64, TxServiceMessage),
     Propagate(EventPropagate),
     Dialog(JsonValue),
+    /// Changes of the message history, only sent if enabled using `RuntimeBuilder::history()`.
+    History(HistoryUpdate),
+}
+
+/// Time required by the frontend to process a patch, as reported with `RxMsg::FrameApplied`.
+///
+/// The frontend parses the instructions of a patch while applying them, hence parsing is
+/// part of `apply_us` rather than `decode_us`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameTiming {
+    /// Microseconds from receiving the patch until it was ready to be applied,
+    /// mostly spent decompressing it
+    pub decode_us: u64,
+    /// Microseconds required to parse the patch and apply it to the DOM
+    pub apply_us: u64,
+}
+
+impl FrameTiming {
+    /// Returns the time required to receive and decompress the patch
+    pub fn decode(&self) -> Duration {
+        Duration::from_micros(self.decode_us)
+    }
+
+    /// Returns the time required to parse and apply the patch
+    pub fn apply(&self) -> Duration {
+        Duration::from_micros(self.apply_us)
+    }
 }
 
 /// Serializable message type to be sent from the frontend to the backend
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RxMsg {
     Event(DomEvent),
-    FrameApplied(),
+    /// Sent by the frontend once the patch with the given frame number was applied.
+    FrameApplied(u64, FrameTiming),
+    /// Sent by the frontend if the patch with the given frame number could not be applied,
+    /// e.g. since its DOM did not match the checksum of the patch. Contains the error message.
+    PatchFailed(u64, String),
     Service(u64, RxServiceMessage),
     Dialog(JsonValue),
     ElementRpc(u64, JsonValue),
+    /// Requests restoring the application state of the given history entry.
+    TimeTravel(u64),
+    /// Emitted by a `Pipe` once a frontend has (re-)connected. Not sent by the frontend.
+    Connected(),
+    /// Emitted by a `Pipe` once the frontend has disconnected. Not sent by the frontend.
+    Disconnected(),
+    /// Emitted by a `Pipe` if the handshake with the frontend has failed. Not sent by the frontend.
+    HandshakeFailed(HandshakeError),
+    /// Emitted by a `Pipe` after sending a patch. Contains the number of bytes transmitted,
+    /// i.e. after compression. Not sent by the frontend.
+    PatchTransmitted(usize),
 }
 
 /// Receiver trait for receiving `RxMsg` objects
@@ -63,49 +209,8 @@ pub trait Pipe {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use crate::testing::TestSender;
     use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
-    use futures::task::{Context, Poll};
-    use std::pin::Pin;
-
-    #[derive(Clone)]
-    pub(crate) struct DummySender(UnboundedSender<TxMsg>);
-
-    impl Sink<TxMsg> for DummySender {
-        type Error = Box<dyn Error>;
-
-        fn poll_ready(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<Result<(), Self::Error>> {
-            Pin::new(&mut self.0)
-                .poll_ready(cx)
-                .map_err(|x| Box::new(x).into())
-        }
-
-        fn start_send(mut self: Pin<&mut Self>, item: TxMsg) -> Result<(), Self::Error> {
-            Pin::new(&mut self.0)
-                .start_send(item)
-                .map_err(|x| Box::new(x).into())
-        }
-
-        fn poll_flush(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<Result<(), Self::Error>> {
-            Pin::new(&mut self.0)
-                .poll_flush(cx)
-                .map_err(|x| Box::new(x).into())
-        }
-
-        fn poll_close(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<Result<(), Self::Error>> {
-            Pin::new(&mut self.0)
-                .poll_close(cx)
-                .map_err(|x| Box::new(x).into())
-        }
-    }
 
     pub(crate) struct DummyPipe {
         sender_tx: UnboundedSender<TxMsg>,
@@ -135,13 +240,48 @@ pub(crate) mod tests {
     }
 
     impl Pipe for DummyPipe {
-        type Sender = DummySender;
+        type Sender = TestSender;
         type Receiver = Box<dyn Receiver>;
 
         fn split(self) -> (Self::Sender, Self::Receiver) {
-            let sender_tx = DummySender(self.sender_tx);
+            let sender_tx = TestSender(self.sender_tx);
             let receiver_rx: Box<dyn Receiver> = Box::new(self.receiver_rx);
             (sender_tx, receiver_rx)
         }
     }
+
+    #[test]
+    fn test_negotiate_handshake() {
+        let data = format!(
+            "{{\"version\": {}, \"capabilities\": [\"unknown\"]}}",
+            PROTOCOL_VERSION
+        );
+        let handshake = Handshake::negotiate(&data).unwrap();
+        assert_eq!(handshake.version, PROTOCOL_VERSION);
+        assert!(!handshake.supports("unknown"));
+
+        let data = "{\"version\": 0, \"capabilities\": []}";
+        assert_eq!(
+            Handshake::negotiate(data),
+            Err(HandshakeError::VersionMismatch {
+                backend: PROTOCOL_VERSION,
+                frontend: 0
+            })
+        );
+        let data = format!(
+            "{{\"version\": {}, \"capabilities\": [\"{}\"]}}",
+            PROTOCOL_VERSION, CAPABILITY_DEFLATE
+        );
+        assert!(!Handshake::negotiate(&data)
+            .unwrap()
+            .supports(CAPABILITY_DEFLATE));
+        let handshake = Handshake::negotiate_with(&data, &[CAPABILITY_DEFLATE]).unwrap();
+        assert!(handshake.supports(CAPABILITY_DEFLATE));
+
+        let data = "{\"FrameApplied\": [1, {\"decode_us\": 1, \"apply_us\": 2}]}";
+        assert!(matches!(
+            Handshake::negotiate(data),
+            Err(HandshakeError::Invalid(_))
+        ));
+    }
 }
@@ -1,4 +1,6 @@
 use cfg_if::cfg_if;
+use futures::channel::oneshot;
+use std::time::Duration;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
@@ -36,6 +38,19 @@ mod wasm {
         let wait_time_ms: i32 = wait_time_ms.try_into().unwrap();
         greenhorn_set_timeout(fun, wait_time_ms);
     }
+
+    /// Applies `fun` to all `items` and returns the results in order.
+    ///
+    /// There is no thread pool available on WebAssembly, hence the items are processed
+    /// sequentially on the current thread.
+    pub fn parallel_map<T, U, F>(items: Vec<T>, fun: F) -> Vec<U>
+    where
+        T: Send,
+        U: Send,
+        F: Fn(T) -> U + Send + Sync,
+    {
+        items.into_iter().map(fun).collect()
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -43,6 +58,7 @@ mod default {
     use async_std::task;
     use async_timer::Interval;
     use futures::Future;
+    use rayon::prelude::*;
 
     pub fn spawn<F, T>(future: F)
     where
@@ -72,12 +88,110 @@ mod default {
             fun();
         });
     }
+
+    /// Applies `fun` to all `items` on a thread pool and returns the results in order.
+    pub fn parallel_map<T, U, F>(items: Vec<T>, fun: F) -> Vec<U>
+    where
+        T: Send,
+        U: Send,
+        F: Fn(T) -> U + Send + Sync,
+    {
+        if items.len() <= 1 {
+            return items.into_iter().map(fun).collect();
+        }
+        items.into_par_iter().map(fun).collect()
+    }
 }
 
 cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
-        pub use wasm::{spawn, spawn_blocking, set_timeout};
+        pub use wasm::{spawn, spawn_blocking, set_timeout, parallel_map};
     } else {
-        pub use default::{spawn, spawn_blocking, set_timeout};
+        pub use default::{spawn, spawn_blocking, set_timeout, parallel_map};
+    }
+}
+
+/// Calls `fun` once `duration` has elapsed, or immediately if `duration` is zero.
+///
+/// Since `set_timeout()` accepts whole milliseconds, the duration is rounded up, such that
+/// `fun` is never called early.
+pub(crate) fn run_after<F: 'static + Send + FnOnce()>(duration: Duration, fun: F) {
+    if duration.as_nanos() == 0 {
+        fun();
+        return;
+    }
+    set_timeout(fun, ceil_millis(duration));
+}
+
+/// Resolves once `duration` has elapsed, or immediately if `duration` is zero.
+pub(crate) async fn sleep(duration: Duration) {
+    let (tx, rx) = oneshot::channel();
+    run_after(duration, move || {
+        let _ = tx.send(());
+    });
+    let _ = rx.await;
+}
+
+/// Returns the number of milliseconds of `duration`, rounded up.
+// `u128::div_ceil()` requires Rust 1.73
+#[allow(clippy::manual_div_ceil)]
+fn ceil_millis(duration: Duration) -> u64 {
+    ((duration.as_nanos() + 999_999) / 1_000_000) as u64
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_ceil_millis() {
+        assert_eq!(ceil_millis(Duration::from_millis(0)), 0);
+        assert_eq!(ceil_millis(Duration::from_micros(1)), 1);
+        assert_eq!(ceil_millis(Duration::from_micros(1500)), 2);
+        assert_eq!(ceil_millis(Duration::from_millis(16)), 16);
+        assert_eq!(ceil_millis(Duration::from_secs(2)), 2000);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let before = Instant::now();
+        async_std::task::block_on(sleep(Duration::from_micros(1500)));
+        assert!(before.elapsed() >= Duration::from_micros(1500));
+        async_std::task::block_on(sleep(Duration::from_millis(0)));
+    }
+
+    #[test]
+    fn test_parallel_map_order() {
+        let items: Vec<_> = (0..100).collect();
+        assert_eq!(
+            parallel_map(items.clone(), |x| x * 2),
+            items.iter().map(|x| x * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parallel_map_distributes_work() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        // each item waits for the other one to start, which only succeeds if they run concurrently
+        let started = AtomicUsize::new(0);
+        let concurrent = pool.install(|| {
+            parallel_map(vec![0, 1], |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while started.load(Ordering::SeqCst) < 2 {
+                    if Instant::now() > deadline {
+                        return false;
+                    }
+                    std::thread::yield_now();
+                }
+                true
+            })
+        });
+        assert_eq!(concurrent, vec![true, true]);
     }
 }
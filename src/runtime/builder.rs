@@ -0,0 +1,288 @@
+use crate::pipe::Pipe;
+use crate::runtime::history::History;
+use crate::runtime::metrics::MetricsSink;
+use crate::runtime::recording::Recorder;
+use crate::runtime::{Runtime, RuntimeControl, SendFailurePolicy};
+use crate::App;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::io::Write;
+use std::time::Duration;
+
+/// Wait time from an update() and a subsequent render
+/// Defines the maximum frame rate.
+const DEFAULT_RENDER_INTERVAL_MS: u64 = 30;
+
+/// applies in case a render is still in progress on the frontend
+/// but a render is scheduled in the runtime
+const RENDER_RETRY_INTERVAL_MS: u64 = 10;
+
+/// Number of times a render is deferred while the frontend has not yet applied the previous frames
+const DEFAULT_MAX_RENDER_RETRIES: u32 = 3;
+
+/// Number of frames which may be sent to the frontend before it has applied them
+const DEFAULT_MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+/// Maximum time to wait for services and tasks to terminate once the runtime quits
+const DEFAULT_SHUTDOWN_TIMEOUT_MS: u64 = 5000;
+
+/// Defines when a [`Runtime`](struct.Runtime.html) renders the application.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Schedule {
+    pub(crate) render_interval: Duration,
+    pub(crate) retry_interval: Duration,
+    pub(crate) max_retries: u32,
+    pub(crate) max_frames_in_flight: usize,
+    pub(crate) initial_delay: Duration,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            render_interval: Duration::from_millis(DEFAULT_RENDER_INTERVAL_MS),
+            retry_interval: Duration::from_millis(RENDER_RETRY_INTERVAL_MS),
+            max_retries: DEFAULT_MAX_RENDER_RETRIES,
+            max_frames_in_flight: DEFAULT_MAX_FRAMES_IN_FLIGHT,
+            initial_delay: Duration::from_millis(DEFAULT_RENDER_INTERVAL_MS),
+        }
+    }
+}
+
+/// Configures and creates a [`Runtime`](struct.Runtime.html).
+///
+/// `Runtime::new(app, pipe)` is a shorthand for `RuntimeBuilder::new(app, pipe).build()`,
+/// which uses the defaults documented on the individual methods.
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::prelude::*;
+/// # use greenhorn::RuntimeBuilder;
+/// # use std::time::Duration;
+/// # use std::net::SocketAddr;
+/// # use std::str::FromStr;
+/// #
+/// # struct MyApp;
+/// # impl Render for MyApp {
+/// #     type Message = ();
+/// #     fn render(&self) -> Node<Self::Message> {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// # impl App for MyApp {
+/// #     fn update(&mut self, msg: Self::Message, ctx: Context<Self::Message>) -> Updated {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// #
+/// # let addr = SocketAddr::from_str("127.0.0.1:1235").unwrap();
+/// let pipe = WebSocketPipe::listen_to_addr(addr);
+/// // render at most 5 times per second
+/// let (runtime, control) = RuntimeBuilder::new(MyApp, pipe)
+///     .render_interval(Duration::from_millis(200))
+///     .build();
+/// ```
+pub struct RuntimeBuilder<A: 'static + App, P: 'static + Pipe> {
+    pub(crate) app: A,
+    pub(crate) pipe: P,
+    pub(crate) schedule: Schedule,
+    pub(crate) metrics_sink: Option<Box<dyn MetricsSink>>,
+    pub(crate) shutdown_timeout: Duration,
+    pub(crate) send_failure_policy: SendFailurePolicy,
+    pub(crate) hydrate: bool,
+    pub(crate) verify_patches: bool,
+    pub(crate) recorder: Option<Recorder<A::Message>>,
+    pub(crate) replaying: bool,
+    pub(crate) history: Option<History<A>>,
+}
+
+impl<A: 'static + App, P: 'static + Pipe> RuntimeBuilder<A, P> {
+    /// Creates a new builder for a runtime executing the backend of the given application.
+    pub fn new(app: A, pipe: P) -> Self {
+        Self {
+            app,
+            pipe,
+            schedule: Default::default(),
+            metrics_sink: None,
+            shutdown_timeout: Duration::from_millis(DEFAULT_SHUTDOWN_TIMEOUT_MS),
+            send_failure_policy: Default::default(),
+            hydrate: false,
+            verify_patches: false,
+            recorder: None,
+            replaying: false,
+            history: None,
+        }
+    }
+
+    /// Sets the wait time between an update of the application and the subsequent render.
+    ///
+    /// Updates during this interval are coalesced into a single frame, thus the interval
+    /// defines the maximum frame rate. Defaults to 30ms.
+    pub fn render_interval(mut self, interval: Duration) -> Self {
+        self.schedule.render_interval = interval;
+        self
+    }
+
+    /// Sets the frame rate limit, which is a shorthand for `render_interval()`.
+    pub fn max_fps(self, fps: u32) -> Self {
+        assert!(fps > 0, "Frame rate must be positive");
+        self.render_interval(Duration::from_secs(1) / fps)
+    }
+
+    /// Configures how a render is deferred while the frontend has not yet applied
+    /// the previously sent frames.
+    ///
+    /// The render is retried at most `max_retries` times, waiting at least `interval` or the time
+    /// the frontend typically requires to apply a frame. Afterwards, the application is rendered
+    /// anyway. Since the frontend state is unknown at that point, the frames in flight are
+    /// discarded and the DOM is rendered from scratch. Defaults to 3 retries every 10ms.
+    pub fn retry_policy(mut self, interval: Duration, max_retries: u32) -> Self {
+        self.schedule.retry_interval = interval;
+        self.schedule.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the number of frames which may be sent to the frontend before it has applied them.
+    ///
+    /// Frames are diffed against the previously sent frame, regardless of whether the frontend
+    /// has already applied it. Once this limit is reached, rendering is deferred according to
+    /// the `retry_policy()`. Defaults to 3.
+    pub fn max_frames_in_flight(mut self, frames: usize) -> Self {
+        assert!(frames > 0, "At least one frame must be allowed in flight");
+        self.schedule.max_frames_in_flight = frames;
+        self
+    }
+
+    /// Sets the wait time before the application is rendered for the first time.
+    ///
+    /// This allows services started in `App::mount()` to update the application before the
+    /// first frame is rendered. Defaults to 30ms.
+    pub fn initial_render_delay(mut self, delay: Duration) -> Self {
+        self.schedule.initial_delay = delay;
+        self
+    }
+
+    /// Reports the collected metrics to `sink` each time a frame was rendered.
+    pub fn metrics_sink<S: 'static + MetricsSink>(mut self, sink: S) -> Self {
+        self.metrics_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Sets the maximum time to wait for the application to shut down once the runtime quits.
+    ///
+    /// During shutdown, services are asked to stop, futures and streams spawned by the application
+    /// are cancelled and pending patches are sent before the pipe is closed. `Runtime::run()`
+    /// resolves shortly after this timeout, even if some tasks have not terminated yet, e.g.
+    /// since they block their thread. Defaults to 5s.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Configures how the runtime reacts if a message cannot be sent to the frontend.
+    ///
+    /// Defaults to `SendFailurePolicy::Stop`.
+    pub fn send_failure_policy(mut self, policy: SendFailurePolicy) -> Self {
+        self.send_failure_policy = policy;
+        self
+    }
+
+    /// Allows the frontend to attach to server-rendered HTML, e.g. as generated by
+    /// [render_to_string()](../ssr/fn.render_to_string.html), instead of replacing it.
+    ///
+    /// Patches which render the DOM from scratch are marked for hydration. The frontend hydrates
+    /// only its first patch: it assigns element ids and installs event listeners on the existing
+    /// nodes. Nodes which do not match the rendered DOM are replaced.
+    pub fn hydrate(mut self) -> Self {
+        self.hydrate = true;
+        self
+    }
+
+    /// Appends a checksum of the expected DOM structure to each patch.
+    ///
+    /// The frontend verifies the checksum after applying a patch. If it does not match, the
+    /// failure is reported in the same way as a patch which could not be applied and the DOM is
+    /// rendered from scratch. Do not enable this if javascript of the application modifies the
+    /// structure of the DOM, e.g. by inserting nodes, since every patch would be rejected.
+    pub fn verify_patches(mut self) -> Self {
+        self.verify_patches = true;
+        self
+    }
+
+    /// Records the session to `writer`, such that it can be replayed later on.
+    ///
+    /// Refer to the [recording](recording/index.html) module for details.
+    pub fn record<W: 'static + Write + Send>(mut self, writer: W) -> Self
+    where
+        A::Message: Serialize,
+    {
+        self.recorder = Some(Recorder::new(writer, false));
+        self
+    }
+
+    /// Same as `record()`, but also records the emitted patches, such that a replay
+    /// detects if the application renders a different DOM.
+    pub fn record_with_patches<W: 'static + Write + Send>(mut self, writer: W) -> Self
+    where
+        A::Message: Serialize,
+    {
+        self.recorder = Some(Recorder::new(writer, true));
+        self
+    }
+
+    /// Keeps a history of the last `capacity` processed messages in debug builds,
+    /// which is shown in an overlay panel of the frontend.
+    ///
+    /// Has no effect in release builds. Refer to the [history](history/index.html) module for details.
+    pub fn history(mut self, capacity: usize) -> Self
+    where
+        A::Message: Debug,
+    {
+        if cfg!(debug_assertions) {
+            self.history = Some(History::new(capacity));
+        }
+        self
+    }
+
+    /// Same as `history()`, but also clones the application after each message,
+    /// such that previous states may be restored from the frontend.
+    pub fn history_with_snapshots(mut self, capacity: usize) -> Self
+    where
+        A: Clone,
+        A::Message: Debug,
+    {
+        if cfg!(debug_assertions) {
+            self.history = Some(History::new(capacity).with_snapshots());
+        }
+        self
+    }
+
+    /// Same as `history()`, but also serializes the application after each message,
+    /// such that previous states may be restored from the frontend.
+    pub fn history_with_serde_snapshots(mut self, capacity: usize) -> Self
+    where
+        A: Serialize + DeserializeOwned,
+        A::Message: Debug,
+    {
+        if cfg!(debug_assertions) {
+            self.history = Some(History::new(capacity).with_serde_snapshots());
+        }
+        self
+    }
+
+    /// Prepares the runtime for replaying a recording.
+    ///
+    /// Futures, streams and services are not executed since their results are part
+    /// of the recording. Also, the application is only rendered using `RuntimeControl::render()`.
+    pub(crate) fn replaying(mut self) -> Self {
+        self.replaying = true;
+        self
+    }
+
+    /// Creates the `Runtime` along with an associated control object, which allows changing
+    /// the state or to send messages to the application.
+    pub fn build(self) -> (Runtime<A, P>, RuntimeControl<A>) {
+        Runtime::from_builder(self)
+    }
+}
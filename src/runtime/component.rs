@@ -1,9 +1,10 @@
 use crate::component::{ComponentContainer, ComponentMap};
 use crate::listener::ListenerKey;
-use crate::runtime::metrics::Metrics;
-use crate::runtime::render::{render_component, ResultItem};
+use crate::runtime::render::{render_component, Memos, ResultItem};
 use crate::vdom::{Path, VNode};
 use crate::{App, Id};
+use instant::Instant;
+use std::time::Duration;
 
 /// Captures the rendered state of a component.
 ///
@@ -19,16 +20,24 @@ pub(crate) struct RenderedComponent<A: App> {
     children: Vec<(Id, Path)>,
     blobs: Vec<Id>,
     rpcs: Vec<Id>,
+    memos: Vec<u64>,
 }
 
 impl<A: App> RenderedComponent<A> {
+    /// Renders the component and converts the result into a VDOM.
+    ///
+    /// Also returns the non-DOM items of the render operation as well as the time spent
+    /// in `render()`. This does not require access to the runtime and may thus be called
+    /// on any thread. Memoized nodes found in `memos` are reused.
     pub(crate) fn new(
         comp: ComponentContainer<A::Message>,
-        metrics: &mut Metrics,
-    ) -> (Self, Vec<ResultItem<A>>) {
-        let dom = metrics.run_comp(comp.id(), || comp.render());
+        memos: Option<&Memos<A>>,
+    ) -> (Self, Vec<ResultItem<A>>, Duration) {
+        let before = Instant::now();
+        let dom = comp.render();
+        let delta = Instant::now().duration_since(before);
         let mut result = Vec::new();
-        let mut vdom = render_component(dom, &mut result);
+        let mut vdom = render_component(dom, &mut result, memos);
         if vdom.len() != 1 {
             panic!("The DOM of a component must be represented by exactly one DOM node");
         }
@@ -39,6 +48,7 @@ impl<A: App> RenderedComponent<A> {
         let mut children = Vec::with_capacity(result.len());
         let mut blobs = Vec::with_capacity(result.len());
         let mut rpcs = Vec::with_capacity(result.len());
+        let mut memo_keys = Vec::new();
 
         for item in &result {
             match item {
@@ -54,6 +64,7 @@ impl<A: App> RenderedComponent<A> {
                     blobs.push(blob.id());
                 }
                 ResultItem::Rpc(rpc) => rpcs.push(rpc.node_id),
+                ResultItem::Memo(key, _) => memo_keys.push(*key),
             }
         }
 
@@ -66,8 +77,10 @@ impl<A: App> RenderedComponent<A> {
                 children,
                 blobs,
                 rpcs,
+                memos: memo_keys,
             },
             result,
+            delta,
         )
     }
 
@@ -95,6 +108,10 @@ impl<A: App> RenderedComponent<A> {
         &self.blobs
     }
 
+    pub(crate) fn memos(&self) -> &Vec<u64> {
+        &self.memos
+    }
+
     pub(crate) fn component(&self) -> ComponentContainer<A::Message> {
         self.component.clone()
     }
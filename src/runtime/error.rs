@@ -0,0 +1,30 @@
+use crate::pipe::HandshakeError;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors which cause a [`Runtime`](struct.Runtime.html) to stop or which are
+/// returned by a [`RuntimeControl`](struct.RuntimeControl.html).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// A message could not be sent to the frontend.
+    ///
+    /// Contains the description of the error reported by the [`Pipe`](../pipe/trait.Pipe.html).
+    /// Only returned if the runtime was configured with [`SendFailurePolicy::Stop`](enum.SendFailurePolicy.html).
+    Send(String),
+    /// The runtime has already stopped and cannot be controlled anymore.
+    Stopped,
+    /// Frontend and backend do not agree on the protocol.
+    Handshake(HandshakeError),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Send(err) => write!(f, "Failed to send message to frontend: {}", err),
+            RuntimeError::Stopped => write!(f, "Runtime has stopped"),
+            RuntimeError::Handshake(err) => write!(f, "Handshake with frontend failed: {}", err),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
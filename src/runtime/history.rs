@@ -0,0 +1,319 @@
+//! This module implements the message history used for time-travel debugging.
+//!
+//! A [`Runtime`](../struct.Runtime.html) configured with `RuntimeBuilder::history()` keeps the most
+//! recently processed messages of the application in debug builds. Each entry shows the `Debug`
+//! output of the message along with the `Updated` result returned by `App::update()`. The history
+//! is sent to the frontend, which lists it in an overlay panel toggled by pressing `Ctrl+Shift+H`.
+//!
+//! Optionally, the runtime takes a snapshot of the application state after each message,
+//! either by cloning the application (`history_with_snapshots()`) or by serializing it
+//! (`history_with_serde_snapshots()`). Clicking an entry in the overlay panel then restores the
+//! state of the application at that point in time and renders it from scratch.
+//!
+//! Note that restoring a state neither re-runs `App::mount()` nor restarts services, futures or streams.
+//! Also, cloning an application does not copy the state of nested [`Components`](../../component/struct.Component.html),
+//! since clones share their state.
+//!
+
+use crate::component::Updated;
+use crate::App;
+use instant::Instant;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// A message processed by the application.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unique and increasing identifier of this entry
+    pub id: u64,
+    /// Milliseconds since the runtime was started
+    pub time_ms: u64,
+    /// `Debug` output of the message
+    pub message: String,
+    /// True if `App::update()` requested a re-render of the application
+    pub render: bool,
+    /// Number of components invalidated by `App::update()`
+    pub invalidated: usize,
+    /// True if the application state after processing the message may be restored
+    pub snapshot: bool,
+}
+
+/// Sent to the frontend whenever the history changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryUpdate {
+    /// Entries added since the last update
+    pub entries: Vec<HistoryEntry>,
+    /// Id of the oldest entry in the history. Older entries have been dropped.
+    pub first: u64,
+    /// Id of the entry whose state the application currently is in
+    pub current: Option<u64>,
+}
+
+/// Saved state of an application, which may be restored multiple times.
+trait Snapshot<A> {
+    fn restore(&self) -> Result<A, String>;
+}
+
+struct Cloned<A>(A);
+
+impl<A: Clone> Snapshot<A> for Cloned<A> {
+    fn restore(&self) -> Result<A, String> {
+        Ok(self.0.clone())
+    }
+}
+
+struct Serialized<A>(JsonValue, PhantomData<fn() -> A>);
+
+impl<A: DeserializeOwned> Snapshot<A> for Serialized<A> {
+    fn restore(&self) -> Result<A, String> {
+        serde_json::from_value(self.0.clone()).map_err(|err| err.to_string())
+    }
+}
+
+type BoxedSnapshot<A> = Box<dyn Snapshot<A>>;
+type TakeSnapshot<A> = fn(&A) -> Result<BoxedSnapshot<A>, String>;
+
+/// Bounded history of the messages processed by an application.
+pub(crate) struct History<A: App> {
+    capacity: usize,
+    entries: VecDeque<(HistoryEntry, Option<BoxedSnapshot<A>>)>,
+    next_id: u64,
+    current: Option<u64>,
+    describe: fn(&A::Message) -> String,
+    take_snapshot: Option<TakeSnapshot<A>>,
+    start: Instant,
+}
+
+impl<A: App> History<A> {
+    pub(crate) fn new(capacity: usize) -> Self
+    where
+        A::Message: Debug,
+    {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+            next_id: 1,
+            current: None,
+            describe: |msg| format!("{:?}", msg),
+            take_snapshot: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Takes snapshots by cloning the application.
+    pub(crate) fn with_snapshots(mut self) -> Self
+    where
+        A: 'static + Clone,
+    {
+        self.take_snapshot = Some(|app| Ok(Box::new(Cloned(app.clone()))));
+        self
+    }
+
+    /// Takes snapshots by serializing the application.
+    pub(crate) fn with_serde_snapshots(mut self) -> Self
+    where
+        A: 'static + Serialize + DeserializeOwned,
+    {
+        self.take_snapshot = Some(|app| {
+            serde_json::to_value(app)
+                .map(|value| Box::new(Serialized(value, PhantomData)) as BoxedSnapshot<A>)
+                .map_err(|err| err.to_string())
+        });
+        self
+    }
+
+    /// Returns the `Debug` output of a message before it is passed to `App::update()`.
+    pub(crate) fn describe(&self, msg: &A::Message) -> String {
+        (self.describe)(msg)
+    }
+
+    /// Adds a processed message to the history, dropping the oldest entry if the history is full.
+    ///
+    /// Returns the update to send to the frontend.
+    pub(crate) fn push(&mut self, message: String, updated: &Updated, app: &A) -> HistoryUpdate {
+        let snapshot = self.take_snapshot.and_then(|take| match take(app) {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) => {
+                log::error!("Failed to take snapshot of application state: {}", err);
+                None
+            }
+        });
+        let entry = HistoryEntry {
+            id: self.next_id,
+            time_ms: Instant::now().duration_since(self.start).as_millis() as u64,
+            message,
+            render: updated.should_render,
+            invalidated: updated.components_render.as_ref().map_or(0, |x| x.len()),
+            snapshot: snapshot.is_some(),
+        };
+        self.next_id += 1;
+        self.current = Some(entry.id);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((entry.clone(), snapshot));
+        HistoryUpdate {
+            entries: vec![entry],
+            first: self.first(),
+            current: self.current,
+        }
+    }
+
+    /// Restores the application state saved after processing the message of entry `id`.
+    ///
+    /// Returns the restored application along with the update to send to the frontend.
+    pub(crate) fn restore(&mut self, id: u64) -> Result<(A, HistoryUpdate), String> {
+        let snapshot = self
+            .entries
+            .iter()
+            .find(|(entry, _)| entry.id == id)
+            .ok_or_else(|| format!("No history entry with id {}", id))?
+            .1
+            .as_ref()
+            .ok_or_else(|| format!("No snapshot taken for history entry {}", id))?;
+        let app = snapshot.restore()?;
+        self.current = Some(id);
+        let update = HistoryUpdate {
+            entries: Vec::new(),
+            first: self.first(),
+            current: self.current,
+        };
+        Ok((app, update))
+    }
+
+    /// Returns an update containing the whole history, e.g. for a newly connected frontend.
+    pub(crate) fn sync(&self) -> HistoryUpdate {
+        HistoryUpdate {
+            entries: self
+                .entries
+                .iter()
+                .map(|(entry, _)| entry.clone())
+                .collect(),
+            first: self.first(),
+            current: self.current,
+        }
+    }
+
+    fn first(&self) -> u64 {
+        self.entries
+            .front()
+            .map_or(self.next_id, |(entry, _)| entry.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::node::Node;
+    use crate::pipe::{RxMsg, TxMsg};
+    use crate::testing::TestHarness;
+    use crate::Render;
+
+    #[derive(Debug)]
+    enum Msg {
+        Add(u32),
+    }
+
+    #[derive(Clone)]
+    struct Counter(u32);
+
+    impl Render for Counter {
+        type Message = Msg;
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html()
+                .elem("button")
+                .id("add")
+                .on("click", |_| Msg::Add(1))
+                .text(self.0.to_string())
+                .build()
+        }
+    }
+
+    impl App for Counter {
+        fn update(&mut self, msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            match msg {
+                Msg::Add(x) if x > 0 => {
+                    self.0 += x;
+                    Updated::yes()
+                }
+                Msg::Add(_) => Updated::no(),
+            }
+        }
+    }
+
+    fn take_updates(harness: &mut TestHarness<Counter>) -> Vec<HistoryUpdate> {
+        harness
+            .take_messages()
+            .into_iter()
+            .filter_map(|msg| match msg {
+                TxMsg::History(update) => Some(update),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut history = History::<Counter>::new(2);
+        let app = Counter(0);
+        history.push("a".into(), &Updated::yes(), &app);
+        history.push("b".into(), &Updated::no(), &app);
+        let update = history.push("c".into(), &Updated::yes(), &app);
+        assert_eq!(update.first, 2);
+        assert_eq!(update.current, Some(3));
+        assert_eq!(update.entries[0].message, "c");
+        assert!(!update.entries[0].snapshot);
+        let sync = history.sync();
+        let messages: Vec<_> = sync.entries.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(messages, vec!["b", "c"]);
+        assert!(!sync.entries[0].render);
+        assert!(history.restore(3).is_err());
+    }
+
+    #[test]
+    fn test_time_travel() {
+        let mut harness = TestHarness::new_with_runtime(
+            || Counter(0),
+            |runtime| runtime.history_with_snapshots(10),
+        );
+        harness.idle_blocking().unwrap();
+        harness.click(harness.find_by_id("add").unwrap());
+        // events and updates are received on different channels
+        harness.idle_blocking().unwrap();
+        harness.update(Msg::Add(0)).unwrap();
+        harness.update(Msg::Add(5)).unwrap();
+        harness.idle_blocking().unwrap();
+        assert_eq!(harness.html(), "<button id=\"add\" @click>6</button>");
+
+        let updates = take_updates(&mut harness);
+        let entries: Vec<_> = updates.iter().flat_map(|x| x.entries.clone()).collect();
+        let messages: Vec<_> = entries.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(messages, vec!["Add(1)", "Add(0)", "Add(5)"]);
+        assert_eq!(
+            entries.iter().map(|x| x.render).collect::<Vec<_>>(),
+            vec![true, false, true]
+        );
+        assert!(entries.iter().all(|x| x.snapshot));
+
+        // restore the state after the first click
+        harness.send(RxMsg::TimeTravel(entries[0].id));
+        harness.idle_blocking().unwrap();
+        assert_eq!(harness.html(), "<button id=\"add\" @click>1</button>");
+        let updates = take_updates(&mut harness);
+        assert_eq!(updates.last().unwrap().current, Some(entries[0].id));
+
+        // the history keeps growing from the restored state
+        harness.click(harness.find_by_id("add").unwrap());
+        harness.idle_blocking().unwrap();
+        assert_eq!(harness.html(), "<button id=\"add\" @click>2</button>");
+        let updates = take_updates(&mut harness);
+        assert_eq!(updates[0].entries[0].id, entries[2].id + 1);
+        harness.quit().unwrap();
+    }
+}
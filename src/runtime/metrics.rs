@@ -173,8 +173,12 @@ impl ResponseTime {
     }
 
     /// Record an execution time in the underlying histogram
+    ///
+    /// Execution times exceeding the supported range are recorded as 1s.
     pub fn record(&mut self, delta: Duration) {
-        self.hist.record(delta.as_micros() as u64).unwrap();
+        self.hist
+            .record((delta.as_micros() as u64).min(1e6 as u64))
+            .unwrap();
     }
 }
 
@@ -184,6 +188,36 @@ impl Default for ResponseTime {
     }
 }
 
+/// Collects the size of transmitted data.
+///
+/// The size is recorded in bytes. The supported range is from 1 byte to 1 GB.
+#[derive(Serialize)]
+pub struct ByteSize {
+    hist: Histogram,
+}
+
+impl ByteSize {
+    /// Create a new `ByteSize` object.
+    pub fn new() -> Self {
+        Self {
+            hist: Histogram::new_with_bounds(1, 1e9 as u64, 3).unwrap(),
+        }
+    }
+
+    /// Record a size in bytes in the underlying histogram
+    pub fn record(&mut self, size: usize) {
+        self.hist
+            .record((size as u64).clamp(1, 1e9 as u64))
+            .unwrap();
+    }
+}
+
+impl Default for ByteSize {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Collects `render()` performance information about a [`Component`](../../component/struct.Component.html).
 ///
 /// Records how often the a function of a `Component` is called and records the time
@@ -205,6 +239,12 @@ impl ComponentMetric {
         self.throughput.hit();
         ret
     }
+
+    /// Record the execution time of a call that was measured elsewhere.
+    pub fn record(&mut self, delta: Duration) {
+        self.time.record(delta);
+        self.throughput.hit();
+    }
 }
 
 /// Aggregation of metrics collected during execution of a [`Runtime`](../struct.Runtime.html) object.
@@ -224,6 +264,21 @@ pub struct Metrics {
     /// This condition might be avoided by correctly reporting whether a component should
     /// re-render using an [`Updated`](../../component/struct.Updated.html) object.
     pub empty_patch: ResponseTime,
+
+    /// Collects the size of serialized patches
+    pub patch_size: ByteSize,
+
+    /// Collects the number of bytes transmitted for each patch after compression
+    ///
+    /// Only recorded if the [`Pipe`](../../pipe/trait.Pipe.html) reports it using
+    /// `RxMsg::PatchTransmitted`, such as the [`WebSocketPipe`](../../websockets/struct.WebSocketPipe.html).
+    pub transmitted_patch_size: ByteSize,
+
+    /// Collects the time the frontend required to decode a patch, as reported by the frontend
+    pub frontend_decode: ResponseTime,
+
+    /// Collects the time the frontend required to apply a patch to the DOM, as reported by the frontend
+    pub frontend_apply: ResponseTime,
 }
 
 impl Metrics {
@@ -231,18 +286,12 @@ impl Metrics {
         Default::default()
     }
 
-    /// Run a function and record its execution time in the component with the associated `id`.
-    pub(crate) fn run_comp<T, F>(&mut self, id: Id, fun: F) -> T
-    where
-        F: FnOnce() -> T,
-    {
-        let metric = if let Some(metric) = self.components.get_mut(&id) {
-            metric
-        } else {
-            self.components.insert(id, ComponentMetric::new());
-            self.components.get_mut(&id).unwrap()
-        };
-        metric.run(fun)
+    /// Record the `render()` execution time of the component with the associated `id`.
+    ///
+    /// Components may be rendered on other threads, thus the time is measured there
+    /// and recorded once the results are merged.
+    pub(crate) fn record_comp(&mut self, id: Id, delta: Duration) {
+        self.components.entry(id).or_default().record(delta);
     }
 
     /// JSON serialize this object.
@@ -250,3 +299,19 @@ impl Metrics {
         serde_json::to_writer(out, self).map_err(|x| format!("{}", x))
     }
 }
+
+/// Receives the metrics of a running [`Runtime`](../struct.Runtime.html), e.g. to export them
+/// to a monitoring system.
+///
+/// Configured using [`RuntimeBuilder::metrics_sink()`](../struct.RuntimeBuilder.html#method.metrics_sink).
+/// Closures accepting a `&Metrics` implement this trait.
+pub trait MetricsSink: Send {
+    /// Called each time a frame was rendered with the metrics collected so far.
+    fn report(&mut self, metrics: &Metrics);
+}
+
+impl<F: FnMut(&Metrics) + Send> MetricsSink for F {
+    fn report(&mut self, metrics: &Metrics) {
+        self(metrics)
+    }
+}
@@ -1,35 +1,49 @@
 use crate::context::{Context, ContextMsg, ContextReceiver};
 use crate::dialog::DialogBinding;
 use crate::event::Emission;
-use crate::pipe::{Pipe, RxMsg, TxMsg};
-use crate::platform::{spawn, spawn_blocking};
-use crate::runtime::metrics::Metrics;
-pub(crate) use crate::runtime::render::RenderResult;
+use crate::pipe::{FrameTiming, Pipe, RxMsg, TxMsg};
+use crate::platform::{run_after, spawn, spawn_blocking};
+use crate::runtime::builder::Schedule;
+use crate::runtime::history::History;
+use crate::runtime::metrics::{Metrics, MetricsSink};
+use crate::runtime::pacing::FramePacer;
+use crate::runtime::recording::{Entry, Recorder};
+pub(crate) use crate::runtime::render::{render_component, RenderResult, ResultItem};
 use crate::runtime::service_runner::{ServiceCollection, ServiceMessage};
 pub(crate) use crate::runtime::state::Frame;
 use crate::runtime::state::RenderedState;
+use crate::runtime::task_set::{until, TaskSet};
 use crate::vdom::{patch_serialize, Differ, Patch};
 use crate::{App, Id};
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
 use futures::SinkExt;
-use futures::{select, FutureExt, StreamExt};
+use futures::{select, Future, FutureExt, StreamExt};
 use instant::Instant;
+use serde_json::Value as JsonValue;
 use std::collections::{HashSet, VecDeque};
+use std::fmt::Debug;
+use std::sync::Arc;
 use std::time::Duration;
 
+mod builder;
 mod component;
+mod error;
+pub mod history;
 pub mod metrics;
+mod pacing;
+pub mod recording;
 mod render;
 mod service_runner;
 mod state;
+mod task_set;
 
-/// Wait time from an update() and a subsequent render
-/// Defines the maximum frame rate.
-const DEFAULT_RENDER_INTERVAL_MS: u64 = 30;
+pub use builder::RuntimeBuilder;
+pub use error::RuntimeError;
 
-/// applies in case a render is still in progress on the frontend
-/// but a render is scheduled in the runtime
-const RENDER_RETRY_INTERVAL_MS: u64 = 10;
+/// Minimum time to wait for the pipe to close during shutdown, even if the shutdown timeout
+/// has already passed
+const PIPE_CLOSE_TIMEOUT_MS: u64 = 100;
 
 /// `RuntimeControl` objects are used to control a [`Runtime`](struct.Runtime.html),
 /// which in turn manages a user-defined application (implementing [`App`](../trait.App.html)).
@@ -41,14 +55,100 @@ pub struct RuntimeControl<A: App> {
 impl<A: App> RuntimeControl<A> {
     /// Quits the event loop of the [`Runtime`](struct.Runtime.html).
     ///
-    /// As a result, the `Runtime::run()` future resolves and the `Runtime::run_blocking()` returns.
-    pub fn quit(&self) {
-        self.tx.unbounded_send(RuntimeMsg::Quit).unwrap();
+    /// As a result, the application is shut down, after which the `Runtime::run()` future resolves
+    /// and the `Runtime::run_blocking()` returns.
+    /// Fails with `RuntimeError::Stopped` if the runtime has already stopped.
+    pub fn quit(&self) -> Result<(), RuntimeError> {
+        self.tx
+            .unbounded_send(RuntimeMsg::Quit)
+            .map_err(|_| RuntimeError::Stopped)
     }
 
     /// Sends a message into the update cycle of the application.
-    pub fn update(&self, msg: A::Message) {
-        self.tx.unbounded_send(RuntimeMsg::Update(msg)).unwrap();
+    /// Fails with `RuntimeError::Stopped` if the runtime has already stopped.
+    pub fn update(&self, msg: A::Message) -> Result<(), RuntimeError> {
+        self.tx
+            .unbounded_send(RuntimeMsg::Update(msg))
+            .map_err(|_| RuntimeError::Stopped)
+    }
+
+    /// Returns the rendering state of the runtime.
+    ///
+    /// Messages sent to the runtime before calling this function have been processed
+    /// once it resolves.
+    pub(crate) async fn status(&self) -> Result<RuntimeStatus, RuntimeError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .unbounded_send(RuntimeMsg::Status(tx))
+            .map_err(|_| RuntimeError::Stopped)?;
+        rx.await.map_err(|_| RuntimeError::Stopped)
+    }
+
+    /// Resolves once the rendering state of the runtime fulfills `condition`,
+    /// e.g. `RuntimeStatus::is_idle`.
+    ///
+    /// Messages sent to the runtime before calling this function have been processed
+    /// once the returned future resolves.
+    pub(crate) fn wait_for(
+        &self,
+        condition: fn(&RuntimeStatus) -> bool,
+    ) -> impl Future<Output = Result<(), RuntimeError>> {
+        let (tx, rx) = oneshot::channel();
+        let sent = self.tx.unbounded_send(RuntimeMsg::WaitFor(condition, tx));
+        async move {
+            sent.map_err(|_| RuntimeError::Stopped)?;
+            rx.await.map_err(|_| RuntimeError::Stopped)
+        }
+    }
+
+    /// Renders the application immediately, regardless of whether a render was scheduled.
+    pub(crate) fn render(&self) -> Result<(), RuntimeError> {
+        self.tx
+            .unbounded_send(RuntimeMsg::Render)
+            .map_err(|_| RuntimeError::Stopped)
+    }
+}
+
+/// Rendering state of a runtime as returned by `RuntimeControl::status()`.
+pub(crate) struct RuntimeStatus {
+    dirty: bool,
+    renders_in_progress: usize,
+    frames_in_flight: usize,
+}
+
+impl RuntimeStatus {
+    /// Returns true if the runtime has neither a render scheduled nor a frame waiting to be
+    /// applied by the frontend.
+    pub(crate) fn is_idle(&self) -> bool {
+        !self.dirty && self.renders_in_progress == 0 && self.frames_in_flight == 0
+    }
+
+    /// Returns true if no render is in progress, i.e. all rendered patches have been sent.
+    pub(crate) fn is_rendered(&self) -> bool {
+        self.renders_in_progress == 0
+    }
+}
+
+/// A `RuntimeControl::wait_for()` request which has not yet been fulfilled
+type Waiter = (fn(&RuntimeStatus) -> bool, oneshot::Sender<()>);
+
+/// Defines how a [`Runtime`](struct.Runtime.html) reacts if sending a message to the frontend fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SendFailurePolicy {
+    /// Stop the runtime, such that `Runtime::run()` resolves to `RuntimeError::Send`.
+    Stop,
+    /// Keep the application running without frontend.
+    ///
+    /// Rendering is suspended and further messages to the frontend are dropped
+    /// until a frontend (re-)connects.
+    Headless,
+}
+
+// `#[default]` on enum variants requires Rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for SendFailurePolicy {
+    fn default() -> Self {
+        SendFailurePolicy::Stop
     }
 }
 
@@ -56,9 +156,14 @@ impl<A: App> RuntimeControl<A> {
 enum RuntimeMsg<A: App> {
     Quit,
     Update(A::Message),
-    ApplyNextFrame(Frame<A>, Duration),
-    NextFrameRendering(Frame<A>, Duration),
+    ApplyNextFrame(Frame<A>, Duration, u64),
+    NextFrameRendering(Frame<A>, Duration, u64, u64, usize, Option<Vec<u8>>),
     AsyncMsg(A::Message),
+    SendFailed(String),
+    Headless,
+    Status(oneshot::Sender<RuntimeStatus>),
+    WaitFor(fn(&RuntimeStatus) -> bool, oneshot::Sender<()>),
+    Render,
 }
 
 /// The `Runtime` object manages the main application life-cycle as well as event distribution.
@@ -75,7 +180,10 @@ enum RuntimeMsg<A: App> {
 ///  * and the `run_blocking()` function
 ///
 /// Both functions resolve to a [`Metrics`](metrics/struct.Metrics.html) object which provides performance
-/// data of the executed application.
+/// data of the executed application. In case the runtime was stopped by an error,
+/// a [`RuntimeError`](enum.RuntimeError.html) is returned instead.
+/// The runtime is configured by creating it using a [`RuntimeBuilder`](struct.RuntimeBuilder.html),
+/// e.g. to set the frame rate or the behavior in case messages cannot be sent to the frontend.
 ///
 /// # Example
 ///
@@ -117,7 +225,7 @@ enum RuntimeMsg<A: App> {
 ///let addr = SocketAddr::from_str("127.0.0.1:1234").unwrap();
 ///let pipe =  WebSocketPipe::listen_to_addr(addr);
 ///let (runtime, control) = Runtime::new(app, pipe);
-///runtime.run_blocking();
+///runtime.run_blocking().unwrap();
 /// ```
 ///
 pub struct Runtime<A: 'static + App, P: 'static + Pipe> {
@@ -125,49 +233,102 @@ pub struct Runtime<A: 'static + App, P: 'static + Pipe> {
     rx: UnboundedReceiver<RuntimeMsg<A>>,
     app: A,
     sender: P::Sender,
-    receiver: P::Receiver,
+    receiver: Option<P::Receiver>,
     event_queue: VecDeque<Emission>,
     rendered: RenderedState<A>,
-    current_frame: Option<Frame<A>>,
-    next_frame: Option<Frame<A>>,
+    current_frame: Option<Arc<Frame<A>>>, // the most recently rendered frame
+    in_flight: VecDeque<(u64, Arc<Frame<A>>)>, // frames sent but not yet applied by the frontend
+    frame_number: u64,
+    applied_frame: u64,
+    discarded_frame: u64, // frames up to this number were discarded
     services: ServiceCollection<A::Message>,
+    tasks: TaskSet,
     render_tx: UnboundedSender<()>,
     render_rx: UnboundedReceiver<()>,
     invalidated_components: Option<HashSet<Id>>,
     root_invalidated: bool,
-    not_applied_counter: i32,
+    not_applied_counter: u32,
+    schedule: Schedule,
+    pacer: FramePacer,
+    metrics_sink: Option<Box<dyn MetricsSink>>,
     dirty: bool,
+    renders_in_progress: usize,
+    waiters: Vec<Waiter>,
     metrics: Metrics,
     dialogs: VecDeque<DialogBinding<A::Message>>,
+    loaded_css: Vec<String>,
+    connected: bool,
+    epoch: u64, // incremented whenever rendered frames are discarded due to a frontend reset
+    send_failure_policy: SendFailurePolicy,
+    headless: bool,
+    recorder: Option<Recorder<A::Message>>,
+    replaying: bool,
+    history: Option<History<A>>,
+    hydrate: bool,
+    verify_patches: bool,
+    shutdown_timeout: Duration,
+    quitting: bool,
 }
 
 impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
     /// Create a new `Runtime`, which allows executing the backend of the given application.
     /// Also returns an associated control object, which allows changing
     /// the state or to send messages to the application.
+    ///
+    /// Use [`RuntimeBuilder`](struct.RuntimeBuilder.html) to configure the runtime.
     pub fn new(app: A, pipe: P) -> (Runtime<A, P>, RuntimeControl<A>) {
+        RuntimeBuilder::new(app, pipe).build()
+    }
+
+    /// Returns a [`RuntimeBuilder`](struct.RuntimeBuilder.html) to configure a new `Runtime`.
+    pub fn builder(app: A, pipe: P) -> RuntimeBuilder<A, P> {
+        RuntimeBuilder::new(app, pipe)
+    }
+
+    fn from_builder(builder: RuntimeBuilder<A, P>) -> (Runtime<A, P>, RuntimeControl<A>) {
         let (tx, rx) = unbounded();
-        let (sender, receiver) = pipe.split();
+        let (sender, receiver) = builder.pipe.split();
         let (render_tx, render_rx) = unbounded();
         let runtime = Runtime {
             tx: tx.clone(),
             rx,
-            app,
+            app: builder.app,
             sender,
-            receiver,
+            receiver: Some(receiver),
             event_queue: VecDeque::new(),
             rendered: RenderedState::new(),
             services: ServiceCollection::new(),
+            tasks: TaskSet::new(),
             render_tx,
             render_rx,
             invalidated_components: Some(HashSet::new()),
-            next_frame: None,
+            in_flight: VecDeque::new(),
+            frame_number: 0,
+            applied_frame: 0,
+            discarded_frame: 0,
             dirty: false,
+            renders_in_progress: 0,
+            waiters: Vec::new(),
             root_invalidated: false,
             current_frame: None,
             not_applied_counter: 0,
+            schedule: builder.schedule,
+            pacer: Default::default(),
+            metrics_sink: builder.metrics_sink,
             metrics: Default::default(),
             dialogs: Default::default(),
+            loaded_css: Vec::new(),
+            connected: true,
+            epoch: 0,
+            send_failure_policy: builder.send_failure_policy,
+            headless: false,
+            recorder: builder.recorder,
+            replaying: builder.replaying,
+            history: builder.history,
+            hydrate: builder.hydrate,
+            verify_patches: builder.verify_patches,
+            shutdown_timeout: builder.shutdown_timeout,
+            quitting: false,
         };
         let control = RuntimeControl { tx };
         (runtime, control)
@@ -175,31 +336,50 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
 
     /// Async runs this application and returns the collected
     /// performance metrics upon completion.
-    pub async fn run(mut self) -> Metrics {
+    ///
+    /// Once the runtime quits or stops due to an error, the application is shut down before this
+    /// future resolves: `App::on_quit()` is called, services are stopped, futures and streams
+    /// spawned by the application are cancelled, the recording is completed and the pipe is
+    /// closed after pending patches have been sent. The shutdown is bounded by
+    /// `RuntimeBuilder::shutdown_timeout()`.
+    pub async fn run(mut self) -> Result<Metrics, RuntimeError> {
+        let result = self.event_loop().await;
+        self.shutdown().await;
+        result.map(|_| self.metrics)
+    }
+
+    /// Runs the event loop of the application until the runtime quits.
+    async fn event_loop(&mut self) -> Result<(), RuntimeError> {
         // schedule a first render, but wait a few milliseconds in case some
         // startup services decide to update the application state immediately.
-        self.schedule_render(DEFAULT_RENDER_INTERVAL_MS);
+        self.schedule_render(self.schedule.initial_delay);
         let (ctx, receiver) = Context::<A::Message>::new();
         self.app.mount(ctx);
-        self.handle_context_result(receiver).await;
+        self.handle_context_result(receiver).await?;
         loop {
             select! {
                 _ = self.render_rx.next().fuse() => {
                     self.dirty = false;
-                    self.render_dom()
+                    if self.render_dom() {
+                        self.record_entry(Entry::Render);
+                    }
                 },
-                msg = self.receiver.next().fuse() => {
+                msg = Self::next_frontend_msg(&mut self.receiver).fuse() => {
                     if let Some(msg) = msg {
-                        if !self.handle_frontend_msg(msg).await {
+                        if !self.handle_frontend_msg(msg).await? {
                             break;
                         }
+                    } else if self.send_failure_policy == SendFailurePolicy::Headless {
+                        log::warn!("Connection to frontend closed, continuing headless");
+                        self.receiver = None;
+                        self.go_headless();
                     } else {
                         break;
                     }
                 },
                 msg = self.rx.next().fuse() => {
                     if let Some(msg) = msg {
-                        if !self.handle_runtime_msg(msg).await {
+                        if !self.handle_runtime_msg(msg).await? {
                             break;
                         }
                     } else {
@@ -208,37 +388,170 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
                 },
                 msg = self.services.next().fuse() => {
                     if let Some(msg) = msg {
-                        self.handle_service_msg(msg).await;
+                        self.handle_service_msg(msg).await?;
                     }
                 }
             }
+            self.notify_waiters();
+        }
+        Ok(())
+    }
+
+    /// Returns the rendering state of the runtime.
+    fn status(&self) -> RuntimeStatus {
+        RuntimeStatus {
+            dirty: self.dirty,
+            renders_in_progress: self.renders_in_progress,
+            frames_in_flight: self.in_flight.len(),
+        }
+    }
+
+    /// Resolves the pending `RuntimeControl::wait_for()` requests whose condition is fulfilled.
+    fn notify_waiters(&mut self) {
+        if self.waiters.is_empty() {
+            return;
+        }
+        let status = self.status();
+        for (condition, waiter) in std::mem::take(&mut self.waiters) {
+            if condition(&status) {
+                let _ = waiter.send(());
+            } else {
+                self.waiters.push((condition, waiter));
+            }
+        }
+    }
+
+    /// Shuts down the application once the event loop has ended.
+    ///
+    /// All steps share the shutdown timeout. Cancellation is requested even if the timeout
+    /// has passed, such that tasks terminate once they are polled the next time. Likewise, the
+    /// pipe is closed in any case, such that the frontend is notified.
+    async fn shutdown(&mut self) {
+        self.quitting = true;
+        let (ctx, receiver) = Context::<A::Message>::new();
+        self.app.on_quit(ctx);
+        if let Err(err) = self.handle_context_result(receiver).await {
+            log::warn!("Failed to send messages emitted by App::on_quit(): {}", err);
+        }
+
+        let deadline = Instant::now() + self.shutdown_timeout;
+        self.services.stop_all();
+        let mut complete = until(deadline, self.services.stopped()).await;
+        // renders in progress are awaited, such that their patches are sent
+        self.tasks.cancel();
+        complete &= until(deadline, self.tasks.join()).await;
+        if let Some(recorder) = self.recorder.take() {
+            complete &= until(deadline, recorder.close()).await;
+        }
+        let sender = &mut self.sender;
+        let close_deadline =
+            deadline.max(Instant::now() + Duration::from_millis(PIPE_CLOSE_TIMEOUT_MS));
+        complete &= until(close_deadline, async move {
+            if let Err(err) = sender.close().await {
+                log::debug!("Failed to close pipe: {}", err);
+            }
+        })
+        .await;
+        if !complete {
+            log::warn!(
+                "Application did not shut down within {:?}",
+                self.shutdown_timeout
+            );
         }
-        self.metrics
     }
 
     /// Execute the application. This function blocks until the application exits.
     /// Returns the performance metrics collected during exeuction of the application.
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn run_blocking(self) -> Metrics {
+    pub fn run_blocking(self) -> Result<Metrics, RuntimeError> {
         async_std::task::block_on(self.run())
     }
 
+    /// Receives the next message from the frontend. Never resolves once the receiver was dropped.
+    async fn next_frontend_msg(receiver: &mut Option<P::Receiver>) -> Option<RxMsg> {
+        match receiver {
+            Some(receiver) => receiver.next().await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Writes an entry to the recording, if the session is recorded.
+    fn record_entry(&mut self, entry: Entry) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(entry);
+        }
+    }
+
+    /// Records a message of the application, if the session is recorded.
+    fn record_message(&mut self, msg: &A::Message, entry: fn(JsonValue) -> Entry) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.message(msg, entry);
+        }
+    }
+
+    /// Sends a message to the frontend.
+    ///
+    /// If sending fails, the configured `SendFailurePolicy` is applied.
+    async fn send(&mut self, msg: TxMsg) -> Result<(), RuntimeError> {
+        if self.headless {
+            return Ok(());
+        }
+        if let Err(err) = self.sender.send(msg).await {
+            return self.send_failed(err.to_string());
+        }
+        Ok(())
+    }
+
+    /// Applies the `SendFailurePolicy` after a message could not be sent to the frontend.
+    fn send_failed(&mut self, err: String) -> Result<(), RuntimeError> {
+        match self.send_failure_policy {
+            SendFailurePolicy::Stop => Err(RuntimeError::Send(err)),
+            SendFailurePolicy::Headless => {
+                log::warn!(
+                    "Failed to send message to frontend, continuing headless: {}",
+                    err
+                );
+                self.go_headless();
+                Ok(())
+            }
+        }
+    }
+
+    /// Stops sending messages to the frontend.
+    ///
+    /// The application is notified about the disconnect once the runtime processes
+    /// the scheduled `RuntimeMsg::Headless`.
+    fn go_headless(&mut self) {
+        if self.headless {
+            return;
+        }
+        self.headless = true;
+        let _ = self.tx.unbounded_send(RuntimeMsg::Headless);
+    }
+
     /// Handle messages as received from services
-    async fn handle_service_msg(&mut self, msg: ServiceMessage<A::Message>) {
+    async fn handle_service_msg(
+        &mut self,
+        msg: ServiceMessage<A::Message>,
+    ) -> Result<(), RuntimeError> {
         match msg {
-            ServiceMessage::Update(msg) => self.update(msg).await,
+            ServiceMessage::Update(msg) => {
+                self.record_message(&msg, Entry::Service);
+                self.update(msg).await?
+            }
             ServiceMessage::Tx(id, msg) => {
-                self.sender
-                    .send(TxMsg::Service(id.data(), msg))
-                    .await
-                    .unwrap();
+                self.send(TxMsg::Service(id.data(), msg)).await?;
             }
             ServiceMessage::Stopped(_) => {}
         }
+        Ok(())
     }
 
     /// Handles a message received from the frontend
-    async fn handle_frontend_msg(&mut self, msg: RxMsg) -> bool {
+    async fn handle_frontend_msg(&mut self, msg: RxMsg) -> Result<bool, RuntimeError> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frontend(&msg);
+        }
         match msg {
             RxMsg::Event(evt) => {
                 // search in listeners and get a message
@@ -249,18 +562,12 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
 
                 // inject the message back into the app
                 if let Some(msg) = msg {
-                    self.update(msg).await;
-                    self.process_events().await;
-                }
-            }
-            RxMsg::FrameApplied() => {
-                // a patch was applied by the frontend
-                // thus we swap the current state to the newly rendered frame
-                if let Some(frame) = self.next_frame.take() {
-                    self.rendered.apply(&frame);
-                    self.current_frame = Some(frame);
+                    self.update(msg).await?;
+                    self.process_events().await?;
                 }
             }
+            RxMsg::FrameApplied(frame, timing) => self.frame_applied(frame, &timing),
+            RxMsg::PatchFailed(frame, reason) => self.patch_failed(frame, &reason),
             RxMsg::Service(id, msg) => {
                 self.services.send(Id::new_from_data(id), msg);
             }
@@ -270,51 +577,213 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
                 let dialog = self.dialogs.pop_front().unwrap();
                 // panic if data was ill formated since that is a bug in the backend
                 let msg = dialog.resolve(data).unwrap();
-                self.update(msg).await;
-                self.process_events().await;
+                self.update(msg).await?;
+                self.process_events().await?;
                 if !self.dialogs.is_empty() {
                     // show next dialog
                     let data = self.dialogs.get(0).unwrap().serialize();
-                    self.sender.send(TxMsg::Dialog(data)).await.unwrap();
+                    self.send(TxMsg::Dialog(data)).await?;
                 }
             }
             RxMsg::ElementRpc(id, value) => {
                 let id = Id::new_from_data(id);
                 let msg = self.rendered.get_rpc(id).map(|rpc| rpc.call(value));
                 if let Some(msg) = msg {
-                    self.update(msg).await;
-                    self.process_events().await;
+                    self.update(msg).await?;
+                    self.process_events().await?;
                 }
             }
+            RxMsg::TimeTravel(id) => self.time_travel(id).await?,
+            RxMsg::Connected() => self.connect().await?,
+            RxMsg::Disconnected() => self.disconnect().await?,
+            RxMsg::HandshakeFailed(err) => return Err(RuntimeError::Handshake(err)),
+            RxMsg::PatchTransmitted(size) => self.metrics.transmitted_patch_size.record(size),
         };
-        true
+        Ok(true)
+    }
+
+    /// Handles the acknowledgement of a frame by the frontend.
+    ///
+    /// Since the frontend applies patches in order, all frames up to `frame` have been applied.
+    /// The state of the latest of them is used to dispatch messages from the frontend.
+    /// Also records the time the frontend required to process the patch, such that
+    /// subsequent renders are paced accordingly.
+    fn frame_applied(&mut self, frame: u64, timing: &FrameTiming) {
+        self.metrics.frontend_decode.record(timing.decode());
+        self.metrics.frontend_apply.record(timing.apply());
+        self.pacer.frame_applied(timing);
+        self.applied_frame = self.applied_frame.max(frame);
+        let mut applied = None;
+        while let Some((number, _)) = self.in_flight.front() {
+            if *number > frame {
+                break;
+            }
+            applied = self.in_flight.pop_front();
+        }
+        if let Some((_, frame)) = applied {
+            self.rendered.apply(&frame);
+        }
+    }
+
+    /// Handles a patch the frontend failed to apply.
+    ///
+    /// Since the DOM of the frontend has diverged from the rendered frames, they are discarded
+    /// along with renders in progress and the DOM is rendered from scratch. Failures of frames
+    /// which were already discarded are ignored, as these are followed by a full render anyway.
+    fn patch_failed(&mut self, frame: u64, reason: &str) {
+        if frame <= self.discarded_frame {
+            return;
+        }
+        log::warn!(
+            "Frontend failed to apply frame {}, rendering from scratch: {}",
+            frame,
+            reason
+        );
+        self.epoch += 1;
+        self.render_from_scratch();
     }
 
     /// Processes a message as received by the runtime control handle.
-    async fn handle_runtime_msg(&mut self, msg: RuntimeMsg<A>) -> bool {
+    async fn handle_runtime_msg(&mut self, msg: RuntimeMsg<A>) -> Result<bool, RuntimeError> {
+        if let RuntimeMsg::ApplyNextFrame(..) | RuntimeMsg::NextFrameRendering(..) = msg {
+            self.renders_in_progress -= 1;
+        }
         match msg {
             RuntimeMsg::Quit => {
-                return false;
+                return Ok(false);
             }
             RuntimeMsg::Update(msg) => {
-                self.update(msg).await;
+                self.record_message(&msg, Entry::Update);
+                self.update(msg).await?;
+            }
+            RuntimeMsg::ApplyNextFrame(_, _, epoch)
+            | RuntimeMsg::NextFrameRendering(_, _, epoch, _, _, _)
+                if epoch != self.epoch =>
+            {
+                // rendered for a frontend state which has been reset in the meantime
             }
-            RuntimeMsg::ApplyNextFrame(frame, duration) => {
-                self.next_frame = None;
-                self.rendered.apply(&frame);
+            RuntimeMsg::ApplyNextFrame(frame, duration, _) => {
+                let frame = Arc::new(frame);
+                if let Some((_, last)) = self.in_flight.back_mut() {
+                    // the DOM did not change, thus the frame is applied along with the last sent frame
+                    *last = frame.clone();
+                } else {
+                    self.rendered.apply(&frame);
+                }
                 self.current_frame = Some(frame);
                 self.metrics.empty_patch.record(duration);
+                self.report_metrics();
             }
-            RuntimeMsg::NextFrameRendering(frame, duration) => {
-                // schedule next frame
-                self.next_frame = Some(frame);
+            RuntimeMsg::NextFrameRendering(frame, duration, _, number, size, data) => {
+                if let Some(data) = data {
+                    self.record_entry(Entry::Patch(data));
+                }
+                let frame = Arc::new(frame);
+                if number <= self.applied_frame {
+                    // the patch was applied before `NextFrameRendering` was processed
+                    self.rendered.apply(&frame);
+                } else {
+                    self.in_flight.push_back((number, frame.clone()));
+                }
+                self.current_frame = Some(frame);
                 self.metrics.diff.record(duration);
+                self.metrics.patch_size.record(size);
+                self.report_metrics();
             }
             RuntimeMsg::AsyncMsg(msg) => {
-                self.update(msg).await;
+                self.record_message(&msg, Entry::Async);
+                self.update(msg).await?;
+            }
+            RuntimeMsg::SendFailed(err) => {
+                self.send_failed(err)?;
+            }
+            RuntimeMsg::Headless => {
+                if self.headless && self.connected {
+                    self.disconnect().await?;
+                }
+            }
+            RuntimeMsg::Status(tx) => {
+                let _ = tx.send(self.status());
+            }
+            RuntimeMsg::WaitFor(condition, tx) => {
+                self.waiters.push((condition, tx));
+            }
+            RuntimeMsg::Render => {
+                self.dirty = false;
+                self.render();
             }
         }
-        true
+        Ok(true)
+    }
+
+    /// Re-synchronizes a newly connected frontend with the current application state.
+    ///
+    /// Since the frontend state is unknown, the previously loaded css and pending dialogs
+    /// are sent again and the DOM is rendered from scratch.
+    async fn connect(&mut self) -> Result<(), RuntimeError> {
+        self.connected = true;
+        self.headless = false;
+        self.epoch += 1;
+        for css in self.loaded_css.clone() {
+            self.send(TxMsg::LoadCss(css)).await?;
+        }
+        if let Some(dialog) = self.dialogs.front() {
+            let data = dialog.serialize();
+            self.send(TxMsg::Dialog(data)).await?;
+        }
+        if let Some(history) = &self.history {
+            let update = history.sync();
+            self.send(TxMsg::History(update)).await?;
+        }
+        let (ctx, receiver) = Context::<A::Message>::new();
+        self.app.on_connect(ctx);
+        self.handle_context_result(receiver).await?;
+        self.render_from_scratch();
+        Ok(())
+    }
+
+    /// Restores the application state of a history entry and renders it from scratch.
+    async fn time_travel(&mut self, id: u64) -> Result<(), RuntimeError> {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        match history.restore(id) {
+            Ok((app, update)) => {
+                self.app = app;
+                self.send(TxMsg::History(update)).await?;
+                // discard frames rendered from the previous state
+                self.epoch += 1;
+                self.render_from_scratch();
+            }
+            Err(err) => log::error!("Failed to restore application state: {}", err),
+        }
+        Ok(())
+    }
+
+    /// Renders the whole DOM instead of diffing it against the current frame.
+    fn render_from_scratch(&mut self) {
+        self.not_applied_counter = 0;
+        self.discard_frames();
+        self.render_dom();
+    }
+
+    /// Discards all rendered frames, such that the next render emits the whole DOM.
+    ///
+    /// Acknowledgements of discarded frames are ignored.
+    fn discard_frames(&mut self) {
+        self.discarded_frame = self.frame_number;
+        self.current_frame = None;
+        self.in_flight.clear();
+        self.root_invalidated = true;
+    }
+
+    /// Marks the frontend as disconnected. Rendering is suspended until a frontend reconnects.
+    async fn disconnect(&mut self) -> Result<(), RuntimeError> {
+        self.connected = false;
+        let (ctx, receiver) = Context::<A::Message>::new();
+        self.app.on_disconnect(ctx);
+        self.handle_context_result(receiver).await
     }
 
     /// Schedules a render of the application.
@@ -324,43 +793,64 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
     /// runtime sets a flag and starts a timer. Once this timer has expired, the rendering happens.
     ///
     /// This debouncing is used to limit the maximum frame rate and thus maximum CPU usage
-    /// of the application.
-    fn schedule_render(&mut self, wait_time: u64) {
+    /// of the application. Use `schedule_update_render()` to schedule a render after an update.
+    fn schedule_render(&mut self, wait_time: Duration) {
         if self.dirty {
             return;
         }
-        let render_tx = self.render_tx.clone();
-        crate::platform::set_timeout(
-            move || {
-                let _ = render_tx.unbounded_send(());
-            },
-            wait_time,
-        );
+        if self.replaying {
+            // renders are triggered by the replay
+            self.dirty = true;
+            return;
+        }
         self.dirty = true;
+        let render_tx = self.render_tx.clone();
+        run_after(wait_time, move || {
+            let _ = render_tx.unbounded_send(());
+        });
+    }
+
+    /// Schedules a render after the application was updated.
+    ///
+    /// The wait time adapts to the frontend: if it is slower than the configured render interval,
+    /// more updates are coalesced into a frame. If no frame is in flight and the last frame was
+    /// rendered long enough ago, the application is rendered immediately.
+    fn schedule_update_render(&mut self) {
+        let idle = self.in_flight.is_empty() && self.renders_in_progress == 0;
+        let delay = self.pacer.render_delay(self.schedule.render_interval, idle);
+        self.schedule_render(delay);
     }
 
     /// Inserts a message into the update loop of the application.
-    async fn update(&mut self, msg: A::Message) {
+    async fn update(&mut self, msg: A::Message) -> Result<(), RuntimeError> {
         let (ctx, receiver) = Context::<A::Message>::new();
+        let message = self.history.as_ref().map(|history| history.describe(&msg));
         let updated = self.app.update(msg, ctx);
+        if let (Some(history), Some(message)) = (&mut self.history, message) {
+            let update = history.push(message, &updated, &self.app);
+            self.send(TxMsg::History(update)).await?;
+        }
         if updated.should_render {
             self.root_invalidated = true;
-            self.schedule_render(DEFAULT_RENDER_INTERVAL_MS);
+            self.schedule_update_render();
         }
         if let Some(invalidated) = updated.components_render {
             let invalidated_components = self.invalidated_components.as_mut().unwrap();
             invalidated.iter().for_each(|x| {
                 invalidated_components.insert(*x);
             });
-            self.schedule_render(DEFAULT_RENDER_INTERVAL_MS);
+            self.schedule_update_render();
         }
-        self.handle_context_result(receiver).await;
+        self.handle_context_result(receiver).await
     }
 
     /// Handles the result of calling a function with a `Context`. The `Context` may be used by
     /// components to interface with the current application state and to execute global services
     /// such as dialogs, running futures, streams, ...
-    async fn handle_context_result(&mut self, receiver: ContextReceiver<A::Message>) {
+    async fn handle_context_result(
+        &mut self,
+        receiver: ContextReceiver<A::Message>,
+    ) -> Result<(), RuntimeError> {
         // TODO: refactor ContextReceiver to Vec<> since not async anymore
         while let Ok(cmd) = receiver.rx.try_recv() {
             match cmd {
@@ -368,45 +858,54 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
                     self.event_queue.push_back(e);
                 }
                 ContextMsg::LoadCss(css) => {
-                    self.sender.send(TxMsg::LoadCss(css)).await.unwrap();
+                    self.loaded_css.push(css.clone());
+                    self.send(TxMsg::LoadCss(css)).await?;
                 }
                 ContextMsg::RunJs(js) => {
-                    self.sender.send(TxMsg::RunJs(js)).await.unwrap();
+                    self.send(TxMsg::RunJs(js)).await?;
                 }
                 ContextMsg::Propagate(prop) => {
-                    self.sender.send(TxMsg::Propagate(prop)).await.unwrap();
+                    self.send(TxMsg::Propagate(prop)).await?;
+                }
+                ContextMsg::Subscription(_) | ContextMsg::Future(_, _) | ContextMsg::Stream(_)
+                    if self.replaying =>
+                {
+                    // results are fed by the replay
+                }
+                ContextMsg::Subscription(_) | ContextMsg::Future(_, _) | ContextMsg::Stream(_)
+                    if self.quitting =>
+                {
+                    // results could not be processed anymore
                 }
                 ContextMsg::Subscription(service) => {
-                    self.services.spawn(service);
+                    self.services.spawn(service, &self.tasks);
                 }
                 ContextMsg::Future(fut, blocking) => {
                     let tx = self.tx.clone();
+                    let task = self.tasks.cancellable(async move {
+                        let result = fut.await;
+                        let _ = tx.unbounded_send(RuntimeMsg::AsyncMsg(result));
+                    });
                     if blocking {
-                        spawn_blocking(async move {
-                            let result = fut.await;
-                            let _ = tx.unbounded_send(RuntimeMsg::AsyncMsg(result));
-                        });
+                        spawn_blocking(task);
                     } else {
-                        spawn(async move {
-                            let result = fut.await;
-                            let _ = tx.unbounded_send(RuntimeMsg::AsyncMsg(result));
-                        });
+                        spawn(task);
                     }
                 }
                 ContextMsg::Stream(mut stream) => {
                     let tx = self.tx.clone();
-                    spawn(async move {
+                    spawn(self.tasks.cancellable(async move {
                         while let Some(value) = stream.next().await {
-                            let _ = tx.unbounded_send(RuntimeMsg::AsyncMsg(value));
+                            if tx.unbounded_send(RuntimeMsg::AsyncMsg(value)).is_err() {
+                                // the runtime has stopped
+                                break;
+                            }
                         }
-                    });
+                    }));
                 }
                 ContextMsg::Dialog(dialog) => {
                     if self.dialogs.is_empty() {
-                        self.sender
-                            .send(TxMsg::Dialog(dialog.serialize()))
-                            .await
-                            .unwrap();
+                        self.send(TxMsg::Dialog(dialog.serialize())).await?;
                     }
                     self.dialogs.push_back(dialog);
                 }
@@ -415,37 +914,69 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
                 }
             }
         }
+        Ok(())
     }
 
     /// Processes all events in the event queue, retrieves subscriptions and updates the app-state
     /// accordingly.
-    async fn process_events(&mut self) {
+    async fn process_events(&mut self) -> Result<(), RuntimeError> {
         while let Some(evt) = self.event_queue.pop_front() {
             let msg = self
                 .rendered
                 .get_subscription(evt.event_id)
                 .map(|subs| subs.call(evt.data));
             if let Some(msg) = msg {
-                self.update(msg).await;
+                self.update(msg).await?;
             }
         }
+        Ok(())
     }
 
     /// This function manages rendering and DOM diffing. Its invocation may be scheduled by calling
     /// `self.schedule_render()`.
     ///
-    /// In case the a render was not yet processed by the frontend, this function delays the rendering
-    /// operation several time to avoid overloading the frontend process.
-    fn render_dom(&mut self) {
-        if self.next_frame.is_some() && self.current_frame.is_none() && self.not_applied_counter < 3
-        {
-            self.not_applied_counter += 1;
+    /// Frames are diffed against the previously rendered frame, thus a render is delayed while
+    /// the previous frame is still being diffed.
+    /// In case the frontend has not yet applied `max_frames_in_flight` frames, this function delays
+    /// the rendering operation several times to avoid overloading the frontend process.
+    /// If the frontend does not catch up, the frames in flight are discarded and the DOM
+    /// is rendered from scratch.
+    /// Returns true if the application was rendered.
+    fn render_dom(&mut self) -> bool {
+        if !self.connected {
+            // the frontend is re-synchronized from scratch once it reconnects
+            return false;
+        }
+        if self.renders_in_progress > 0 {
             self.dirty = false;
-            self.schedule_render(RENDER_RETRY_INTERVAL_MS);
-            return;
+            self.schedule_render(self.schedule.retry_interval);
+            return false;
+        }
+        if self.in_flight.len() >= self.schedule.max_frames_in_flight {
+            if self.not_applied_counter < self.schedule.max_retries {
+                self.not_applied_counter += 1;
+                self.dirty = false;
+                self.schedule_render(self.pacer.retry_delay(self.schedule.retry_interval));
+                return false;
+            }
+            self.discard_frames();
         }
+        self.render();
+        true
+    }
+
+    /// Passes the collected metrics to the metrics sink, if configured.
+    fn report_metrics(&mut self) {
+        if let Some(sink) = &mut self.metrics_sink {
+            sink.report(&self.metrics);
+        }
+    }
+
+    /// Renders the application, diffs it against the current frame and sends the patch to the frontend.
+    fn render(&mut self) {
         self.not_applied_counter = 0;
-        let old_frame = self.current_frame.take();
+        self.pacer.rendered();
+        let old_frame = self.current_frame.clone();
 
         let metrics = &mut self.metrics;
         let app = &mut self.app;
@@ -455,41 +986,64 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
         self.invalidated_components = Some(HashSet::new());
 
         let result = if self.root_invalidated {
-            RenderResult::new_from_root(dom, &updated, &mut self.metrics)
+            RenderResult::new_from_root(dom, old_frame.as_deref(), &updated, &mut self.metrics)
         } else if let Some(old_frame) = &old_frame {
             RenderResult::new_from_frame(old_frame, &updated, &mut self.metrics)
         } else {
-            RenderResult::new_from_root(dom, &updated, &mut self.metrics)
+            RenderResult::new_from_root(dom, None, &updated, &mut self.metrics)
         };
         self.root_invalidated = false;
         self.dirty = false;
         let tx = self.tx.clone();
         let mut sender = self.sender.clone();
+        let epoch = self.epoch;
+        let record_patch = matches!(&self.recorder, Some(x) if x.records_patches());
+        let hydrate = self.hydrate;
+        let verify_patches = self.verify_patches;
+        self.frame_number += 1;
+        let number = self.frame_number;
+        self.renders_in_progress += 1;
 
-        spawn_blocking(async move {
+        spawn_blocking(self.tasks.tracked(async move {
             // create a patch
             let before = Instant::now();
-            let patch = if let Some(old_frame) = &old_frame {
-                Differ::new(&old_frame, &result).diff()
+            let mut patch = if let Some(old_frame) = &old_frame {
+                Differ::new(old_frame, &result).diff()
+            } else if hydrate {
+                Patch::new_for_hydration(&result)
             } else {
                 Patch::new_from_dom(&result)
             };
+            patch.frame = number;
+            if verify_patches && !patch.is_empty() {
+                patch.push_checksum(&result);
+            }
             let after = Instant::now();
             let delta = after.duration_since(before);
 
             if patch.is_empty() {
                 let translations = patch.translations;
                 let frame = Frame::new(result, translations);
-                let _ = tx.unbounded_send(RuntimeMsg::ApplyNextFrame(frame, delta));
+                let _ = tx.unbounded_send(RuntimeMsg::ApplyNextFrame(frame, delta, epoch));
             } else {
                 let serialized = patch_serialize(&result, &patch);
                 let translations = patch.translations;
                 let frame = Frame::new(result, translations);
-                let _ = tx.unbounded_send(RuntimeMsg::NextFrameRendering(frame, delta));
+                let size = serialized.len();
+                let recorded = if record_patch {
+                    Some(serialized.clone())
+                } else {
+                    None
+                };
+                let _ = tx.unbounded_send(RuntimeMsg::NextFrameRendering(
+                    frame, delta, epoch, number, size, recorded,
+                ));
                 // serialize the patch and send it to the client
-                sender.send(TxMsg::Patch(serialized)).await.unwrap();
+                if let Err(err) = sender.send(TxMsg::Patch(serialized)).await {
+                    let _ = tx.unbounded_send(RuntimeMsg::SendFailed(err.to_string()));
+                }
             }
-        });
+        }));
     }
 }
 
@@ -497,12 +1051,19 @@ impl<A: 'static + App, P: 'static + Pipe> Runtime<A, P> {
 mod tests {
     use super::*;
     use crate::node::Node;
+    use crate::patch::{DecodedPatch, Document, Instruction};
     use crate::pipe::tests::DummyPipe;
-    use crate::vdom::Attr;
-    use crate::vdom::{Patch, PatchItem, VElement, VNode};
+    use crate::service::{Mailbox, RxServiceMessage, Service};
+    use crate::vdom::VNode;
+    use crate::Component;
     use crate::{Render, Updated};
+    use assert_matches::assert_matches;
     use async_std::task::{block_on, spawn_blocking};
+    use futures::future::pending;
     use futures::stream::StreamExt;
+    use std::ops::Deref;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     struct DummyComponent(u32);
     impl Render for DummyComponent {
@@ -524,13 +1085,24 @@ mod tests {
         }
     }
 
-    fn make_patch(patch: Vec<PatchItem>) -> Vec<u8> {
-        let patch = Patch {
-            items: patch,
-            translations: Default::default(),
-        };
-        let render_result = RenderResult::<DummyComponent>::new_empty();
-        patch_serialize(&render_result, &patch)
+    /// Decodes a patch and applies it to `document`
+    fn apply(document: &mut Document, data: &[u8]) -> DecodedPatch {
+        let patch = DecodedPatch::decode(data).unwrap();
+        document.apply(&patch).unwrap();
+        patch
+    }
+
+    /// Waits until the runtime is idle. Since messages of the frontend and status requests
+    /// are received on different channels, the runtime may report its status before it has
+    /// processed previously sent acknowledgements.
+    fn wait_idle<A: App>(control: &RuntimeControl<A>) -> bool {
+        (0..100).any(|_| {
+            if block_on(control.status()).unwrap().is_idle() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            false
+        })
     }
 
     #[test]
@@ -540,21 +1112,32 @@ mod tests {
         let (rt, _control) = Runtime::new(app, pipe);
         let handle = spawn_blocking(move || match block_on(frontend.sender_rx.next()) {
             Some(TxMsg::Patch(msg)) => {
-                let elem = VNode::element(VElement {
-                    id: Id::new_empty(),
-                    tag: "div".to_string(),
-                    attr: vec![Attr::new("id", "html-id")],
-                    js_events: vec![],
-                    events: vec![],
-                    children: vec![VNode::Text("1".to_string())],
-                    namespace: None,
-                });
-                let serialized = make_patch(vec![PatchItem::Replace(&elem)]);
-                assert_eq!(serialized, msg);
+                let mut document = Document::new();
+                let patch = apply(&mut document, &msg);
+                assert_matches!(patch.instructions.as_slice(), [Instruction::Replace(_)]);
+                assert_eq!(document.to_string(), "<div id=\"html-id\">1</div>");
+            }
+            _ => panic!(),
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+    }
+
+    #[test]
+    fn test_hydrate_first_render() {
+        let app = DummyComponent(1);
+        let (pipe, mut frontend) = DummyPipe::new();
+        let (rt, _control) = Runtime::builder(app, pipe).hydrate().build();
+        let handle = spawn_blocking(move || match block_on(frontend.sender_rx.next()) {
+            Some(TxMsg::Patch(msg)) => {
+                let mut document = Document::new();
+                let patch = apply(&mut document, &msg);
+                assert_matches!(patch.instructions.as_slice(), [Instruction::Hydrate(_)]);
+                assert_eq!(document.to_string(), "<div id=\"html-id\">1</div>");
             }
             _ => panic!(),
         });
-        rt.run_blocking();
+        rt.run_blocking().unwrap();
         block_on(handle);
     }
 
@@ -564,21 +1147,30 @@ mod tests {
         let (pipe, mut frontend) = DummyPipe::new();
         let (rt, control) = Runtime::new(app, pipe);
         let handle = spawn_blocking(move || {
-            let _ = block_on(frontend.sender_rx.next()).unwrap();
-            control.update(());
-            block_on(frontend.receiver_tx.send(RxMsg::FrameApplied())).unwrap();
+            let mut document = Document::new();
+            match block_on(frontend.sender_rx.next()) {
+                Some(TxMsg::Patch(msg)) => apply(&mut document, &msg),
+                _ => panic!(),
+            };
+            control.update(()).unwrap();
+            block_on(
+                frontend
+                    .receiver_tx
+                    .send(RxMsg::FrameApplied(1, Default::default())),
+            )
+            .unwrap();
             let msg2 = block_on(frontend.sender_rx.next());
             match msg2 {
                 Some(TxMsg::Patch(msg)) => {
-                    let new_text = "2";
-                    let serialized =
-                        make_patch(vec![PatchItem::Descend(), PatchItem::ChangeText(&new_text)]);
-                    assert_eq!(serialized, msg);
+                    let patch = apply(&mut document, &msg);
+                    let expected = vec![Instruction::Descend, Instruction::ChangeText("2".into())];
+                    assert_eq!(patch.instructions, expected);
+                    assert_eq!(document.to_string(), "<div id=\"html-id\">2</div>");
                 }
                 _ => panic!(),
             }
         });
-        rt.run_blocking();
+        rt.run_blocking().unwrap();
         block_on(handle);
     }
 
@@ -586,34 +1178,528 @@ mod tests {
     fn test_rerender_if_timeout() {
         let app = DummyComponent(1);
         let (pipe, mut frontend) = DummyPipe::new();
-        let (rt, control) = Runtime::new(app, pipe);
+        let (rt, control) = Runtime::builder(app, pipe).max_frames_in_flight(1).build();
         let handle = spawn_blocking(move || {
             let _ = block_on(frontend.sender_rx.next()).unwrap();
-            control.update(());
+            control.update(()).unwrap();
             // don't do this now
-            // task::block_on(frontend.receiver_tx.send(RxMsg::FrameApplied())).unwrap();
+            // task::block_on(frontend.receiver_tx.send(RxMsg::FrameApplied(1, Default::default()))).unwrap();
             let msg2 = block_on(frontend.sender_rx.next());
 
-            let elem = VNode::element(VElement {
-                id: Id::new_empty(),
-                tag: "div".to_string(),
-                attr: vec![Attr::new("id", "html-id")],
-                js_events: vec![],
-                events: vec![],
-                children: vec![VNode::Text("2".to_string())],
-                namespace: None,
-            });
-            let serialized = make_patch(vec![PatchItem::Replace(&elem)]);
-
+            // the frame was never applied, thus the whole DOM is replaced
             match msg2 {
                 Some(TxMsg::Patch(msg)) => {
-                    assert_eq!(msg, serialized);
+                    let mut document = Document::new();
+                    let patch = apply(&mut document, &msg);
+                    assert_matches!(patch.instructions.as_slice(), [Instruction::Replace(_)]);
+                    assert_eq!(document.to_string(), "<div id=\"html-id\">2</div>");
+                }
+                _ => panic!(),
+            }
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+    }
+
+    #[test]
+    fn test_render_without_retry() {
+        let app = DummyComponent(1);
+        let (pipe, mut frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::builder(app, pipe)
+            .render_interval(Duration::from_millis(1))
+            .initial_render_delay(Duration::from_millis(0))
+            .retry_policy(Duration::from_secs(3600), 0)
+            .max_frames_in_flight(1)
+            .build();
+        let handle = spawn_blocking(move || {
+            let _ = block_on(frontend.sender_rx.next()).unwrap();
+            control.update(()).unwrap();
+            // the frame is rendered from scratch without waiting for the previous one to be applied
+            match block_on(frontend.sender_rx.next()) {
+                Some(TxMsg::Patch(msg)) => {
+                    let mut document = Document::new();
+                    let patch = apply(&mut document, &msg);
+                    assert_matches!(patch.instructions.as_slice(), [Instruction::Replace(_)]);
+                    assert_eq!(document.to_string(), "<div id=\"html-id\">2</div>");
+                }
+                _ => panic!(),
+            }
+            control.quit().unwrap();
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+    }
+
+    #[test]
+    fn test_pipelined_frames() {
+        let app = DummyComponent(1);
+        let (pipe, mut frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::new(app, pipe);
+        let handle = spawn_blocking(move || {
+            let mut document = Document::new();
+            let mut next_patch = || match block_on(frontend.sender_rx.next()) {
+                Some(TxMsg::Patch(msg)) => apply(&mut document, &msg),
+                _ => panic!(),
+            };
+            let first = next_patch();
+            // the second frame is diffed against the first one before it was acknowledged
+            control.update(()).unwrap();
+            let second = next_patch();
+            let expected = vec![Instruction::Descend, Instruction::ChangeText("2".into())];
+            assert_eq!(second.instructions, expected);
+            assert!(second.frame > first.frame);
+
+            // a late acknowledgement of the first frame leaves the second one in flight
+            let applied = |frame| RxMsg::FrameApplied(frame, Default::default());
+            block_on(frontend.receiver_tx.send(applied(first.frame))).unwrap();
+            block_on(control.wait_for(|status| status.frames_in_flight < 2)).unwrap();
+            assert_eq!(block_on(control.status()).unwrap().frames_in_flight, 1);
+            block_on(frontend.receiver_tx.send(applied(second.frame))).unwrap();
+            assert!(wait_idle(&control));
+            assert_eq!(document.to_string(), "<div id=\"html-id\">2</div>");
+            control.quit().unwrap();
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+    }
+
+    #[test]
+    fn test_render_from_scratch_after_patch_failed() {
+        let app = DummyComponent(1);
+        let (pipe, mut frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::builder(app, pipe).verify_patches().build();
+        let handle = spawn_blocking(move || {
+            let mut sender_rx = frontend.sender_rx;
+            let mut next_patch = |document: &mut Document| match block_on(sender_rx.next()) {
+                Some(TxMsg::Patch(msg)) => apply(document, &msg),
+                _ => panic!(),
+            };
+            let mut document = Document::new();
+            let first = next_patch(&mut document);
+            assert_matches!(
+                first.instructions.as_slice(),
+                [Instruction::Replace(_), Instruction::Checksum(_)]
+            );
+            control.update(()).unwrap();
+            let second = next_patch(&mut document);
+            assert_matches!(second.instructions.last(), Some(Instruction::Checksum(_)));
+
+            // the frontend could not apply the second frame
+            let failed = |frame| RxMsg::PatchFailed(frame, "error".into());
+            block_on(frontend.receiver_tx.send(failed(second.frame))).unwrap();
+            let mut document = Document::new();
+            let third = next_patch(&mut document);
+            assert_matches!(
+                third.instructions.as_slice(),
+                [Instruction::Replace(_), Instruction::Checksum(_)]
+            );
+            assert!(third.frame > second.frame);
+            assert_eq!(document.to_string(), "<div id=\"html-id\">2</div>");
+
+            // failures of discarded frames are ignored
+            block_on(frontend.receiver_tx.send(failed(second.frame))).unwrap();
+            let applied = RxMsg::FrameApplied(third.frame, Default::default());
+            block_on(frontend.receiver_tx.send(applied)).unwrap();
+            assert!(wait_idle(&control));
+            control.quit().unwrap();
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+    }
+
+    #[test]
+    fn test_metrics_sink() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let app = DummyComponent(1);
+        let (pipe, mut frontend) = DummyPipe::new();
+        let sink_reports = reports.clone();
+        let (rt, control) = Runtime::builder(app, pipe)
+            .metrics_sink(move |_: &Metrics| {
+                sink_reports.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        let handle = spawn_blocking(move || {
+            let _ = block_on(frontend.sender_rx.next()).unwrap();
+            block_on(async { control.status().await.unwrap() });
+            control.quit().unwrap();
+        });
+        rt.run_blocking().unwrap();
+        block_on(handle);
+        assert_eq!(reports.load(Ordering::SeqCst), 1);
+    }
+
+    struct ConnectingApp {
+        connects: Arc<AtomicUsize>,
+        disconnects: Arc<AtomicUsize>,
+    }
+
+    impl Render for ConnectingApp {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html().elem("div").build()
+        }
+    }
+
+    impl App for ConnectingApp {
+        fn update(&mut self, _msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            Updated::no()
+        }
+
+        fn mount(&mut self, ctx: Context<Self::Message>) {
+            ctx.load_css("div {}");
+        }
+
+        fn on_connect(&mut self, _ctx: Context<Self::Message>) {
+            self.connects.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_disconnect(&mut self, _ctx: Context<Self::Message>) {
+            self.disconnects.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_resync_after_reconnect() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let app = ConnectingApp {
+            connects: connects.clone(),
+            disconnects: disconnects.clone(),
+        };
+        let (pipe, mut frontend) = DummyPipe::new();
+        let (rt, _control) = Runtime::new(app, pipe);
+        let handle = spawn_blocking(move || {
+            assert_matches!(block_on(frontend.sender_rx.next()), Some(TxMsg::LoadCss(_)));
+            let first = match block_on(frontend.sender_rx.next()) {
+                Some(TxMsg::Patch(patch)) => DecodedPatch::decode(&patch).unwrap(),
+                _ => panic!(),
+            };
+            block_on(
+                frontend
+                    .receiver_tx
+                    .send(RxMsg::FrameApplied(1, Default::default())),
+            )
+            .unwrap();
+            block_on(frontend.receiver_tx.send(RxMsg::Disconnected())).unwrap();
+            block_on(frontend.receiver_tx.send(RxMsg::Connected())).unwrap();
+            // the new frontend receives the css and the full DOM again
+            assert_matches!(block_on(frontend.sender_rx.next()), Some(TxMsg::LoadCss(css)) if css == "div {}");
+            match block_on(frontend.sender_rx.next()) {
+                Some(TxMsg::Patch(patch)) => {
+                    let patch = DecodedPatch::decode(&patch).unwrap();
+                    assert_eq!(patch.instructions, first.instructions);
+                    assert!(patch.frame > first.frame);
                 }
                 _ => panic!(),
             }
         });
-        rt.run_blocking();
+        rt.run_blocking().unwrap();
         block_on(handle);
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_send_failure_stops() {
+        let app = DummyComponent(1);
+        let (pipe, frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::new(app, pipe);
+        // the frontend stops receiving but keeps the connection open
+        drop(frontend.sender_rx);
+        assert_matches!(rt.run_blocking().err(), Some(RuntimeError::Send(_)));
+        assert_eq!(control.update(()), Err(RuntimeError::Stopped));
+        drop(frontend.receiver_tx);
+    }
+
+    #[test]
+    fn test_send_failure_headless() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let app = ConnectingApp {
+            connects: connects.clone(),
+            disconnects: disconnects.clone(),
+        };
+        let (pipe, frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::builder(app, pipe)
+            .send_failure_policy(SendFailurePolicy::Headless)
+            .build();
+        drop(frontend.sender_rx);
+        let handle = spawn_blocking(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            control.quit().unwrap();
+        });
+        assert!(rt.run_blocking().is_ok());
+        block_on(handle);
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 0);
+        drop(frontend.receiver_tx);
+    }
+
+    /// Service which stops once it receives `RxServiceMessage::Stop`
+    struct StoppableService(Arc<AtomicBool>);
+
+    impl Service for StoppableService {
+        type Data = ();
+        type DataStream = UnboundedReceiver<()>;
+
+        fn start(self, mut mailbox: Mailbox) -> Self::DataStream {
+            let (tx, rx) = unbounded();
+            spawn(async move {
+                let _tx = tx;
+                while let Some(msg) = mailbox.next().await {
+                    if let RxServiceMessage::Stop = msg {
+                        self.0.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                }
+            });
+            rx
+        }
+    }
+
+    /// Service which ignores `RxServiceMessage::Stop`
+    struct StubbornService(UnboundedReceiver<()>);
+
+    impl Service for StubbornService {
+        type Data = ();
+        type DataStream = UnboundedReceiver<()>;
+
+        fn start(self, _mailbox: Mailbox) -> Self::DataStream {
+            self.0
+        }
+    }
+
+    /// Sets a flag once dropped
+    struct DropGuard(Arc<AtomicBool>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[derive(Default)]
+    struct ShutdownApp {
+        quit: Arc<AtomicBool>,
+        service_stopped: Arc<AtomicBool>,
+        future_dropped: Arc<AtomicBool>,
+        stubborn_service: Option<StubbornService>,
+    }
+
+    impl Render for ShutdownApp {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html().elem("div").build()
+        }
+    }
+
+    impl App for ShutdownApp {
+        fn update(&mut self, _msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            Updated::no()
+        }
+
+        fn mount(&mut self, ctx: Context<Self::Message>) {
+            ctx.run_service(StoppableService(self.service_stopped.clone()), |x| x);
+            if let Some(service) = self.stubborn_service.take() {
+                ctx.run_service(service, |x| x);
+            }
+            let guard = DropGuard(self.future_dropped.clone());
+            ctx.spawn(async move {
+                let _guard = guard;
+                pending::<()>().await
+            });
+        }
+
+        fn on_quit(&mut self, ctx: Context<Self::Message>) {
+            self.quit.store(true, Ordering::SeqCst);
+            ctx.run_js("bye");
+        }
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let app = ShutdownApp::default();
+        let quit = app.quit.clone();
+        let service_stopped = app.service_stopped.clone();
+        let future_dropped = app.future_dropped.clone();
+        let (pipe, frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::new(app, pipe);
+        control.quit().unwrap();
+        rt.run_blocking().unwrap();
+        assert!(quit.load(Ordering::SeqCst));
+        assert!(service_stopped.load(Ordering::SeqCst));
+        assert!(future_dropped.load(Ordering::SeqCst));
+        // messages of `on_quit()` are sent before the pipe is closed
+        let msgs = block_on(frontend.sender_rx.collect::<Vec<_>>());
+        assert_matches!(msgs.last(), Some(TxMsg::RunJs(js)) if js == "bye");
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let (tx, rx) = unbounded();
+        let app = ShutdownApp {
+            stubborn_service: Some(StubbornService(rx)),
+            ..Default::default()
+        };
+        let (pipe, frontend) = DummyPipe::new();
+        let (rt, control) = Runtime::builder(app, pipe)
+            .shutdown_timeout(Duration::from_millis(50))
+            .build();
+        control.quit().unwrap();
+        let before = Instant::now();
+        rt.run_blocking().unwrap();
+        assert!(before.elapsed() < Duration::from_secs(2));
+        // the pipe is closed after the timeout, such that pending messages are delivered
+        let msgs = block_on(frontend.sender_rx.collect::<Vec<_>>());
+        assert_matches!(msgs.last(), Some(TxMsg::RunJs(js)) if js == "bye");
+        // the service is cancelled anyway, which drops its stream
+        let cancelled = (0..100).any(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            tx.is_closed()
+        });
+        assert!(cancelled);
+    }
+
+    #[test]
+    fn test_render_components_in_parallel() {
+        let panels: Vec<_> = (0..8).map(|k| Component::new(DummyComponent(k))).collect();
+        let root = Node::html()
+            .elem("div")
+            .add(panels.iter().map(|x| x.mount()).collect::<Vec<_>>())
+            .build();
+        let mut metrics = Metrics::new();
+        let result = RenderResult::<DummyComponent>::new_from_root(
+            root,
+            None,
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(result.components.len(), 8);
+        for (k, panel) in panels.iter().enumerate() {
+            match result.get_component_vdom(panel.id()) {
+                Some(VNode::Element(elem)) => {
+                    assert_matches!(&elem.children[0], VNode::Text(x) if x == &k.to_string())
+                }
+                _ => panic!(),
+            }
+            assert!(metrics.components.contains_key(&panel.id()));
+        }
+
+        // only re-render the invalidated components
+        let frame = Frame::new(result, Default::default());
+        let changes: HashSet<Id> = panels[..4].iter().map(|x| x.id()).collect();
+        let result = RenderResult::new_from_frame(&frame, &changes, &mut metrics);
+        assert_eq!(result.components.len(), 8);
+        assert_eq!(result.rendered, changes);
+    }
+
+    #[test]
+    fn test_memo_reuses_rendered_nodes() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let render = |key: u32| {
+            let calls = calls.clone();
+            let memo = Node::memo(key, move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Node::html()
+                    .elem("button")
+                    .on("click", |_| ())
+                    .add(Node::text("foo"))
+                    .build()
+            });
+            Node::html().elem("div").add(memo).build()
+        };
+        let mut metrics = Metrics::new();
+
+        let result = RenderResult::<DummyComponent>::new_from_root(
+            render(1),
+            None,
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.memos.len(), 1);
+        let frame = Frame::new(result, Default::default());
+
+        // same key: the closure is not called and the listener is carried over
+        let result = RenderResult::<DummyComponent>::new_from_root(
+            render(1),
+            Some(&frame),
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(result.listeners.len(), 1);
+        let button_id = match result.vdom.deref() {
+            VNode::Element(elem) => elem.children[0].id(),
+            _ => panic!(),
+        };
+        assert!(!button_id.is_empty());
+        assert_eq!(result.listeners.values().next().unwrap().node_id, button_id);
+        let patch = Differ::new(&frame, &result).diff();
+        assert!(patch.is_empty());
+        let frame = Frame::new(result, Default::default());
+
+        // changed key: the closure is called again
+        let _ = RenderResult::<DummyComponent>::new_from_root(
+            render(2),
+            Some(&frame),
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_memo_equal_keys_at_same_call_site_collide() {
+        // the key does not reflect the label, thus both memoized nodes are considered equal
+        let label = |text: &'static str| {
+            Node::memo(0, move || {
+                Node::html().elem("span").add(Node::text(text)).build()
+            })
+        };
+        let render = |first: &'static str, second: &'static str| {
+            Node::html()
+                .elem("div")
+                .add(label(first))
+                .add(label(second))
+                .build()
+        };
+        let texts = |result: &RenderResult<DummyComponent>| match result.vdom.deref() {
+            VNode::Element(elem) => elem
+                .children
+                .iter()
+                .map(|child| match child {
+                    VNode::Element(span) => match &span.children[0] {
+                        VNode::Text(text) => text.clone(),
+                        _ => panic!(),
+                    },
+                    _ => panic!(),
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!(),
+        };
+        let mut metrics = Metrics::new();
+
+        let result = RenderResult::<DummyComponent>::new_from_root(
+            render("a", "b"),
+            None,
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(texts(&result), vec!["a", "b"]);
+        assert_eq!(result.memos.len(), 1);
+        let frame = Frame::new(result, Default::default());
+
+        // both nodes reuse the memoized DOM rendered last in the previous frame
+        let result = RenderResult::<DummyComponent>::new_from_root(
+            render("c", "d"),
+            Some(&frame),
+            &HashSet::new(),
+            &mut metrics,
+        );
+        assert_eq!(texts(&result), vec!["b", "b"]);
     }
 
     #[test]
@@ -0,0 +1,121 @@
+use crate::pipe::FrameTiming;
+use instant::Instant;
+use std::time::Duration;
+
+/// Weight of the most recent frame in the estimated frontend frame time, in percent
+const ESTIMATE_WEIGHT_PERCENT: u32 = 25;
+
+/// Adapts the render interval of the runtime to the time the frontend requires to apply frames.
+///
+/// The pacer keeps an exponentially weighted moving average of the decode and apply durations
+/// reported by the frontend. If the frontend is slower than the configured render interval,
+/// updates are coalesced for the estimated frame time instead. If no frame is in flight and the
+/// last frame was rendered long enough ago, the application is rendered immediately.
+#[derive(Default)]
+pub(crate) struct FramePacer {
+    estimate: Option<Duration>,
+    last_render: Option<Instant>,
+}
+
+impl FramePacer {
+    /// Records the timing of a frame applied by the frontend.
+    pub(crate) fn frame_applied(&mut self, timing: &FrameTiming) {
+        let duration = timing.decode() + timing.apply();
+        self.estimate = Some(match self.estimate {
+            Some(estimate) => {
+                (estimate * (100 - ESTIMATE_WEIGHT_PERCENT) + duration * ESTIMATE_WEIGHT_PERCENT)
+                    / 100
+            }
+            None => duration,
+        });
+    }
+
+    /// Marks that a frame was rendered.
+    pub(crate) fn rendered(&mut self) {
+        self.last_render = Some(Instant::now());
+    }
+
+    /// Returns the estimated time the frontend requires to apply a frame, if known.
+    pub(crate) fn estimate(&self) -> Option<Duration> {
+        self.estimate
+    }
+
+    /// Returns the interval between two renders, which is at least `render_interval`.
+    pub(crate) fn interval(&self, render_interval: Duration) -> Duration {
+        match self.estimate {
+            Some(estimate) => render_interval.max(estimate),
+            None => render_interval,
+        }
+    }
+
+    /// Returns the wait time before rendering after the application was updated.
+    ///
+    /// If the frontend is `idle`, i.e. no frame is in flight, only the remainder of the
+    /// interval since the last render is waited for.
+    pub(crate) fn render_delay(&self, render_interval: Duration, idle: bool) -> Duration {
+        let interval = self.interval(render_interval);
+        match self.last_render {
+            Some(last_render) if idle => interval.saturating_sub(last_render.elapsed()),
+            None if idle => Duration::from_millis(0),
+            _ => interval,
+        }
+    }
+
+    /// Returns the wait time before retrying a render which was deferred since frames are in flight.
+    pub(crate) fn retry_delay(&self, retry_interval: Duration) -> Duration {
+        match self.estimate {
+            Some(estimate) => retry_interval.max(estimate),
+            None => retry_interval,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(decode_ms: u64, apply_ms: u64) -> FrameTiming {
+        FrameTiming {
+            decode_us: decode_ms * 1000,
+            apply_us: apply_ms * 1000,
+        }
+    }
+
+    #[test]
+    fn test_estimate() {
+        let mut pacer = FramePacer::default();
+        assert_eq!(pacer.estimate(), None);
+        pacer.frame_applied(&timing(20, 80));
+        assert_eq!(pacer.estimate(), Some(Duration::from_millis(100)));
+        pacer.frame_applied(&timing(0, 20));
+        assert_eq!(pacer.estimate(), Some(Duration::from_millis(80)));
+    }
+
+    #[test]
+    fn test_interval() {
+        let interval = Duration::from_millis(30);
+        let mut pacer = FramePacer::default();
+        assert_eq!(pacer.interval(interval), interval);
+        assert_eq!(pacer.render_delay(interval, true), Duration::from_millis(0));
+        assert_eq!(pacer.render_delay(interval, false), interval);
+
+        // a fast frontend does not exceed the configured frame rate
+        pacer.frame_applied(&timing(1, 1));
+        assert_eq!(pacer.interval(interval), interval);
+        pacer.rendered();
+        assert!(pacer.render_delay(interval, true) > Duration::from_millis(0));
+
+        // a slow frontend coalesces more updates
+        let mut pacer = FramePacer::default();
+        pacer.frame_applied(&timing(50, 50));
+        assert_eq!(pacer.interval(interval), Duration::from_millis(100));
+        assert_eq!(
+            pacer.render_delay(interval, false),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            pacer.retry_delay(Duration::from_millis(10)),
+            Duration::from_millis(100)
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! This module defines the format of session recordings.
+//!
+//! A [`Runtime`](../struct.Runtime.html) configured with `RuntimeBuilder::record()` writes every
+//! input of the application to a recording: messages received from the frontend, messages passed to
+//! `RuntimeControl::update()`, results of futures, streams and services as well as the points in
+//! time at which the application was rendered. Using `RuntimeBuilder::record_with_patches()`, all
+//! emitted patches are recorded as well, such that a replay can detect divergences of the DOM.
+//! Since patches make up most of a recording, they are omitted by default.
+//!
+//! A recording consists of one [`Record`](struct.Record.html) per line, each serialized as JSON.
+//! Application messages are stored in their serde JSON representation, thus recording requires
+//! the message type of the application to implement `serde::Serialize`.
+//! Recordings are replayed using [Replay](../../testing/struct.Replay.html).
+//!
+//! Renders from scratch, e.g. after the frontend (re-)connected or failed to apply a patch,
+//! are not recorded as `Entry::Render`, since they are caused by the recorded frontend message
+//! and are thus repeated by the replay anyway.
+//!
+
+use crate::pipe::RxMsg;
+use crate::platform::spawn_blocking;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::channel::oneshot;
+use futures::StreamExt;
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::io::{self, BufRead, Write};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+/// A single entry of a recording along with the time it was recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Milliseconds since the runtime was started
+    pub time_ms: u64,
+    pub entry: Entry,
+}
+
+/// An input or output of a recorded application.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Entry {
+    /// A [RxMsg](../../pipe/enum.RxMsg.html) received from the frontend
+    Frontend(JsonValue),
+    /// A message passed to `RuntimeControl::update()`
+    Update(JsonValue),
+    /// The result of a future or an item of a stream spawned using a `Context`
+    Async(JsonValue),
+    /// A message emitted by a service
+    Service(JsonValue),
+    /// A scheduled render of the application
+    ///
+    /// Renders triggered by frontend messages are not recorded, refer to the module documentation.
+    Render,
+    /// A patch sent to the frontend, serialized as base64 string
+    Patch(#[serde(with = "base64_data")] Vec<u8>),
+}
+
+/// Serializes binary data as base64 string, which is considerably shorter than a JSON array.
+mod base64_data {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(data))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let data = String::deserialize(deserializer)?;
+        base64::decode(&data).map_err(D::Error::custom)
+    }
+}
+
+/// Reads all records of a recording.
+pub fn read<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+    let mut ret = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        ret.push(record);
+    }
+    Ok(ret)
+}
+
+/// Writes records of a running application.
+///
+/// Records are serialized by the runtime, but written by a blocking task, such that slow writers
+/// do not stall the runtime. Each record is flushed immediately, such that the recording is
+/// complete even if the application crashes.
+pub(crate) struct Recorder<T> {
+    lines: UnboundedSender<Vec<u8>>,
+    written: oneshot::Receiver<()>,
+    serialize: fn(&T) -> serde_json::Result<JsonValue>,
+    patches: bool,
+    start: Instant,
+}
+
+impl<T> Recorder<T> {
+    /// Creates a recorder writing to `writer`, which records emitted patches if `patches` is set.
+    pub(crate) fn new<W: 'static + Write + Send>(mut writer: W, patches: bool) -> Self
+    where
+        T: Serialize,
+    {
+        let (lines, mut rx) = unbounded::<Vec<u8>>();
+        let (written_tx, written) = oneshot::channel();
+        spawn_blocking(async move {
+            while let Some(line) = rx.next().await {
+                if let Err(err) = writer.write_all(&line).and_then(|_| writer.flush()) {
+                    log::error!("Failed to write recording: {}", err);
+                }
+            }
+            let _ = written_tx.send(());
+        });
+        Self {
+            lines,
+            written,
+            serialize: |x| serde_json::to_value(x),
+            patches,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns true if emitted patches are recorded.
+    pub(crate) fn records_patches(&self) -> bool {
+        self.patches
+    }
+
+    /// Resolves once all records have been written.
+    pub(crate) async fn close(self) {
+        let Self { lines, written, .. } = self;
+        drop(lines);
+        let _ = written.await;
+    }
+
+    /// Records a message of the application, which is wrapped into an entry using `entry`.
+    pub(crate) fn message(&mut self, msg: &T, entry: fn(JsonValue) -> Entry) {
+        match (self.serialize)(msg) {
+            Ok(value) => self.record(entry(value)),
+            Err(err) => log::error!("Failed to record message: {}", err),
+        }
+    }
+
+    /// Records a message received from the frontend.
+    pub(crate) fn frontend(&mut self, msg: &RxMsg) {
+        match serde_json::to_value(msg) {
+            Ok(value) => self.record(Entry::Frontend(value)),
+            Err(err) => log::error!("Failed to record message: {}", err),
+        }
+    }
+
+    pub(crate) fn record(&mut self, entry: Entry) {
+        let record = Record {
+            time_ms: Instant::now().duration_since(self.start).as_millis() as u64,
+            entry,
+        };
+        match serde_json::to_vec(&record) {
+            Ok(mut line) => {
+                line.push(b'\n');
+                let _ = self.lines.unbounded_send(line);
+            }
+            Err(err) => log::error!("Failed to record entry: {}", err),
+        }
+    }
+}
+
+/// In-memory recording, which remains accessible after it was passed to a runtime.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Buffer {
+    /// Returns the records written so far.
+    pub(crate) fn records(&self) -> Vec<Record> {
+        read(self.0.lock().unwrap().as_slice()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::FrameTiming;
+    use async_std::task::block_on;
+
+    #[test]
+    fn test_read_write() {
+        let buffer = Buffer::default();
+        let mut recorder = Recorder::<(u32, String)>::new(buffer.clone(), true);
+        recorder.frontend(&RxMsg::FrameApplied(
+            3,
+            FrameTiming {
+                decode_us: 1,
+                apply_us: 2,
+            },
+        ));
+        recorder.message(&(1, "a".to_string()), Entry::Update);
+        recorder.record(Entry::Render);
+        recorder.record(Entry::Patch(vec![2, 1]));
+        block_on(recorder.close());
+        let data = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(data.contains("{\"Patch\":\"AgE=\"}"));
+        let entries: Vec<_> = buffer.records().into_iter().map(|x| x.entry).collect();
+        assert_eq!(
+            entries,
+            vec![
+                Entry::Frontend(
+                    serde_json::json!({"FrameApplied": [3, {"decode_us": 1, "apply_us": 2}]})
+                ),
+                Entry::Update(serde_json::json!([1, "a"])),
+                Entry::Render,
+                Entry::Patch(vec![2, 1]),
+            ]
+        );
+        assert!(read("{}\n".as_bytes()).is_err());
+    }
+}
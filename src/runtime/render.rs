@@ -8,6 +8,8 @@
 //!  * The VDOM is rendered based on a previous render and only a limited set of marked components
 //!     are re-rendered. The remaining components are transferred to the newly created VDOM.
 //!
+//! Components which require re-rendering are rendered in parallel on a thread pool.
+//!
 
 use crate::blob::Blob;
 use crate::component::{ComponentContainer, ComponentMap};
@@ -15,6 +17,7 @@ use crate::element::ElementMap;
 use crate::event::Subscription;
 use crate::listener::{Listener, ListenerKey, Rpc};
 use crate::node::{Node, NodeItems};
+use crate::platform::parallel_map;
 use crate::runtime::component::RenderedComponent;
 use crate::runtime::metrics::Metrics;
 use crate::runtime::state::Frame;
@@ -28,13 +31,19 @@ use std::sync::Arc;
 // since we store the event_id as the key to find a single subscription
 // however, we should use a subscription list as value
 
+/// Memoized nodes of a frame indexed by the hash of their key.
+pub(crate) type Memos<A> = HashMap<u64, Arc<Memo<A>>>;
+
 /// Renders a component from scratch and emit a set of `ResultItem`s.
+///
+/// Memoized nodes found in `memos` are reused instead of being rendered.
 pub(crate) fn render_component<A: App>(
     dom: Node<A::Message>,
     result: &mut Vec<ResultItem<A>>,
+    memos: Option<&Memos<A>>,
 ) -> Vec<VNode> {
     let mut path = Path::new();
-    render_recursive(dom, result, &mut path)
+    render_recursive(dom, result, &mut path, memos)
 }
 
 /// Recursively renders an arbitrary node.
@@ -43,10 +52,11 @@ fn render_recursive<A: App>(
     dom: Node<A::Message>,
     result: &mut Vec<ResultItem<A>>,
     path: &mut Path,
+    memos: Option<&Memos<A>>,
 ) -> Vec<VNode> {
     match dom.0 {
         NodeItems::ElementMap(mut elem) => {
-            if let Some(x) = render_element(&mut *elem.inner, result, path) {
+            if let Some(x) = render_element(&mut *elem.inner, result, path, memos) {
                 vec![x]
             } else {
                 vec![]
@@ -59,7 +69,7 @@ fn render_recursive<A: App>(
         }
         NodeItems::Text(text) => vec![VNode::text(text)],
         NodeItems::Element(mut elem) => {
-            if let Some(x) = render_element(&mut elem, result, path) {
+            if let Some(x) = render_element(&mut elem, result, path, memos) {
                 vec![x]
             } else {
                 vec![]
@@ -75,8 +85,24 @@ fn render_recursive<A: App>(
         }
         NodeItems::FlatMap(mut nodes) => nodes
             .drain(..)
-            .flat_map(|x| render_recursive(x, result, path))
+            .flat_map(|x| render_recursive(x, result, path, memos))
             .collect(),
+        NodeItems::Memo(key, fun) => {
+            if let Some(memo) = memos.and_then(|x| x.get(&key)) {
+                let vdom = memo.instantiate(result);
+                result.push(ResultItem::Memo(key, memo.clone()));
+                return vdom;
+            }
+            let mut items = Vec::new();
+            let vdom = render_component(fun(), &mut items, memos);
+            let memo = Arc::new(Memo {
+                vdom: vdom.clone(),
+                items: items.clone(),
+            });
+            result.extend(items);
+            result.push(ResultItem::Memo(key, memo));
+            vdom
+        }
     }
 }
 
@@ -86,13 +112,14 @@ fn render_element<A: App>(
     elem: &mut dyn ElementMap<A::Message>,
     result: &mut Vec<ResultItem<A>>,
     path: &mut Path,
+    memos: Option<&Memos<A>>,
 ) -> Option<VNode> {
     let mut children = Vec::new();
     path.push(0);
     for (k, child) in elem.take_children().drain(..).enumerate() {
         path.pop();
         path.push(k);
-        let child = render_component(child, result);
+        let child = render_component(child, result, memos);
         children.extend(child);
     }
     let mut events = Vec::new();
@@ -103,7 +130,7 @@ fn render_element<A: App>(
     if let Some(rpc) = elem.take_rpc() {
         result.push(ResultItem::Rpc(rpc));
     }
-    Some(VNode::element(VElement {
+    let mut velem = VElement {
         id: elem.id(),
         tag: elem.take_tag(),
         attr: elem.take_attrs(),
@@ -111,7 +138,11 @@ fn render_element<A: App>(
         events,
         children,
         namespace: elem.take_namespace(),
-    }))
+        key: elem.take_key(),
+        hash: None,
+    };
+    velem.hash = velem.structural_hash();
+    Some(VNode::element(velem))
 }
 
 /// Non-DOM items which are emitted from rendering a `Node<T>` data structure.
@@ -121,6 +152,73 @@ pub(crate) enum ResultItem<A: App> {
     Component(ComponentContainer<A::Message>, Path),
     Blob(Blob),
     Rpc(Rpc<A::Message>),
+    Memo(u64, Arc<Memo<A>>),
+}
+
+impl<A: App> Clone for ResultItem<A> {
+    fn clone(&self) -> Self {
+        match self {
+            ResultItem::Listener(listener) => ResultItem::Listener(listener.clone()),
+            ResultItem::Subscription(id, subs) => ResultItem::Subscription(*id, subs.clone()),
+            ResultItem::Component(comp, path) => ResultItem::Component(comp.clone(), path.clone()),
+            ResultItem::Blob(blob) => ResultItem::Blob(blob.clone()),
+            ResultItem::Rpc(rpc) => ResultItem::Rpc(rpc.clone()),
+            ResultItem::Memo(key, memo) => ResultItem::Memo(*key, memo.clone()),
+        }
+    }
+}
+
+/// The rendered state of a memoized node, which is reused in subsequent frames
+/// as long as the key of the memoized node does not change.
+pub(crate) struct Memo<A: App> {
+    vdom: Vec<VNode>,
+    items: Vec<ResultItem<A>>,
+}
+
+impl<A: App> Memo<A> {
+    /// Creates a new instance of the memoized VDOM and pushes its non-DOM items into `result`.
+    ///
+    /// All elements are assigned fresh ids, as if they were rendered anew. This avoids
+    /// clashes in case the same memoized node is used multiple times or moves around in the DOM.
+    fn instantiate(&self, result: &mut Vec<ResultItem<A>>) -> Vec<VNode> {
+        let mut ids = HashMap::new();
+        let mut vdom = self.vdom.clone();
+        for node in vdom.iter_mut() {
+            renew_ids(node, &mut ids);
+        }
+        for item in &self.items {
+            let item = match item {
+                ResultItem::Listener(listener) => {
+                    let mut listener = listener.clone();
+                    listener.node_id = *ids.get(&listener.node_id).unwrap_or(&listener.node_id);
+                    ResultItem::Listener(listener)
+                }
+                ResultItem::Rpc(rpc) => {
+                    let mut rpc = rpc.clone();
+                    rpc.node_id = *ids.get(&rpc.node_id).unwrap_or(&rpc.node_id);
+                    ResultItem::Rpc(rpc)
+                }
+                item => item.clone(),
+            };
+            result.push(item);
+        }
+        vdom
+    }
+}
+
+/// Recursively assigns new ids to all elements which carry an id.
+/// The mapping from old to new ids is recorded in `ids`.
+fn renew_ids(node: &mut VNode, ids: &mut HashMap<Id, Id>) {
+    if let VNode::Element(elem) = node {
+        if !elem.id.is_empty() {
+            let id = Id::new();
+            ids.insert(elem.id, id);
+            elem.id = id;
+        }
+        for child in elem.children.iter_mut() {
+            renew_ids(child, ids);
+        }
+    }
 }
 
 /// Collects the result of a render operation.
@@ -138,6 +236,8 @@ pub(crate) struct RenderResult<A: App> {
     pub(crate) root_listeners: Vec<ListenerKey>,
     pub(crate) root_blobs: Vec<Id>,
     pub(crate) root_rpcs: Vec<Id>,
+    pub(crate) memos: Memos<A>,
+    pub(crate) root_memos: Vec<u64>,
     pub(crate) vdom: Arc<VNode>,
     pub(crate) rendered: HashSet<Id>,
 }
@@ -156,6 +256,8 @@ impl<A: App> RenderResult<A> {
             root_listeners: vec![],
             root_blobs: vec![],
             root_rpcs: vec![],
+            memos: Default::default(),
+            root_memos: vec![],
             vdom: Arc::new(root),
             rendered: Default::default()
         }
@@ -174,16 +276,24 @@ impl<A: App> RenderResult<A> {
             root_listeners: vec![],
             root_blobs: vec![],
             root_rpcs: vec![],
+            memos: Default::default(),
+            root_memos: vec![],
             vdom: Arc::new(VNode::Text("".to_string())),
             rendered: Default::default()
         }
     }
 
     /// Create a new RenderResult if the root component was re-rendered.
-    /// Re-renders the whole component tree.
-    pub(crate) fn new_from_root(root_rendered: Node<A::Message>, changes: &HashSet<Id>, metrics: &mut Metrics) -> Self {
+    /// Re-renders the whole component tree, only memoized nodes of the `old` frame are reused.
+    pub(crate) fn new_from_root(
+        root_rendered: Node<A::Message>,
+        old: Option<&Frame<A>>,
+        changes: &HashSet<Id>,
+        metrics: &mut Metrics,
+    ) -> Self {
+        let memos = old.map(|x| &x.rendered.memos);
         let mut result = Vec::new();
-        let mut vdom = render_component::<A>(root_rendered, &mut result);
+        let mut vdom = render_component::<A>(root_rendered, &mut result, memos);
         if vdom.len() != 1 {
             panic!("The DOM of the root app must be represented by exactly one DOM node");
         }
@@ -200,10 +310,13 @@ impl<A: App> RenderResult<A> {
             root_listeners: vec![],
             root_blobs: vec![],
             root_rpcs: vec![],
+            memos: Default::default(),
+            root_memos: vec![],
             vdom: Arc::new(vdom),
             rendered: Default::default()
         };
 
+        let mut pending = Vec::new();
         for item in result.drain(..) {
             match item {
                 ResultItem::Listener(listener) => {
@@ -217,7 +330,7 @@ impl<A: App> RenderResult<A> {
                 }
                 ResultItem::Component(comp, path) => {
                     ret.root_components.push((comp.id(), path));
-                    ret.render_updated_component(None, comp, changes, metrics);
+                    pending.push(comp);
                 }
                 ResultItem::Blob(blob) => {
                     ret.root_blobs.push(blob.id());
@@ -227,8 +340,13 @@ impl<A: App> RenderResult<A> {
                     ret.root_rpcs.push(rpc.node_id);
                     ret.rpcs.insert(rpc.node_id, rpc);
                 }
+                ResultItem::Memo(key, memo) => {
+                    ret.root_memos.push(key);
+                    ret.memos.insert(key, memo);
+                }
             }
         }
+        ret.render_pending(None, memos, pending, changes, metrics);
         ret
     }
 
@@ -264,6 +382,11 @@ impl<A: App> RenderResult<A> {
             new_blobs.insert(*blob, old.blobs.get(&blob).unwrap().clone());
         }
 
+        let mut new_memos = HashMap::with_capacity(old.memos.len());
+        for key in &old.root_memos {
+            new_memos.insert(*key, old.memos.get(key).unwrap().clone());
+        }
+
         let mut ret = Self {
             listeners: new_listeners,
             subscriptions: new_subs,
@@ -275,30 +398,57 @@ impl<A: App> RenderResult<A> {
             root_listeners: old.root_listeners.clone(),
             root_blobs: old.root_blobs.clone(),
             root_rpcs: old.root_rpcs.clone(),
+            memos: new_memos,
+            root_memos: old.root_memos.clone(),
             vdom: old.vdom.clone(),
             rendered: Default::default()
         };
 
         // iterate over all components and check / render them recursively
+        let mut pending = Vec::new();
         for (id, _) in &old.root_components {
             let comp = old.components.get(id).unwrap();
-            ret.render_component(Some(old), comp.component(), changes, metrics);
+            ret.render_component(Some(old), comp.component(), changes, &mut pending);
         }
+        ret.render_pending(Some(old), Some(&old.memos), pending, changes, metrics);
 
         ret
     }
 
-    /// Renders a component and registers its results into the current object.
-    fn render_updated_component(
+    /// Renders all pending components and registers their results into the current object.
+    ///
+    /// Components are rendered in batches on a thread pool. Child components discovered
+    /// while merging the results of a batch are rendered in the subsequent batch.
+    fn render_pending(
         &mut self,
         old: Option<&RenderResult<A>>,
-        comp: ComponentContainer<A::Message>,
+        memos: Option<&Memos<A>>,
+        mut pending: Vec<ComponentContainer<A::Message>>,
         changes: &HashSet<Id>,
         metrics: &mut Metrics,
     ) {
-        let id = comp.id();
+        while !pending.is_empty() {
+            let batch = std::mem::take(&mut pending);
+            let rendered = parallel_map(batch, |comp| RenderedComponent::<A>::new(comp, memos));
+            for (rendered, result, delta) in rendered {
+                let id = rendered.component().id();
+                metrics.record_comp(id, delta);
+                self.register_rendered_component(old, id, rendered, result, changes, &mut pending);
+            }
+        }
+    }
+
+    /// Registers the results of a freshly rendered component into the current object.
+    fn register_rendered_component(
+        &mut self,
+        old: Option<&RenderResult<A>>,
+        id: Id,
+        rendered: RenderedComponent<A>,
+        mut result: Vec<ResultItem<A>>,
+        changes: &HashSet<Id>,
+        pending: &mut Vec<ComponentContainer<A::Message>>,
+    ) {
         self.rendered.insert(id);
-        let (rendered, mut result) = RenderedComponent::new(comp, metrics);
         self.components.insert(id, Arc::new(rendered));
 
         for item in result.drain(..) {
@@ -310,7 +460,7 @@ impl<A: App> RenderResult<A> {
                     self.subscriptions.insert(id, subscription);
                 }
                 ResultItem::Component(comp, _) => {
-                    self.render_component(old, comp, changes, metrics);
+                    self.render_component(old, comp, changes, pending);
                 }
                 ResultItem::Blob(blob) => {
                     self.blobs.insert(blob.id(), blob);
@@ -318,6 +468,9 @@ impl<A: App> RenderResult<A> {
                 ResultItem::Rpc(rpc) => {
                     self.rpcs.insert(rpc.node_id, rpc);
                 }
+                ResultItem::Memo(key, memo) => {
+                    self.memos.insert(key, memo);
+                }
             }
         }
     }
@@ -328,14 +481,14 @@ impl<A: App> RenderResult<A> {
         old: &RenderResult<A>,
         comp: ComponentContainer<A::Message>,
         changes: &HashSet<Id>,
-        metrics: &mut Metrics,
+        pending: &mut Vec<ComponentContainer<A::Message>>,
     ) {
         // transfer all child items of this component to the new result.
         let id = comp.id();
         let old_render = old.components.get(&id).unwrap();
         for (child, _) in old_render.children() {
             let old_comp = old.components.get(child).unwrap();
-            self.render_component(Some(old), old_comp.component(), changes, metrics)
+            self.render_component(Some(old), old_comp.component(), changes, pending)
         }
         for key in old_render.listeners() {
             let listener = old.listeners.get(key).unwrap();
@@ -353,27 +506,32 @@ impl<A: App> RenderResult<A> {
             let blob = old.blobs.get(&blob_id).unwrap();
             self.blobs.insert(*blob_id, blob.clone());
         }
+        for key in old_render.memos() {
+            let memo = old.memos.get(key).unwrap();
+            self.memos.insert(*key, memo.clone());
+        }
         self.components.insert(id, old_render.clone());
     }
 
-    /// Renders a component based on an old RenderResult
+    /// Renders a component based on an old RenderResult.
+    ///
+    /// Unchanged components are transferred immediately, all others
+    /// are pushed to `pending` to be rendered by `render_pending()`.
     fn render_component(
         &mut self,
         old: Option<&RenderResult<A>>,
         comp: ComponentContainer<A::Message>,
         changes: &HashSet<Id>,
-        metrics: &mut Metrics,
+        pending: &mut Vec<ComponentContainer<A::Message>>,
     ) {
-        let id = comp.id();
         if let Some(old) = old {
+            let id = comp.id();
             if !changes.contains(&id) && old.components.contains_key(&id) {
-                self.render_unchanged_component(old, comp, changes, metrics);
-            } else {
-                self.render_updated_component(Some(old), comp, changes, metrics);
+                self.render_unchanged_component(old, comp, changes, pending);
+                return;
             }
-        } else {
-            self.render_updated_component(old, comp, changes, metrics);
         }
+        pending.push(comp);
     }
 
     pub(crate) fn get_component_vdom(&self, component_id: Id) -> Option<&VNode> {
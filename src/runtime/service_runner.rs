@@ -3,12 +3,13 @@
 //! It spawns a new task and feeds the update messages emitted by the service
 //! back into the `update()` cycle of the application.
 
+use crate::runtime::task_set::TaskSet;
 use crate::service::{RxServiceMessage, ServiceSubscription, TxServiceMessage};
 use crate::Id;
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::stream;
 use futures::task::{Context, Poll};
-use futures::{Stream, StreamExt};
+use futures::{Future, Stream, StreamExt};
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
@@ -77,21 +78,32 @@ impl<Msg: Send> ServiceCollection<Msg> {
     }
 
     /// Start executing a new service on this `ServiceCollection`.
-    pub(crate) fn spawn(&mut self, subs: ServiceSubscription<Msg>) {
+    ///
+    /// The service is cancelled along with the other tasks in `tasks`.
+    pub(crate) fn spawn(&mut self, subs: ServiceSubscription<Msg>, tasks: &TaskSet) {
         let id = subs.id();
         let mailbox_tx = subs.rxmailbox_tx.clone();
         let runner = ServiceRunner {
             tx: self.msg_sender.clone(),
             service: subs,
         };
-        runner.run();
+        crate::platform::spawn(tasks.cancellable(runner.run()));
         let control = ServiceControl { mailbox_tx };
         self.services.insert(id, control);
     }
 
-    /// Stop all running services
-    fn stop_all(mut self) {
-        self.services.drain().for_each(|x| x.1.stop());
+    /// Asks all running services to stop. Use `stopped()` to wait for them.
+    pub(crate) fn stop_all(&mut self) {
+        self.services.values().for_each(ServiceControl::stop);
+    }
+
+    /// Resolves once all services have stopped. Messages emitted meanwhile are discarded.
+    pub(crate) async fn stopped(&mut self) {
+        while !self.services.is_empty() {
+            if self.next().await.is_none() {
+                break;
+            }
+        }
         self.msg_receiver.close();
     }
 
@@ -132,10 +144,10 @@ enum ServiceRunnerMsg<Msg: Send> {
 }
 
 impl<Msg: Send> ServiceRunner<Msg> {
-    /// Spawn a new task and run the contained service in it.
-    pub(crate) fn run(self) {
+    /// Runs the contained service. The returned future is spawned as a separate task.
+    pub(crate) fn run(self) -> impl Future<Output = ()> {
         let runner = self;
-        crate::platform::spawn(async {
+        async {
             let id = runner.service.id();
             let mut service = runner.service;
             let txmailbox_rx = service.txmailbox_rx.take().unwrap();
@@ -171,7 +183,7 @@ impl<Msg: Send> ServiceRunner<Msg> {
             // if the channel is already broken, the receiving ends have probably hung up
             // this is no big deal, we can just ignore this condition.
             let _ = runner.tx.unbounded_send(ServiceMessage::Stopped(id));
-        });
+        }
     }
 }
 
@@ -203,8 +215,9 @@ mod tests {
     fn service_runner_without_frontend_io() {
         let subs = ServiceSubscription::new(DummyService, |x| x);
         let mut col = ServiceCollection::new();
+        let tasks = TaskSet::new();
         let id = subs.id();
-        col.spawn(subs);
+        col.spawn(subs, &tasks);
         task::block_on(async move {
             assert_matches!(col.next().await, Some(ServiceMessage::Update(0)));
             assert_matches!(col.next().await, Some(ServiceMessage::Update(1)));
@@ -220,6 +233,7 @@ mod tests {
             assert_eq!(col.services.len(), 0);
             // stop service collection
             col.stop_all();
+            col.stopped().await;
         });
     }
 
@@ -260,8 +274,9 @@ mod tests {
     fn service_runner_with_frontend_io() {
         let subs = ServiceSubscription::new(IoService, |x| x);
         let mut col = ServiceCollection::new();
+        let tasks = TaskSet::new();
         let subs_id = subs.id();
-        col.spawn(subs);
+        col.spawn(subs, &tasks);
         task::block_on(async move {
             let mut expected_msg = 0;
             let mut js_count = 0;
@@ -289,6 +304,7 @@ mod tests {
             assert_eq!(expected_msg, 4);
             assert_eq!(js_count, 6);
             col.stop_all();
+            col.stopped().await;
         });
     }
 }
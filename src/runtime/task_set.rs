@@ -0,0 +1,105 @@
+//! Keeps track of the tasks spawned by a [Runtime](../struct.Runtime.html), such that they
+//! can be cancelled and awaited once the runtime shuts down.
+
+use crate::platform::sleep;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::{select, Either, Shared};
+use futures::{Future, FutureExt, StreamExt};
+use instant::Instant;
+
+/// A set of running tasks.
+///
+/// Futures are wrapped using `cancellable()` or `tracked()` before they are spawned. Each
+/// wrapped future holds a handle to the set, which is released once the future terminates.
+/// `join()` resolves once all handles have been released.
+pub(crate) struct TaskSet {
+    cancel_tx: Option<oneshot::Sender<()>>,
+    cancel_rx: Shared<oneshot::Receiver<()>>,
+    running_tx: Option<UnboundedSender<()>>, // cloned into each task, never sends
+    running_rx: UnboundedReceiver<()>,
+}
+
+impl TaskSet {
+    pub(crate) fn new() -> Self {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let (running_tx, running_rx) = unbounded();
+        Self {
+            cancel_tx: Some(cancel_tx),
+            cancel_rx: cancel_rx.shared(),
+            running_tx: Some(running_tx),
+            running_rx,
+        }
+    }
+
+    /// Wraps `fut` such that it is dropped at its next suspension point after `cancel()`.
+    pub(crate) fn cancellable<F: Future<Output = ()>>(&self, fut: F) -> impl Future<Output = ()> {
+        let cancelled = self.cancel_rx.clone();
+        let running = self.running_tx.clone();
+        async move {
+            let _running = running;
+            select(Box::pin(fut), cancelled).await;
+        }
+    }
+
+    /// Wraps `fut` such that it is awaited by `join()`, but not cancelled.
+    pub(crate) fn tracked<F: Future<Output = ()>>(&self, fut: F) -> impl Future<Output = ()> {
+        let running = self.running_tx.clone();
+        async move {
+            let _running = running;
+            fut.await;
+        }
+    }
+
+    /// Cancels all cancellable tasks, including those spawned afterwards.
+    pub(crate) fn cancel(&mut self) {
+        self.cancel_tx.take();
+    }
+
+    /// Resolves once all tasks have terminated. No tasks may be added afterwards.
+    pub(crate) async fn join(&mut self) {
+        self.running_tx.take();
+        while self.running_rx.next().await.is_some() {}
+    }
+}
+
+/// Awaits `fut` unless `deadline` passes before. Returns false if the deadline has passed.
+pub(crate) async fn until<F: Future<Output = ()>>(deadline: Instant, fut: F) -> bool {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match select(Box::pin(fut), Box::pin(sleep(remaining))).await {
+        Either::Left(_) => true,
+        Either::Right(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+    use futures::future::pending;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_and_join() {
+        let mut tasks = TaskSet::new();
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        task::spawn(tasks.cancellable(pending()));
+        task::spawn(tasks.tracked(async move {
+            task::sleep(Duration::from_millis(10)).await;
+            flag.store(true, Ordering::SeqCst);
+        }));
+        tasks.cancel();
+        task::block_on(tasks.join());
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_until() {
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert!(!task::block_on(until(deadline, pending())));
+        assert!(task::block_on(until(deadline, async {})));
+    }
+}
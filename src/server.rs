@@ -0,0 +1,360 @@
+//! This module implements a server which runs a separate application for each connection.
+//!
+//! While a [WebSocketPipe](../websockets/struct.WebSocketPipe.html) only accepts a single
+//! connection, the [Server](struct.Server.html) keeps accepting connections. For each connection
+//! it creates a new application using a user-supplied factory function and executes it
+//! in its own [Runtime](../runtime/struct.Runtime.html).
+//!
+
+use crate::runtime::Runtime;
+use crate::websockets::{accept, Access, TlsConfig, WebSocketPipe, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::App;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use futures::prelude::*;
+use log::error;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+
+/// The `Server` type accepts WebSocket connections and runs a fresh application for each of them.
+///
+/// Each connection is called a session. A session ends once either the connection is closed
+/// or the application quits.
+/// Optionally, the number of concurrent sessions may be limited using `max_sessions()`.
+/// Connections exceeding this limit are closed immediately.
+/// Also, sessions may be closed after not receiving any message from the frontend for a
+/// certain time using `idle_timeout()`.
+/// Which frontends may start a session is restricted using `access()`.
+/// Connections are encrypted using TLS once configured with `tls()`.
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::prelude::*;
+/// # use greenhorn::server::Server;
+/// # use std::net::SocketAddr;
+/// # use std::str::FromStr;
+/// # use std::time::Duration;
+/// #
+/// # struct MyApp;
+/// # impl Render for MyApp {
+/// #     type Message = ();
+/// #     fn render(&self) -> Node<Self::Message> {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// # impl App for MyApp {
+/// #     fn update(&mut self, msg: Self::Message, ctx: Context<Self::Message>) -> Updated {
+/// #         unimplemented!()
+/// #     }
+/// # }
+/// #
+/// let addr = SocketAddr::from_str("127.0.0.1:8080").unwrap();
+/// Server::listen_to_addr(addr, || MyApp)
+///     .max_sessions(16)
+///     .idle_timeout(Duration::from_secs(600))
+///     .run_blocking();
+/// ```
+pub struct Server<A, F>
+where
+    A: 'static + App,
+    F: 'static + Fn() -> A + Send + Sync,
+{
+    listener: TcpListener,
+    factory: Arc<F>,
+    idle_timeout: Option<Duration>,
+    max_sessions: Option<usize>,
+    access: Access,
+    tls: Option<TlsConfig>,
+    compression_threshold: Option<usize>,
+    sessions: Arc<AtomicUsize>,
+    app: PhantomData<fn() -> A>,
+}
+
+impl<A, F> Server<A, F>
+where
+    A: 'static + App,
+    F: 'static + Fn() -> A + Send + Sync,
+{
+    /// Starts listening to a given `SocketAddr`
+    pub fn listen_to_addr(addr: SocketAddr, factory: F) -> Self {
+        let try_socket = task::block_on(async { TcpListener::bind(&addr).await });
+        let listener = try_socket.expect("Failed to bind");
+        Self::listen_to_socket(listener, factory)
+    }
+
+    /// Start listening to `TcpListener`.
+    ///
+    /// The `factory` function is called once for each accepted connection.
+    pub fn listen_to_socket(listener: TcpListener, factory: F) -> Self {
+        Self {
+            listener,
+            factory: Arc::new(factory),
+            idle_timeout: None,
+            max_sessions: None,
+            access: Access::new(),
+            tls: None,
+            compression_threshold: Some(DEFAULT_COMPRESSION_THRESHOLD),
+            sessions: Arc::new(AtomicUsize::new(0)),
+            app: PhantomData,
+        }
+    }
+
+    /// Closes a session if no message was received from the frontend for the given duration.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the number of concurrently running sessions.
+    pub fn max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = Some(max_sessions);
+        self
+    }
+
+    /// Only starts sessions for frontends which are granted `access`.
+    pub fn access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Only accepts TLS encrypted connections, i.e. frontends must connect using `wss://`.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sets the size in bytes above which messages are compressed.
+    ///
+    /// Refer to [WebSocketPipe::compression_threshold()](../websockets/struct.WebSocketPipe.html#method.compression_threshold).
+    pub fn compression_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.compression_threshold = threshold;
+        self
+    }
+
+    /// Returns the local address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Returns the port the server is listening on
+    pub fn port(&self) -> u16 {
+        self.local_addr().port()
+    }
+
+    /// Returns a shared counter of the currently running sessions.
+    pub fn sessions(&self) -> Arc<AtomicUsize> {
+        self.sessions.clone()
+    }
+
+    /// Accepts connections and runs a new application for each of them.
+    ///
+    /// This future does not resolve under normal operation.
+    pub async fn run(self) {
+        let mut incoming = self.listener.incoming();
+        while let Some(stream) = incoming.next().await {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("Could not accept connection: {}", err);
+                    continue;
+                }
+            };
+            if let Some(max_sessions) = self.max_sessions {
+                if self.sessions.load(Ordering::SeqCst) >= max_sessions {
+                    task::spawn(reject(stream, self.tls.clone()));
+                    continue;
+                }
+            }
+            let slot = SessionSlot::take(&self.sessions);
+            let pipe = WebSocketPipe::from_stream(
+                stream,
+                self.idle_timeout,
+                self.access.clone(),
+                self.tls.clone(),
+            )
+            .compression_threshold(self.compression_threshold);
+            let factory = self.factory.clone();
+            // the runtime is not `Send`, thus each session is run on its own thread
+            task::spawn_blocking(move || {
+                // the slot is released even if the session panics
+                let _slot = slot;
+                // a panic would otherwise abort the whole server
+                let session = panic::catch_unwind(AssertUnwindSafe(move || {
+                    let (runtime, _control) = Runtime::new(factory(), pipe);
+                    runtime.run_blocking()
+                }));
+                match session {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(err)) => error!("Session stopped: {}", err),
+                    Err(_) => error!("Session panicked"),
+                }
+            });
+        }
+    }
+
+    /// Runs the server. This function does not return under normal operation.
+    pub fn run_blocking(self) {
+        task::block_on(self.run())
+    }
+}
+
+/// Counts a running session and releases it once dropped.
+struct SessionSlot(Arc<AtomicUsize>);
+
+impl SessionSlot {
+    fn take(sessions: &Arc<AtomicUsize>) -> Self {
+        sessions.fetch_add(1, Ordering::SeqCst);
+        SessionSlot(sessions.clone())
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Completes the WebSocket handshake of a connection and closes it immediately
+/// since the session limit was reached.
+async fn reject(stream: TcpStream, tls: Option<TlsConfig>) {
+    match accept(stream, &Access::new(), tls.as_ref()).await {
+        Ok(mut ws) => {
+            let frame = CloseFrame {
+                code: CloseCode::Again,
+                reason: Cow::from("Too many sessions"),
+            };
+            let _ = ws.close(Some(frame)).await;
+        }
+        Err(err) => error!("Error during handshake: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::node::Node;
+    use crate::websockets::tests::client_handshake;
+    use crate::{Render, Updated};
+    use async_tungstenite::{client_async, WebSocketStream};
+    use tungstenite::protocol::Message;
+    use url::Url;
+
+    struct DummyApp;
+
+    impl Render for DummyApp {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html().elem("div").build()
+        }
+    }
+
+    impl App for DummyApp {
+        fn update(&mut self, _msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            Updated::no()
+        }
+    }
+
+    fn listen<F: 'static + Fn() -> DummyApp + Send + Sync>(factory: F) -> Server<DummyApp, F> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        Server::listen_to_socket(TcpListener::from(listener), factory)
+    }
+
+    async fn connect_without_handshake(port: u16) -> WebSocketStream<TcpStream> {
+        let url = Url::parse(&format!("ws://127.0.0.1:{}", port)).unwrap();
+        let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (ws, _) = client_async(url, TcpStream::from(stream)).await.unwrap();
+        ws
+    }
+
+    async fn connect(port: u16) -> WebSocketStream<TcpStream> {
+        let mut ws = connect_without_handshake(port).await;
+        client_handshake(&mut ws).await;
+        ws
+    }
+
+    #[test]
+    fn test_multiple_sessions() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let created_cloned = created.clone();
+        let server = listen(move || {
+            created_cloned.fetch_add(1, Ordering::SeqCst);
+            DummyApp
+        });
+        let port = server.port();
+        task::spawn(server.run());
+        task::block_on(async move {
+            let mut first = connect(port).await;
+            let mut second = connect(port).await;
+            // both sessions render their initial frame
+            assert!(matches!(first.next().await, Some(Ok(Message::Binary(_)))));
+            assert!(matches!(second.next().await, Some(Ok(Message::Binary(_)))));
+            first.close(None).await.unwrap();
+            second.close(None).await.unwrap();
+        });
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        let server = listen(|| DummyApp).max_sessions(1);
+        let port = server.port();
+        task::spawn(server.run());
+        task::block_on(async move {
+            let mut first = connect(port).await;
+            assert!(matches!(first.next().await, Some(Ok(Message::Binary(_)))));
+            let mut second = connect_without_handshake(port).await;
+            match second.next().await {
+                Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Again),
+                _ => panic!(),
+            }
+        });
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let server = listen(|| DummyApp).idle_timeout(Duration::from_millis(100));
+        let port = server.port();
+        let sessions = server.sessions();
+        task::spawn(server.run());
+        task::block_on(async move {
+            let mut client = connect(port).await;
+            assert!(matches!(client.next().await, Some(Ok(Message::Binary(_)))));
+            assert_eq!(sessions.load(Ordering::SeqCst), 1);
+            // the session is closed without the client sending anything
+            assert!(matches!(client.next().await, Some(Ok(Message::Close(_)))));
+            task::sleep(Duration::from_millis(100)).await;
+            assert_eq!(sessions.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn test_panicking_session_releases_slot() {
+        let server = listen(|| panic!("factory failed")).max_sessions(1);
+        let port = server.port();
+        let sessions = server.sessions();
+        task::spawn(server.run());
+        task::block_on(async move {
+            for _ in 0..2 {
+                let _client = connect_without_handshake(port).await;
+                // the session panics and its slot becomes available again
+                for _ in 0..100 {
+                    if sessions.load(Ordering::SeqCst) == 0 {
+                        break;
+                    }
+                    task::sleep(Duration::from_millis(10)).await;
+                }
+                assert_eq!(sessions.load(Ordering::SeqCst), 0);
+            }
+        });
+    }
+}
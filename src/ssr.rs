@@ -0,0 +1,226 @@
+//! Renders nodes, components and applications to static HTML.
+//!
+//! The generated HTML contains the DOM as it would be rendered by the frontend,
+//! including all nested components. Since the HTML is static, event listeners,
+//! javascript events, rpcs and blobs are omitted. Also, the element ids assigned
+//! by the runtime are not part of the output. Elements and attributes with names which cannot
+//! be written as HTML, e.g. because they contain whitespace or quotes, are omitted as well.
+//!
+//! This is useful to export a view, e.g. for emails, reports or printing, as well as to show
+//! a page before the frontend has connected to the backend, refer to `HttpServer::prerender()`.
+//!
+//! # Example
+//!
+//! ```
+//! # use greenhorn::prelude::*;
+//! # use greenhorn::ssr::render_to_string;
+//! struct Greeting(String);
+//!
+//! impl Render for Greeting {
+//!     type Message = ();
+//!
+//!     fn render(&self) -> Node<Self::Message> {
+//!         Node::html().elem("p").text(format!("Hello, {}", self.0)).build()
+//!     }
+//! }
+//!
+//! let html = render_to_string(&Greeting("<World>".into()));
+//! assert_eq!(html, "<p>Hello, &lt;World&gt;</p>");
+//! ```
+//!
+
+use crate::node::Node;
+use crate::standalone::RenderedNode;
+use crate::vdom::{VElement, VNode};
+use crate::{Component, Render};
+
+/// Elements of the HTML namespace which must not have an end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements of the HTML namespace whose content is not parsed as HTML
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Separates adjacent text nodes, which would otherwise be merged by the HTML parser
+pub(crate) const TEXT_SEPARATOR: &str = "<!---->";
+
+/// Renders `renderable` including all nested components to HTML.
+pub fn render_to_string<R: Render>(renderable: &R) -> String {
+    render_node_to_string(renderable.render())
+}
+
+/// Renders a component including all nested components to HTML.
+pub fn render_component_to_string<T: 'static + Render + Send>(component: &Component<T>) -> String {
+    render_node_to_string(component.render())
+}
+
+/// Renders a node including all nested components to HTML.
+pub fn render_node_to_string<T: 'static + Send>(node: Node<T>) -> String {
+    let mut ret = String::new();
+    write_component(&RenderedNode::new(node), None, &mut ret);
+    ret
+}
+
+/// Escapes text such that it can be used as content of an element.
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escapes text such that it can be used as double-quoted attribute value.
+pub fn escape_attribute(value: &str) -> String {
+    escape_text(value).replace('"', "&quot;")
+}
+
+/// Returns true if `name` can be used as tag or attribute name, i.e. it does not contain
+/// characters which would end the name or the tag.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '/' | '=')
+        })
+}
+
+/// Escapes the content of a raw text element, such that it does not end the element early.
+fn escape_raw_text(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find("</") {
+        ret.push_str(&rest[..idx]);
+        rest = &rest[idx + 2..];
+        let ends_element = RAW_TEXT_ELEMENTS.iter().any(|tag| {
+            rest.get(..tag.len())
+                .map(|x| x.eq_ignore_ascii_case(tag))
+                .unwrap_or(false)
+        });
+        ret.push_str(if ends_element { "<\\/" } else { "</" });
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Writes the DOM of a rendered component to `out`.
+fn write_component(rendered: &RenderedNode, namespace: Option<&str>, out: &mut String) {
+    let mut writer = Writer { rendered, out };
+    writer.nodes(&rendered.vdom, namespace, false);
+}
+
+struct Writer<'a> {
+    rendered: &'a RenderedNode,
+    out: &'a mut String,
+}
+
+impl<'a> Writer<'a> {
+    fn nodes(&mut self, nodes: &[VNode], namespace: Option<&str>, raw: bool) {
+        let mut after_text = false;
+        for node in nodes {
+            match node {
+                VNode::Element(elem) => self.element(elem, namespace),
+                VNode::Text(text) if raw => self.out.push_str(&escape_raw_text(text)),
+                VNode::Text(text) => {
+                    if after_text {
+                        self.out.push_str(TEXT_SEPARATOR);
+                    }
+                    self.out.push_str(&escape_text(text));
+                }
+                VNode::Placeholder(id, _) => {
+                    write_component(&self.rendered.component(*id), namespace, self.out);
+                }
+            }
+            after_text = matches!(node, VNode::Text(_));
+        }
+    }
+
+    fn element(&mut self, elem: &VElement, namespace: Option<&str>) {
+        if !is_valid_name(&elem.tag) {
+            log::warn!("Omitting element with invalid tag name {:?}", elem.tag);
+            return;
+        }
+        self.out.push('<');
+        self.out.push_str(&elem.tag);
+        let has_xmlns = elem.attr.iter().any(|attr| attr.key == "xmlns");
+        if elem.namespace.as_deref() != namespace && !has_xmlns {
+            if let Some(ns) = &elem.namespace {
+                self.out.push_str(" xmlns=\"");
+                self.out.push_str(&escape_attribute(ns));
+                self.out.push('"');
+            }
+        }
+        let html = elem.namespace.is_none();
+        for attr in &elem.attr {
+            if !is_valid_name(&attr.key) {
+                log::warn!("Omitting attribute with invalid name {:?}", attr.key);
+                continue;
+            }
+            if html && attr.key == "checked" {
+                // the frontend sets the `checked` property instead of the attribute
+                if attr.value == "true" {
+                    self.out.push_str(" checked");
+                }
+                continue;
+            }
+            self.out.push(' ');
+            self.out.push_str(&attr.key);
+            self.out.push_str("=\"");
+            self.out.push_str(&escape_attribute(&attr.value));
+            self.out.push('"');
+        }
+        self.out.push('>');
+        let tag = elem.tag.to_ascii_lowercase();
+        if html && VOID_ELEMENTS.contains(&tag.as_str()) {
+            return;
+        }
+        let raw = html && RAW_TEXT_ELEMENTS.contains(&tag.as_str());
+        self.nodes(&elem.children, elem.namespace.as_deref(), raw);
+        self.out.push_str("</");
+        self.out.push_str(&elem.tag);
+        self.out.push('>');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::standalone::fixture::parent;
+
+    #[test]
+    fn test_render_to_string() {
+        let parent = parent();
+        let expected = concat!(
+            "<div title=\"&quot;quoted&quot; &amp; &lt;tagged&gt;\" class=\"parent\">",
+            "<button>1 &lt; 2<!---->&amp; 3</button>",
+            "<input><input checked>",
+            "<script>if (a < b) {}</div><\\/SCRIPT></script>",
+            "<canvas></canvas>",
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"><circle r=\"5\"></circle></svg>",
+            "</div>"
+        );
+        assert_eq!(render_to_string(&parent), expected);
+        assert_eq!(
+            render_component_to_string(&parent.child),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"><circle r=\"5\"></circle></svg>"
+        );
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let node: Node<()> = Node::html()
+            .elem("div")
+            .attr("onclick=alert(1) x", "y")
+            .attr("id", "a")
+            .add(Node::html().elem("img src=x").build())
+            .add(Node::html().elem("p").build())
+            .build();
+        assert_eq!(render_node_to_string(node), "<div id=\"a\"><p></p></div>");
+    }
+
+    #[test]
+    fn test_escape_raw_text() {
+        assert_eq!(escape_raw_text("a</b></script"), "a</b><\\/script");
+        assert_eq!(escape_raw_text("</Style>"), "<\\/Style>");
+        assert_eq!(escape_raw_text("</scrip"), "</scrip");
+    }
+}
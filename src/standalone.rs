@@ -0,0 +1,166 @@
+//! Renders nodes without a runtime, such that they can be written in a different format,
+//! e.g. as static HTML by the [ssr](../ssr/index.html) module or as test snapshots.
+//!
+
+use crate::blob::Blob;
+use crate::component::ComponentMap;
+use crate::context::Context;
+use crate::node::Node;
+use crate::runtime::{render_component, ResultItem};
+use crate::vdom::VNode;
+use crate::{App, Id, Render, Updated};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Allows rendering nodes with any message type, without an actual application.
+struct NodeApp<T>(PhantomData<fn() -> T>);
+
+impl<T: 'static + Send> Render for NodeApp<T> {
+    type Message = T;
+
+    fn render(&self) -> Node<Self::Message> {
+        unreachable!()
+    }
+}
+
+impl<T: 'static + Send> App for NodeApp<T> {
+    fn update(&mut self, _msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+        unreachable!()
+    }
+}
+
+/// Non-DOM items emitted by rendering a node, except for nested components.
+pub(crate) enum Item {
+    /// A subscription to the event `event`, which emits values of type `value_type`
+    Subscription {
+        event: Id,
+        value_type: &'static str,
+    },
+    Blob(Blob),
+    /// An rpc registered on the element with the given id
+    Rpc(Id),
+}
+
+/// The DOM of a node rendered without a runtime, along with its non-DOM items.
+///
+/// Nested components are represented by `VNode::Placeholder` and rendered on demand using `component()`.
+pub(crate) struct RenderedNode {
+    pub(crate) vdom: Vec<VNode>,
+    pub(crate) items: Vec<Item>,
+    components: HashMap<Id, Box<dyn Fn() -> RenderedNode>>,
+}
+
+impl RenderedNode {
+    pub(crate) fn new<T: 'static + Send>(node: Node<T>) -> Self {
+        let mut result = Vec::new();
+        let vdom = render_component::<NodeApp<T>>(node, &mut result, None);
+        let mut items = Vec::new();
+        let mut components: HashMap<Id, Box<dyn Fn() -> RenderedNode>> = HashMap::new();
+        for item in result {
+            match item {
+                ResultItem::Subscription(event, subscription) => items.push(Item::Subscription {
+                    event,
+                    value_type: subscription.value_type(),
+                }),
+                ResultItem::Blob(blob) => items.push(Item::Blob(blob)),
+                ResultItem::Rpc(rpc) => items.push(Item::Rpc(rpc.node_id)),
+                ResultItem::Component(comp, _) => {
+                    let id = comp.id();
+                    components.insert(id, Box::new(move || RenderedNode::new(comp.render())));
+                }
+                ResultItem::Listener(_) | ResultItem::Memo(_, _) => {}
+            }
+        }
+        Self {
+            vdom,
+            items,
+            components,
+        }
+    }
+
+    /// Renders the nested component which is represented by the placeholder `id`.
+    pub(crate) fn component(&self, id: Id) -> RenderedNode {
+        (self.components[&id])()
+    }
+}
+
+/// Components exercising the different kinds of nodes, shared by the tests of the output formats.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use crate::blob::Blob;
+    use crate::event::Event;
+    use crate::node::Node;
+    use crate::{Component, Render};
+
+    pub(crate) struct Child {
+        pub(crate) clicked: Event<()>,
+        pub(crate) checked: Event<bool>,
+    }
+
+    impl Render for Child {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::svg()
+                .elem("svg")
+                .add(Node::svg().elem("circle").attr("r", 5).build())
+                .build()
+        }
+    }
+
+    pub(crate) struct Parent {
+        pub(crate) child: Component<Child>,
+        pub(crate) blob: Blob,
+    }
+
+    impl Render for Parent {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            let child = self.child.lock();
+            Node::html()
+                .elem("div")
+                .class("parent")
+                .attr("title", "\"quoted\" & <tagged>")
+                .add(
+                    Node::html()
+                        .elem("button")
+                        .listener("click", |_| ())
+                        .prevent_default()
+                        .build()
+                        .js_event("onmouseover", "highlight()")
+                        .text("1 < 2")
+                        .text("& 3")
+                        .build(),
+                )
+                .add(Node::html().elem("input").attr("checked", "false").build())
+                .add(Node::html().elem("input").attr("checked", "true").build())
+                .add(
+                    Node::html()
+                        .elem("script")
+                        .text("if (a < b) {}</div></SCRIPT>")
+                        .build(),
+                )
+                .add(Node::html().elem("canvas").key("c").rpc(|_| ()).build())
+                .add(self.child.mount())
+                .add(child.clicked.subscribe(|_| ()))
+                .add(child.checked.subscribe(|_| ()))
+                .add(child.clicked.subscribe(|_| ()))
+                .add(&self.blob)
+                .build()
+        }
+    }
+
+    pub(crate) fn parent() -> Parent {
+        Parent {
+            child: Component::new(Child {
+                clicked: Event::new(),
+                checked: Event::new(),
+            }),
+            blob: Blob::build(42)
+                .mime_type("image/png")
+                .data(vec![1, 2, 3])
+                .build(),
+        }
+    }
+}
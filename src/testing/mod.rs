@@ -0,0 +1,657 @@
+//! This module allows testing applications without a browser.
+//!
+//! A [TestHarness](struct.TestHarness.html) executes an application in a
+//! [Runtime](../runtime/struct.Runtime.html) which is connected to an in-process frontend.
+//! This frontend applies the patches sent by the runtime to a
+//! [Document](../patch/struct.Document.html) and acknowledges each frame, just like
+//! the javascript frontend does. Tests look up elements in the document, fire synthetic
+//! [DomEvents](../dom/enum.DomEvent.html) at them and assert on the rendered HTML.
+//!
+//! The underlying [TestPipe](struct.TestPipe.html) may also be used on its own to
+//! drive a `Runtime` message by message.
+//!
+//! Session recordings are replayed using [Replay](struct.Replay.html).
+//!
+//! Furthermore, components may be rendered to deterministic text snapshots using
+//! [snapshot()](fn.snapshot.html), which are compared against snapshot files
+//! using [assert_snapshot()](fn.assert_snapshot.html).
+//!
+//! # Example
+//!
+//! ```
+//! use greenhorn::prelude::*;
+//! use greenhorn::testing::TestHarness;
+//!
+//! struct Counter(u32);
+//!
+//! impl Render for Counter {
+//!     type Message = ();
+//!
+//!     fn render(&self) -> Node<Self::Message> {
+//!         Node::html()
+//!             .elem("button")
+//!             .id("increment")
+//!             .on("click", |_| ())
+//!             .text(self.0.to_string())
+//!             .build()
+//!     }
+//! }
+//!
+//! impl App for Counter {
+//!     fn update(&mut self, _msg: (), _ctx: Context<()>) -> Updated {
+//!         self.0 += 1;
+//!         Updated::yes()
+//!     }
+//! }
+//!
+//! let mut harness = TestHarness::new(|| Counter(0));
+//! harness.idle_blocking().unwrap();
+//! harness.click(harness.find_by_id("increment").unwrap());
+//! harness.idle_blocking().unwrap();
+//! assert_eq!(harness.html(), "<button id=\"increment\" @click>1</button>");
+//! ```
+//!
+
+mod replay;
+mod snapshot;
+
+pub use replay::{Divergence, Replay, ReplayReport};
+pub use snapshot::{
+    assert_snapshot, snapshot, snapshot_component, snapshot_node, UPDATE_SNAPSHOTS_ENV,
+};
+
+use crate::dom::{
+    BaseEvent, DomEvent, InputValue, KeyboardEvent, ModifierState, MouseEvent, Point,
+};
+use crate::patch::{DecodedPatch, Document, Element};
+use crate::pipe::{Pipe, RxMsg, TxMsg};
+use crate::runtime::metrics::Metrics;
+use crate::runtime::{RuntimeBuilder, RuntimeControl, RuntimeError, RuntimeStatus};
+use crate::{App, Id};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::task::{Context, Poll};
+use futures::{select, FutureExt, Sink, Stream, StreamExt};
+use std::error::Error;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+
+/// Sender of a [TestPipe](struct.TestPipe.html)
+#[derive(Clone)]
+pub struct TestSender(pub(crate) UnboundedSender<TxMsg>);
+
+impl Sink<TxMsg> for TestSender {
+    type Error = Box<dyn Error>;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0)
+            .poll_ready(cx)
+            .map_err(|x| Box::new(x).into())
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: TxMsg) -> Result<(), Self::Error> {
+        Pin::new(&mut self.0)
+            .start_send(item)
+            .map_err(|x| Box::new(x).into())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0)
+            .poll_flush(cx)
+            .map_err(|x| Box::new(x).into())
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.0)
+            .poll_close(cx)
+            .map_err(|x| Box::new(x).into())
+    }
+}
+
+/// Receiver of a [TestPipe](struct.TestPipe.html)
+///
+/// Keeps track of the messages which were sent by the frontend but not yet received by the runtime.
+pub struct TestReceiver {
+    rx: UnboundedReceiver<RxMsg>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Stream for TestReceiver {
+    type Item = RxMsg;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let ret = Pin::new(&mut self.rx).poll_next(cx);
+        if let Poll::Ready(Some(_)) = ret {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        ret
+    }
+}
+
+/// A [Pipe](../pipe/trait.Pipe.html) connecting a runtime to a [TestFrontend](struct.TestFrontend.html)
+/// within the same process.
+pub struct TestPipe {
+    sender: TestSender,
+    receiver: TestReceiver,
+}
+
+impl TestPipe {
+    /// Creates a new pipe along with the frontend it is connected to.
+    pub fn new() -> (TestPipe, TestFrontend) {
+        let (sender_tx, sender_rx) = unbounded();
+        let (receiver_tx, receiver_rx) = unbounded();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let pipe = TestPipe {
+            sender: TestSender(sender_tx),
+            receiver: TestReceiver {
+                rx: receiver_rx,
+                pending: pending.clone(),
+            },
+        };
+        let frontend = TestFrontend {
+            rx: sender_rx,
+            tx: receiver_tx,
+            pending,
+        };
+        (pipe, frontend)
+    }
+}
+
+impl Pipe for TestPipe {
+    type Sender = TestSender;
+    type Receiver = TestReceiver;
+
+    fn split(self) -> (Self::Sender, Self::Receiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+/// The frontend side of a [TestPipe](struct.TestPipe.html).
+pub struct TestFrontend {
+    rx: UnboundedReceiver<TxMsg>,
+    tx: UnboundedSender<RxMsg>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl TestFrontend {
+    /// Sends a message to the runtime. Messages sent after the runtime has stopped are dropped.
+    pub fn send(&self, msg: RxMsg) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self.tx.unbounded_send(msg).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns the number of messages sent to the runtime which it has not yet received.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Receives the next message from the runtime.
+    ///
+    /// Resolves to `None` once the runtime has stopped.
+    pub async fn next(&mut self) -> Option<TxMsg> {
+        self.rx.next().await
+    }
+
+    /// Returns a message from the runtime if one is available without waiting.
+    pub fn try_next(&mut self) -> Option<TxMsg> {
+        self.rx.try_next().ok().flatten()
+    }
+}
+
+/// Runs an application against an in-process frontend.
+///
+/// The runtime is executed on its own thread, thus the application is created by a
+/// `factory` function on that thread, similar to a [Server](../server/struct.Server.html).
+///
+/// Patches are only applied while the harness processes messages, i.e. while waiting
+/// for the runtime to become idle using `idle()`. Non-patch messages, such as loaded
+/// css or dialogs, are recorded and returned by `messages()`.
+///
+/// Dropping the harness quits the application and waits for the runtime thread to finish.
+pub struct TestHarness<A: 'static + App> {
+    control: RuntimeControl<A>,
+    frontend: TestFrontend,
+    document: Document,
+    messages: Vec<TxMsg>,
+    handle: Option<JoinHandle<Result<Metrics, RuntimeError>>>,
+}
+
+impl<A: 'static + App> TestHarness<A> {
+    /// Creates an application using `factory` and starts running it.
+    pub fn new<F: 'static + FnOnce() -> A + Send>(factory: F) -> Self {
+        Self::new_with_runtime(factory, |runtime| runtime)
+    }
+
+    /// Same as `new()`, but allows configuring the runtime before it is started,
+    /// e.g. to record the session.
+    pub fn new_with_runtime<F, C>(factory: F, configure: C) -> Self
+    where
+        F: 'static + FnOnce() -> A + Send,
+        C: 'static + FnOnce(RuntimeBuilder<A, TestPipe>) -> RuntimeBuilder<A, TestPipe> + Send,
+    {
+        let (pipe, frontend) = TestPipe::new();
+        let (control_tx, control_rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let (runtime, control) = configure(RuntimeBuilder::new(factory(), pipe)).build();
+            control_tx.send(control).unwrap();
+            runtime.run_blocking()
+        });
+        let control = control_rx.recv().expect("Failed to create the application");
+        Self {
+            control,
+            frontend,
+            document: Document::new(),
+            messages: Vec::new(),
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns a control object of the runtime executing the application.
+    pub fn control(&self) -> &RuntimeControl<A> {
+        &self.control
+    }
+
+    /// Sends a message into the update cycle of the application.
+    pub fn update(&self, msg: A::Message) -> Result<(), RuntimeError> {
+        self.control.update(msg)
+    }
+
+    /// Processes messages from the runtime until it becomes idle.
+    ///
+    /// The runtime is idle once all messages sent to it have been processed, no render
+    /// is scheduled and the last frame was applied to the document.
+    /// Note that this future does not resolve if the application keeps re-rendering,
+    /// e.g. because it subscribed to a stream which updates the application periodically.
+    pub async fn idle(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            self.process_messages();
+            // messages received by the runtime are processed before the idle request, hence
+            // the runtime may only signal idleness early if messages are still on their way
+            let pending = self.frontend.pending();
+            let mut idle = Box::pin(self.control.wait_for(RuntimeStatus::is_idle).fuse());
+            loop {
+                let msg = select! {
+                    result = idle => {
+                        result?;
+                        break;
+                    },
+                    msg = self.frontend.next().fuse() => msg,
+                };
+                match msg {
+                    Some(msg) => self.handle(msg),
+                    None => return Err(RuntimeError::Stopped),
+                }
+            }
+            if pending == 0 {
+                self.process_messages();
+                return Ok(());
+            }
+        }
+    }
+
+    /// Blocks until the runtime becomes idle. Refer to [idle()](#method.idle).
+    pub fn idle_blocking(&mut self) -> Result<(), RuntimeError> {
+        async_std::task::block_on(self.idle())
+    }
+
+    /// Quits the application and returns the performance metrics collected by the runtime.
+    pub fn quit(mut self) -> Result<Metrics, RuntimeError> {
+        // the runtime may have stopped already, in which case its result is returned below
+        let _ = self.control.quit();
+        let handle = self.handle.take().expect("Runtime was already joined");
+        handle.join().expect("Runtime panicked")
+    }
+
+    /// Returns the document as currently shown by the frontend.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Returns the HTML-like representation of the document.
+    ///
+    /// Refer to the `Display` implementation of [Node](../patch/enum.Node.html).
+    pub fn html(&self) -> String {
+        self.document.to_string()
+    }
+
+    /// Returns the messages other than patches which the frontend has received.
+    pub fn messages(&self) -> &[TxMsg] {
+        &self.messages
+    }
+
+    /// Returns the received messages and clears them.
+    pub fn take_messages(&mut self) -> Vec<TxMsg> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Returns the element with the given `id` attribute.
+    pub fn find_by_id(&self, id: &str) -> Option<&Element> {
+        self.document
+            .find_all(|x| x.attributes.get("id").map(|x| x == id).unwrap_or(false))
+            .into_iter()
+            .next()
+    }
+
+    /// Returns all elements with the given class in document order.
+    pub fn find_by_class(&self, class: &str) -> Vec<&Element> {
+        self.document.find_all(|x| x.has_class(class))
+    }
+
+    /// Returns all elements with the given tag name in document order.
+    pub fn find_by_tag(&self, tag: &str) -> Vec<&Element> {
+        self.document.find_all(|x| x.tag == tag)
+    }
+
+    /// Sends a message to the runtime as if it was sent by the frontend.
+    pub fn send(&self, msg: RxMsg) {
+        self.frontend.send(msg);
+    }
+
+    /// Fires an event, as if it was triggered on the frontend.
+    pub fn fire(&self, event: DomEvent) {
+        self.send(RxMsg::Event(event));
+    }
+
+    /// Clicks the given element.
+    ///
+    /// Panics if the element does not listen to `click` events.
+    pub fn click(&self, elem: &Element) {
+        let target = target(elem, "click");
+        self.fire(DomEvent::Mouse(MouseEvent {
+            target,
+            event_name: "click".to_string(),
+            modifier_state: no_modifiers(),
+            button: 0,
+            buttons: 0,
+            client: Point { x: 0, y: 0 },
+            offset: Point { x: 0, y: 0 },
+            page: Point { x: 0, y: 0 },
+            screen: Point { x: 0, y: 0 },
+            target_value: target_value(elem),
+        }));
+    }
+
+    /// Presses `key` while the given element is focused.
+    ///
+    /// Panics if the element does not listen to `keydown` events.
+    pub fn keydown(&self, elem: &Element, key: &str) {
+        let target = target(elem, "keydown");
+        self.fire(DomEvent::Keyboard(KeyboardEvent {
+            target,
+            event_name: "keydown".to_string(),
+            modifier_state: no_modifiers(),
+            code: key_code(key),
+            key: key.to_string(),
+            location: 0,
+            repeat: false,
+            target_value: target_value(elem),
+        }));
+    }
+
+    /// Enters `value` into the given element.
+    ///
+    /// Panics if the element does not listen to `input` events.
+    pub fn input(&self, elem: &Element, value: &str) {
+        let target = target(elem, "input");
+        self.fire(DomEvent::Base(BaseEvent {
+            target,
+            event_name: "input".to_string(),
+            target_value: InputValue::Text(value.to_string()),
+        }));
+    }
+
+    /// Handles all messages from the runtime which are available without waiting.
+    fn process_messages(&mut self) {
+        while let Some(msg) = self.frontend.try_next() {
+            self.handle(msg);
+        }
+    }
+
+    fn handle(&mut self, msg: TxMsg) {
+        match msg {
+            TxMsg::Patch(data) => {
+                // a patch which cannot be applied is a bug in the backend
+                let patch = DecodedPatch::decode(&data).expect("Received an invalid patch");
+                if let Err(err) = self.document.apply(&patch) {
+                    panic!("{}\n{}", err, patch);
+                }
+                self.send(RxMsg::FrameApplied(patch.frame, Default::default()));
+            }
+            TxMsg::Ping() => {}
+            msg => self.messages.push(msg),
+        }
+    }
+}
+
+impl<A: 'static + App> Drop for TestHarness<A> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = self.control.quit();
+            // the result is of no interest and a panic of the runtime was reported already
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Returns the physical key code of `key` on a US keyboard layout, as reported in
+/// `KeyboardEvent.code` by browsers.
+///
+/// Named keys, such as `Enter` or `ArrowUp`, share their name with their code.
+fn key_code(key: &str) -> String {
+    let mut chars = key.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return key.to_string(),
+    };
+    let code = match c {
+        'a'..='z' | 'A'..='Z' => return format!("Key{}", c.to_ascii_uppercase()),
+        '0'..='9' => return format!("Digit{}", c),
+        ' ' => "Space",
+        '-' => "Minus",
+        '=' => "Equal",
+        '[' => "BracketLeft",
+        ']' => "BracketRight",
+        '\\' => "Backslash",
+        ';' => "Semicolon",
+        '\'' => "Quote",
+        '`' => "Backquote",
+        ',' => "Comma",
+        '.' => "Period",
+        '/' => "Slash",
+        _ => return key.to_string(),
+    };
+    code.to_string()
+}
+
+/// Returns the id of `elem` which is used to fire the event `name`.
+fn target(elem: &Element, name: &str) -> Id {
+    match elem.id {
+        Some(id) if elem.listener(name).is_some() => Id::new_from_data(id),
+        _ => panic!(
+            "Element <{}> does not listen to `{}` events",
+            elem.tag, name
+        ),
+    }
+}
+
+/// The value of the element as reported by the javascript frontend.
+fn target_value(elem: &Element) -> InputValue {
+    match elem.attributes.get("value") {
+        Some(value) => InputValue::Text(value.clone()),
+        None => InputValue::NoValue,
+    }
+}
+
+fn no_modifiers() -> ModifierState {
+    ModifierState {
+        alt_key: false,
+        ctrl_key: false,
+        meta_key: false,
+        shift_key: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::node::Node;
+    use crate::{Render, Updated};
+    use assert_matches::assert_matches;
+
+    enum Msg {
+        Add,
+        Key(String),
+        Input(String),
+    }
+
+    #[derive(Default)]
+    struct TodoApp {
+        items: Vec<String>,
+        text: String,
+    }
+
+    impl Render for TodoApp {
+        type Message = Msg;
+
+        fn render(&self) -> Node<Self::Message> {
+            let items = self.items.iter().map(|x| {
+                Node::html()
+                    .elem("li")
+                    .class("item")
+                    .text(x.clone())
+                    .build()
+            });
+            Node::html()
+                .elem("div")
+                .add(
+                    Node::html()
+                        .elem("input")
+                        .id("text")
+                        .attr("value", &self.text)
+                        .on("input", |evt| {
+                            Msg::Input(evt.target_value().get_text().unwrap())
+                        })
+                        .on("keydown", |evt| match evt {
+                            DomEvent::Keyboard(evt) => Msg::Key(evt.key),
+                            _ => panic!(),
+                        })
+                        .build(),
+                )
+                .add(
+                    Node::html()
+                        .elem("button")
+                        .id("add")
+                        .on("click", |_| Msg::Add)
+                        .text("Add")
+                        .build(),
+                )
+                .add(
+                    Node::html()
+                        .elem("ul")
+                        .add(items.collect::<Vec<_>>())
+                        .build(),
+                )
+                .build()
+        }
+    }
+
+    impl App for TodoApp {
+        fn update(&mut self, msg: Self::Message, ctx: Context<Self::Message>) -> Updated {
+            match msg {
+                Msg::Input(text) => self.text = text,
+                Msg::Key(key) if key == "Enter" => {
+                    self.items.push(std::mem::take(&mut self.text));
+                    ctx.load_css("li {}");
+                }
+                Msg::Key(_) => return Updated::no(),
+                Msg::Add => self.items.push(std::mem::take(&mut self.text)),
+            }
+            Updated::yes()
+        }
+    }
+
+    struct QuitNotifier(mpsc::Sender<()>);
+
+    impl Render for QuitNotifier {
+        type Message = ();
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html().elem("div").build()
+        }
+    }
+
+    impl App for QuitNotifier {
+        fn update(&mut self, _msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            Updated::no()
+        }
+
+        fn on_quit(&mut self, _ctx: Context<Self::Message>) {
+            self.0.send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_harness() {
+        let mut harness = TestHarness::new(TodoApp::default);
+        harness.idle_blocking().unwrap();
+        assert_eq!(harness.find_by_tag("li").len(), 0);
+
+        harness.input(harness.find_by_id("text").unwrap(), "first");
+        harness.click(harness.find_by_id("add").unwrap());
+        harness.idle_blocking().unwrap();
+        let items = harness.find_by_class("item");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].to_string(), "<li class=\"item\">first</li>");
+
+        harness.input(harness.find_by_id("text").unwrap(), "second");
+        harness.idle_blocking().unwrap();
+        let input = harness.find_by_id("text").unwrap();
+        assert_eq!(input.attributes.get("value").unwrap(), "second");
+        harness.keydown(input, "Enter");
+        harness.idle_blocking().unwrap();
+        let items: Vec<_> = harness.find_by_tag("li").iter().map(|x| x.text()).collect();
+        assert_eq!(items, vec!["first", "second"]);
+        assert_matches!(harness.take_messages().as_slice(), [TxMsg::LoadCss(css)] if css == "li {}");
+        assert!(harness.messages().is_empty());
+        harness.quit().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "does not listen to `click` events")]
+    fn test_click_without_listener() {
+        let mut harness = TestHarness::new(TodoApp::default);
+        harness.idle_blocking().unwrap();
+        harness.click(harness.find_by_id("text").unwrap());
+    }
+
+    #[test]
+    fn test_key_code() {
+        assert_eq!(key_code("a"), "KeyA");
+        assert_eq!(key_code("Q"), "KeyQ");
+        assert_eq!(key_code("7"), "Digit7");
+        assert_eq!(key_code(" "), "Space");
+        assert_eq!(key_code("/"), "Slash");
+        assert_eq!(key_code("Enter"), "Enter");
+        assert_eq!(key_code("ArrowUp"), "ArrowUp");
+    }
+
+    #[test]
+    fn test_drop_joins_runtime() {
+        let (tx, rx) = mpsc::channel();
+        let harness = TestHarness::new(move || QuitNotifier(tx));
+        drop(harness);
+        assert_matches!(rx.try_recv(), Ok(()));
+    }
+
+    #[test]
+    fn test_quit_after_stop() {
+        let mut harness = TestHarness::new(TodoApp::default);
+        harness.control().quit().unwrap();
+        assert_eq!(harness.idle_blocking(), Err(RuntimeError::Stopped));
+        assert!(harness.quit().is_ok());
+    }
+}
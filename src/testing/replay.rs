@@ -0,0 +1,532 @@
+use crate::dom::DomEvent;
+use crate::patch::{DecodedPatch, Instruction, Node};
+use crate::pipe::{RxMsg, TxMsg};
+use crate::runtime::recording::{self, Entry, Record};
+use crate::runtime::{RuntimeBuilder, RuntimeControl, RuntimeError, RuntimeStatus};
+use crate::testing::{TestFrontend, TestPipe};
+use crate::{App, Id};
+use futures::{select, FutureExt};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Time to wait for a recorded patch to be emitted by the replayed application
+const PATCH_TIMEOUT_MS: u64 = 1000;
+
+/// Replays a session [recording](../runtime/recording/index.html) with a fresh application.
+///
+/// The recorded inputs are fed into the application in the same order as they were recorded,
+/// but without waiting for the recorded timing. Futures, streams and services spawned by
+/// the application are not executed, instead their recorded results are used.
+/// Also, the application is only rendered at the recorded points in time.
+/// If the recording contains patches, i.e. it was recorded using
+/// `RuntimeBuilder::record_with_patches()`, each emitted patch is compared against the recorded
+/// one. Since element ids differ between sessions, ids of recorded events are translated to the
+/// ids of the replay.
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::prelude::*;
+/// # use greenhorn::testing::Replay;
+/// # struct MyApp;
+/// # impl Render for MyApp {
+/// #     type Message = ();
+/// #     fn render(&self) -> Node<Self::Message> {
+/// #         Node::html().elem("div").build()
+/// #     }
+/// # }
+/// # impl App for MyApp {
+/// #     fn update(&mut self, _msg: (), _ctx: Context<()>) -> Updated {
+/// #         Updated::yes()
+/// #     }
+/// # }
+/// let replay = Replay::from_file("session.rec").unwrap();
+/// let report = replay.run_blocking(|| MyApp).unwrap();
+/// for divergence in &report.divergences {
+///     println!("{}", divergence);
+/// }
+/// ```
+pub struct Replay {
+    records: Vec<Record>,
+}
+
+impl Replay {
+    pub fn new(records: Vec<Record>) -> Self {
+        Self { records }
+    }
+
+    /// Reads a recording from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        recording::read(BufReader::new(file)).map(Self::new)
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Replays the recording with an application created by `factory`.
+    ///
+    /// Fails if the runtime stops before the recording was replayed completely.
+    pub async fn run<A, F>(&self, factory: F) -> Result<ReplayReport, RuntimeError>
+    where
+        A: 'static + App,
+        A::Message: DeserializeOwned,
+        F: 'static + FnOnce() -> A + Send,
+    {
+        let (pipe, frontend) = TestPipe::new();
+        let (control_tx, control_rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let (runtime, control) = RuntimeBuilder::new(factory(), pipe).replaying().build();
+            control_tx.send(control).unwrap();
+            runtime.run_blocking()
+        });
+        let control = control_rx.recv().expect("Failed to create the application");
+        let mut replayer = Replayer {
+            control,
+            frontend,
+            compare_patches: self
+                .records
+                .iter()
+                .any(|x| matches!(x.entry, Entry::Patch(_))),
+            patches: VecDeque::new(),
+            ids: HashMap::new(),
+            frames: HashMap::new(),
+            report: ReplayReport::default(),
+        };
+        let result = replayer.replay(&self.records).await;
+        let Replayer {
+            control,
+            frontend,
+            report,
+            ..
+        } = replayer;
+        let _ = control.quit();
+        drop(frontend);
+        let _ = handle.join();
+        result.map(|_| report)
+    }
+
+    /// Blocks until the recording was replayed. Refer to [run()](#method.run).
+    pub fn run_blocking<A, F>(&self, factory: F) -> Result<ReplayReport, RuntimeError>
+    where
+        A: 'static + App,
+        A::Message: DeserializeOwned,
+        F: 'static + FnOnce() -> A + Send,
+    {
+        async_std::task::block_on(self.run(factory))
+    }
+}
+
+/// Result of a [Replay](struct.Replay.html).
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Number of patches compared against the recording
+    pub patches: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+    /// Returns true if the replay behaved differently than the recording.
+    pub fn diverged(&self) -> bool {
+        !self.divergences.is_empty()
+    }
+}
+
+/// Describes where a replay differs from the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index of the record the replay diverged at
+    pub record: usize,
+    pub reason: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Record {}: {}", self.record, self.reason)
+    }
+}
+
+struct Replayer<A: 'static + App> {
+    control: RuntimeControl<A>,
+    frontend: TestFrontend,
+    /// True if the recording contains patches to compare against
+    compare_patches: bool,
+    /// Patches emitted by the replay which were not yet compared
+    patches: VecDeque<Vec<u8>>,
+    /// Maps ids of the recording to ids of the replay
+    ids: HashMap<u64, u64>,
+    /// Maps frame numbers of the recording to frame numbers of the replay
+    frames: HashMap<u64, u64>,
+    report: ReplayReport,
+}
+
+impl<A: 'static + App> Replayer<A>
+where
+    A::Message: DeserializeOwned,
+{
+    async fn replay(&mut self, records: &[Record]) -> Result<(), RuntimeError> {
+        for (idx, record) in records.iter().enumerate() {
+            match &record.entry {
+                // frontend messages are parsed from text, just like when received by a pipe,
+                // since `from_value()` fails for empty tuple variants such as `Connected()`
+                Entry::Frontend(value) => match serde_json::from_str(&value.to_string()) {
+                    Ok(msg) => {
+                        let msg = self.translate(msg);
+                        self.frontend.send(msg);
+                    }
+                    Err(err) => self.diverge(idx, format!("Invalid frontend message: {}", err)),
+                },
+                Entry::Update(value) | Entry::Async(value) | Entry::Service(value) => {
+                    match serde_json::from_value(value.clone()) {
+                        Ok(msg) => self.control.update(msg)?,
+                        Err(err) => self.diverge(idx, format!("Invalid message: {}", err)),
+                    }
+                }
+                Entry::Render => self.control.render()?,
+                Entry::Patch(expected) => {
+                    let actual = self.next_patch().await;
+                    self.compare(idx, expected, actual);
+                    continue;
+                }
+            }
+            self.settle().await?;
+        }
+        self.settle().await?;
+        while let Some(patch) = self.patches.pop_front() {
+            self.diverge(records.len(), describe("Unexpected patch", &patch));
+        }
+        Ok(())
+    }
+
+    /// Waits until the runtime has processed all messages and sent all rendered patches.
+    async fn settle(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            self.receive();
+            // refer to `TestHarness::idle()`
+            let pending = self.frontend.pending();
+            let mut rendered = Box::pin(self.control.wait_for(RuntimeStatus::is_rendered).fuse());
+            loop {
+                let msg = select! {
+                    result = rendered => {
+                        result?;
+                        break;
+                    },
+                    msg = self.frontend.next().fuse() => msg,
+                };
+                match msg {
+                    Some(msg) => self.handle(msg),
+                    None => return Err(RuntimeError::Stopped),
+                }
+            }
+            if pending == 0 {
+                self.receive();
+                return Ok(());
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        while let Some(msg) = self.frontend.try_next() {
+            self.handle(msg);
+        }
+    }
+
+    fn handle(&mut self, msg: TxMsg) {
+        match msg {
+            TxMsg::Patch(data) if self.compare_patches => self.patches.push_back(data),
+            _ => {}
+        }
+    }
+
+    /// Returns the next patch emitted by the replay.
+    ///
+    /// A patch is sent after the runtime has finished rendering, thus it may take a moment to arrive.
+    async fn next_patch(&mut self) -> Option<Vec<u8>> {
+        let timeout = Duration::from_millis(PATCH_TIMEOUT_MS);
+        while self.patches.is_empty() {
+            match async_std::future::timeout(timeout, self.frontend.next()).await {
+                Ok(Some(msg)) => self.handle(msg),
+                _ => return None,
+            }
+        }
+        self.patches.pop_front()
+    }
+
+    fn compare(&mut self, idx: usize, expected: &[u8], actual: Option<Vec<u8>>) {
+        let actual = match actual {
+            Some(actual) => actual,
+            None => return self.diverge(idx, "The recorded patch was not emitted".into()),
+        };
+        self.report.patches += 1;
+        let (expected, actual) = match (
+            DecodedPatch::decode(expected),
+            DecodedPatch::decode(&actual),
+        ) {
+            (Ok(expected), Ok(actual)) => (expected, actual),
+            (Err(err), _) | (_, Err(err)) => {
+                return self.diverge(idx, format!("Failed to decode patch: {}", err))
+            }
+        };
+        self.frames.insert(expected.frame, actual.frame);
+        let matches = expected.instructions.len() == actual.instructions.len()
+            && expected
+                .instructions
+                .iter()
+                .zip(&actual.instructions)
+                .all(|(x, y)| unify(x, y, &mut self.ids));
+        if !matches {
+            let reason = format!(
+                "Patch differs from the recording\n--- expected\n{}+++ actual\n{}",
+                expected, actual
+            );
+            self.diverge(idx, reason);
+        }
+    }
+
+    fn diverge(&mut self, record: usize, reason: String) {
+        self.report.divergences.push(Divergence { record, reason });
+    }
+
+    /// Translates the ids and frame numbers of a recorded message to those of the replay.
+    fn translate(&self, msg: RxMsg) -> RxMsg {
+        match msg {
+            RxMsg::Event(mut evt) => {
+                let target = match &mut evt {
+                    DomEvent::Base(evt) => &mut evt.target,
+                    DomEvent::Focus(evt) => &mut evt.target,
+                    DomEvent::Keyboard(evt) => &mut evt.target,
+                    DomEvent::Mouse(evt) => &mut evt.target,
+                    DomEvent::Wheel(evt) => &mut evt.target,
+                };
+                *target = Id::new_from_data(self.translate_id(target.data()));
+                RxMsg::Event(evt)
+            }
+            RxMsg::ElementRpc(id, value) => RxMsg::ElementRpc(self.translate_id(id), value),
+            RxMsg::FrameApplied(frame, timing) => {
+                RxMsg::FrameApplied(*self.frames.get(&frame).unwrap_or(&frame), timing)
+            }
+            RxMsg::PatchFailed(frame, reason) => {
+                RxMsg::PatchFailed(*self.frames.get(&frame).unwrap_or(&frame), reason)
+            }
+            msg => msg,
+        }
+    }
+
+    fn translate_id(&self, id: u64) -> u64 {
+        *self.ids.get(&id).unwrap_or(&id)
+    }
+}
+
+/// Describes a patch which could not be matched with the recording.
+fn describe(what: &str, data: &[u8]) -> String {
+    match DecodedPatch::decode(data) {
+        Ok(patch) => format!("{}\n{}", what, patch),
+        Err(err) => format!("{}: {}", what, err),
+    }
+}
+
+/// Compares two instructions, considering ids equal if they consistently map from
+/// the recording to the replay. Newly encountered ids are added to `ids`.
+fn unify(expected: &Instruction, actual: &Instruction, ids: &mut HashMap<u64, u64>) -> bool {
+    match (expected, actual) {
+        (Instruction::AppendSibling(x), Instruction::AppendSibling(y))
+        | (Instruction::Replace(x), Instruction::Replace(y))
+        | (Instruction::Hydrate(x), Instruction::Hydrate(y)) => unify_node(x, y, ids),
+        (Instruction::AddChildren(x), Instruction::AddChildren(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| unify_node(x, y, ids))
+        }
+        (Instruction::InsertChild(i, x), Instruction::InsertChild(j, y)) => {
+            i == j && unify_node(x, y, ids)
+        }
+        (Instruction::AddBlob(i, x), Instruction::AddBlob(j, y)) => unify_id(*i, *j, ids) && x == y,
+        (Instruction::RemoveBlob(i), Instruction::RemoveBlob(j)) => unify_id(*i, *j, ids),
+        // checksums cover the ids, which differ between the recording and the replay
+        (Instruction::Checksum(_), Instruction::Checksum(_)) => true,
+        (x, y) => x == y,
+    }
+}
+
+fn unify_node(expected: &Node, actual: &Node, ids: &mut HashMap<u64, u64>) -> bool {
+    match (expected, actual) {
+        (Node::Element(x), Node::Element(y)) => {
+            let ids_match = match (x.id, y.id) {
+                (Some(i), Some(j)) => unify_id(i, j, ids),
+                (None, None) => true,
+                _ => false,
+            };
+            ids_match
+                && x.tag == y.tag
+                && x.namespace == y.namespace
+                && x.attributes == y.attributes
+                && x.events == y.events
+                && x.js_events == y.js_events
+                && x.children.len() == y.children.len()
+                && x.children
+                    .iter()
+                    .zip(&y.children)
+                    .all(|(x, y)| unify_node(x, y, ids))
+        }
+        (x, y) => x == y,
+    }
+}
+
+fn unify_id(expected: u64, actual: u64, ids: &mut HashMap<u64, u64>) -> bool {
+    *ids.entry(expected).or_insert(actual) == actual
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::node::Node;
+    use crate::runtime::recording::Buffer;
+    use crate::testing::TestHarness;
+    use crate::{Render, Updated};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    enum Msg {
+        Increment,
+        Set(u32),
+    }
+
+    struct Counter {
+        count: u32,
+        label: &'static str,
+    }
+
+    impl Render for Counter {
+        type Message = Msg;
+
+        fn render(&self) -> Node<Self::Message> {
+            Node::html()
+                .elem("button")
+                .id("increment")
+                .on("click", |_| Msg::Increment)
+                .text(format!("{} {}", self.label, self.count))
+                .build()
+        }
+    }
+
+    impl App for Counter {
+        fn update(&mut self, msg: Self::Message, _ctx: Context<Self::Message>) -> Updated {
+            match msg {
+                Msg::Increment => self.count += 1,
+                Msg::Set(count) => self.count = count,
+            }
+            Updated::yes()
+        }
+
+        fn mount(&mut self, ctx: Context<Self::Message>) {
+            ctx.spawn(async { Msg::Set(10) });
+        }
+    }
+
+    fn record(patches: bool) -> Vec<Record> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let mut harness = TestHarness::new_with_runtime(counter, move |runtime| {
+            if patches {
+                runtime.record_with_patches(writer)
+            } else {
+                runtime.record(writer)
+            }
+        });
+        harness.idle_blocking().unwrap();
+        assert_eq!(
+            harness.html(),
+            "<button id=\"increment\" @click>Count 10</button>"
+        );
+        harness.click(harness.find_by_id("increment").unwrap());
+        harness.idle_blocking().unwrap();
+        harness.update(Msg::Set(20)).unwrap();
+        harness.idle_blocking().unwrap();
+        harness.click(harness.find_by_id("increment").unwrap());
+        harness.idle_blocking().unwrap();
+        assert_eq!(
+            harness.html(),
+            "<button id=\"increment\" @click>Count 21</button>"
+        );
+        harness.quit().unwrap();
+        buffer.records()
+    }
+
+    fn counter() -> Counter {
+        Counter {
+            count: 0,
+            label: "Count",
+        }
+    }
+
+    #[test]
+    fn test_replay_reconnect() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let mut harness = TestHarness::new_with_runtime(counter, move |runtime| {
+            runtime.record_with_patches(writer)
+        });
+        harness.idle_blocking().unwrap();
+        harness.send(RxMsg::Connected());
+        harness.idle_blocking().unwrap();
+        assert_eq!(
+            harness.html(),
+            "<button id=\"increment\" @click>Count 10</button>"
+        );
+        harness.quit().unwrap();
+
+        // the render from scratch is repeated by replaying the `Connected()` message
+        let records = buffer.records();
+        let connected = records
+            .iter()
+            .position(|x| matches!(&x.entry, Entry::Frontend(msg) if msg["Connected"].is_array()))
+            .unwrap();
+        assert!(!records[connected..]
+            .iter()
+            .any(|x| x.entry == Entry::Render));
+        let report = Replay::new(records).run_blocking(counter).unwrap();
+        assert!(!report.diverged(), "{:?}", report.divergences);
+        assert_eq!(report.patches, 2);
+    }
+
+    #[test]
+    fn test_replay() {
+        let records = record(true);
+        let entries: Vec<_> = records.iter().map(|x| &x.entry).collect();
+        assert!(entries.contains(&&Entry::Async(serde_json::json!({"Set": 10}))));
+        assert!(entries.contains(&&Entry::Update(serde_json::json!({"Set": 20}))));
+        assert!(entries.contains(&&Entry::Render));
+
+        let replay = Replay::new(records);
+        let report = replay.run_blocking(counter).unwrap();
+        assert!(!report.diverged(), "{:?}", report.divergences);
+        assert!(report.patches >= 3);
+
+        let report = replay
+            .run_blocking(|| Counter {
+                count: 0,
+                label: "Clicks",
+            })
+            .unwrap();
+        assert!(report.diverged());
+        assert!(report.divergences[0]
+            .reason
+            .contains("Patch differs from the recording"));
+    }
+
+    #[test]
+    fn test_replay_without_patches() {
+        let records = record(false);
+        assert!(!records.iter().any(|x| matches!(x.entry, Entry::Patch(_))));
+        let report = Replay::new(records).run_blocking(counter).unwrap();
+        assert!(!report.diverged(), "{:?}", report.divergences);
+        assert_eq!(report.patches, 0);
+    }
+}
@@ -0,0 +1,298 @@
+//! Renders components to a normalized text representation, which is compared against
+//! snapshot files.
+//!
+//! # Format
+//!
+//! Each element, text node and annotation is written on its own line and indented by
+//! two spaces per nesting level. Elements show their attributes in the order they were
+//! added. Element ids assigned by the runtime change in every render and are thus omitted.
+//! Within the start tag, event listeners are shown as `@name`, javascript events as `js:name`,
+//! keys as `#key` and registered rpcs as `#rpc`. Lines starting with `#` mark nested components,
+//! event subscriptions and blobs. Since event ids change in every run as well, subscribed events
+//! are numbered in the order they first appear in the snapshot and shown along with the type
+//! of their values.
+//!
+
+use crate::node::Node;
+use crate::standalone::{Item, RenderedNode};
+use crate::vdom::{VElement, VNode};
+use crate::{Component, Id, Render};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// If this environment variable is set to a value other than `0`, `assert_snapshot()`
+/// overwrites the snapshot files instead of comparing against them.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "GREENHORN_UPDATE_SNAPSHOTS";
+
+/// Renders `renderable` including all nested components to a snapshot.
+pub fn snapshot<R: Render>(renderable: &R) -> String {
+    snapshot_node(renderable.render())
+}
+
+/// Renders a component including all nested components to a snapshot.
+pub fn snapshot_component<T: 'static + Render + Send>(component: &Component<T>) -> String {
+    snapshot_node(component.render())
+}
+
+/// Renders a node including all nested components to a snapshot.
+pub fn snapshot_node<T: 'static + Send>(node: Node<T>) -> String {
+    let mut ret = String::new();
+    write_component(
+        &RenderedNode::new(node),
+        0,
+        None,
+        &mut HashMap::new(),
+        &mut ret,
+    );
+    ret
+}
+
+/// Compares `actual` against the snapshot stored at `path`.
+///
+/// Relative paths are resolved against the directory of the crate under test, i.e. `CARGO_MANIFEST_DIR`.
+/// Panics if the snapshot does not exist or does not match, unless the environment variable
+/// `GREENHORN_UPDATE_SNAPSHOTS` is set, in which case the snapshot is written instead.
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::prelude::*;
+/// # use greenhorn::testing::{assert_snapshot, snapshot};
+/// # struct MyComponent;
+/// # impl Render for MyComponent {
+/// #     type Message = ();
+/// #     fn render(&self) -> Node<Self::Message> {
+/// #         Node::html().elem("div").build()
+/// #     }
+/// # }
+/// assert_snapshot("snapshots/my_component.snap", &snapshot(&MyComponent));
+/// ```
+pub fn assert_snapshot<P: AsRef<Path>>(path: P, actual: &str) {
+    let update = std::env::var(UPDATE_SNAPSHOTS_ENV)
+        .map(|x| !x.is_empty() && x != "0")
+        .unwrap_or(false);
+    let mut resolved = PathBuf::new();
+    if let Ok(dir) = std::env::var("CARGO_MANIFEST_DIR") {
+        resolved.push(dir);
+    }
+    resolved.push(path);
+    if let Err(err) = check_snapshot(&resolved, actual, update) {
+        panic!("{}", err);
+    }
+}
+
+/// Compares `actual` against the snapshot at `path` or writes it if `update` is set.
+fn check_snapshot(path: &Path, actual: &str, update: bool) -> Result<(), String> {
+    let actual = normalize(actual);
+    if update {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        return fs::write(path, actual).map_err(|err| err.to_string());
+    }
+    let expected = fs::read_to_string(path).map_err(|err| {
+        format!(
+            "Failed to read snapshot {}: {}\nSet {}=1 to create it.",
+            path.display(),
+            err,
+            UPDATE_SNAPSHOTS_ENV
+        )
+    })?;
+    let expected = normalize(&expected);
+    if expected == actual {
+        return Ok(());
+    }
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(x, y)| x != y)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    Err(format!(
+        "Snapshot {} does not match at line {}\n--- expected\n{}+++ actual\n{}Set {}=1 to update it.",
+        path.display(),
+        line + 1,
+        expected,
+        actual,
+        UPDATE_SNAPSHOTS_ENV
+    ))
+}
+
+/// Unifies line endings and ensures a single trailing newline.
+fn normalize(data: &str) -> String {
+    let mut ret = data.replace("\r\n", "\n").trim_end().to_string();
+    ret.push('\n');
+    ret
+}
+
+/// Writes the DOM of a rendered component, along with its non-DOM items, to `out`.
+///
+/// `events` maps the ids of the subscribed events to their number within the snapshot.
+fn write_component(
+    rendered: &RenderedNode,
+    depth: usize,
+    namespace: Option<&str>,
+    events: &mut HashMap<Id, usize>,
+    out: &mut String,
+) {
+    let mut rpcs = HashSet::new();
+    for item in &rendered.items {
+        match item {
+            Item::Subscription { event, value_type } => {
+                let count = events.len();
+                let number = *events.entry(*event).or_insert(count);
+                line(
+                    out,
+                    depth,
+                    &format!("# subscription event={} type={}", number, value_type),
+                )
+            }
+            Item::Blob(blob) => line(
+                out,
+                depth,
+                &format!(
+                    "# blob hash={} mime_type={:?} size={}",
+                    blob.hash(),
+                    blob.mime_type(),
+                    blob.data().len()
+                ),
+            ),
+            Item::Rpc(id) => {
+                rpcs.insert(*id);
+            }
+        }
+    }
+    let mut writer = Writer {
+        rendered,
+        rpcs,
+        events,
+        out,
+    };
+    for node in &rendered.vdom {
+        writer.node(node, depth, namespace);
+    }
+}
+
+struct Writer<'a> {
+    rendered: &'a RenderedNode,
+    rpcs: HashSet<Id>,
+    events: &'a mut HashMap<Id, usize>,
+    out: &'a mut String,
+}
+
+impl<'a> Writer<'a> {
+    fn node(&mut self, node: &VNode, depth: usize, namespace: Option<&str>) {
+        match node {
+            VNode::Element(elem) => self.element(elem, depth, namespace),
+            VNode::Text(text) => line(self.out, depth, &format!("{:?}", text)),
+            VNode::Placeholder(id, _) => {
+                line(self.out, depth, "# component");
+                let rendered = self.rendered.component(*id);
+                write_component(&rendered, depth, namespace, self.events, self.out);
+            }
+        }
+    }
+
+    fn element(&mut self, elem: &VElement, depth: usize, namespace: Option<&str>) {
+        let mut tag = format!("<{}", elem.tag);
+        if elem.namespace.as_deref() != namespace {
+            if let Some(ns) = &elem.namespace {
+                let _ = write!(tag, " xmlns={:?}", ns);
+            }
+        }
+        if let Some(key) = &elem.key {
+            let _ = write!(tag, " #key={:?}", key);
+        }
+        for attr in &elem.attr {
+            let _ = write!(tag, " {}={:?}", attr.key, attr.value);
+        }
+        for evt in &elem.events {
+            let _ = write!(tag, " @{}", evt.name);
+            if evt.prevent_default {
+                tag.push_str(".prevent_default");
+            }
+            if evt.no_propagate {
+                tag.push_str(".no_propagate");
+            }
+        }
+        for evt in &elem.js_events {
+            let _ = write!(tag, " js:{}={:?}", evt.key, evt.value);
+        }
+        if self.rpcs.contains(&elem.id) {
+            tag.push_str(" #rpc");
+        }
+        tag.push('>');
+        if elem.children.is_empty() {
+            let _ = write!(tag, "</{}>", elem.tag);
+            line(self.out, depth, &tag);
+            return;
+        }
+        line(self.out, depth, &tag);
+        for child in &elem.children {
+            self.node(child, depth + 1, elem.namespace.as_deref());
+        }
+        line(self.out, depth, &format!("</{}>", elem.tag));
+    }
+}
+
+fn line(out: &mut String, depth: usize, data: &str) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+    out.push_str(data);
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::standalone::fixture::parent;
+
+    #[test]
+    fn test_snapshot() {
+        let parent = parent();
+        let expected = r##"# subscription event=0 type=()
+# subscription event=1 type=bool
+# subscription event=0 type=()
+# blob hash=42 mime_type="image/png" size=3
+<div title="\"quoted\" & <tagged>" class="parent">
+  <button @click.prevent_default js:onmouseover="highlight()">
+    "1 < 2"
+    "& 3"
+  </button>
+  <input checked="false"></input>
+  <input checked="true"></input>
+  <script>
+    "if (a < b) {}</div></SCRIPT>"
+  </script>
+  <canvas #key="c" #rpc></canvas>
+  # component
+  <svg xmlns="http://www.w3.org/2000/svg">
+    <circle r="5"></circle>
+  </svg>
+</div>
+"##;
+        assert_eq!(snapshot(&parent), expected);
+        // element ids differ between renders
+        assert_eq!(snapshot(&parent), expected);
+        assert_eq!(
+            snapshot_component(&parent.child),
+            snapshot_node(parent.child.render())
+        );
+    }
+
+    #[test]
+    fn test_check_snapshot() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("greenhorn-{}", Id::new().data()));
+        path.push("test.snap");
+        assert!(check_snapshot(&path, "<div></div>", false).is_err());
+        check_snapshot(&path, "<div></div>", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<div></div>\n");
+        check_snapshot(&path, "<div></div>\r\n", false).unwrap();
+        let err = check_snapshot(&path, "<span></span>", false).unwrap_err();
+        assert!(err.contains("does not match at line 1"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -6,6 +6,16 @@
 //! Therefore, neither DOM node re-orders nor insertion are detected by this algorithm.
 //! In these cases, the DOM will simply be re-emiited.
 //!
+//! The exception are keyed children: If all children of both the old and the new element
+//! carry a unique key, children are matched by key. Removed children are dropped,
+//! matched children are moved into their new position and new children are inserted.
+//! Only then, the matched children are diffed pairwise.
+//!
+//! Every `VElement` carries a structural hash of its subtree, which is computed
+//! while rendering. If the hashes of two elements match, the subtree is skipped
+//! and only the id translations are recorded. Subtrees containing components
+//! carry no hash and are always diffed.
+//!
 
 use crate::runtime::{Frame, RenderResult};
 use crate::vdom::{Patch, PatchItem, VElement, VNode};
@@ -13,10 +23,8 @@ use crate::App;
 use std::collections::HashMap;
 
 // Expansion ideas
-// [ ] key based diffing
 // Refer to https://programming.vip/docs/realization-and-analysis-of-virtual-dom-diff-algorithm.html
 // performance tuning...
-// hash based diffing => compute hash in parallel
 // https://github.com/Matt-Esch/virtual-dom/blob/master/vtree/diff.js
 //
 // potentially parallelize using a concurrent hashmap in RenderResult (or just use RwLock<HashMap<>>
@@ -163,6 +171,10 @@ impl<'a, A: App> Differ<'a, A> {
             return true;
         }
 
+        if let (Some(old_keys), Some(new_keys)) = (child_keys(old), child_keys(new)) {
+            return self.diff_keyed_children(old, new, &old_keys, &new_keys, patch);
+        }
+
         let mut ret = false;
         let mut truncates = 1;
         patch.push(PatchItem::Descend());
@@ -208,6 +220,89 @@ impl<'a, A: App> Differ<'a, A> {
         ret
     }
 
+    /// Diffs the children of two elements whose children are all keyed.
+    ///
+    /// First, the children are re-arranged to match the order of the new children by emitting
+    /// `RemoveChild`, `MoveChild` and `InsertChild` instructions. Afterwards, all children present
+    /// in both elements are diffed recursively.
+    /// Returns true if changes were detected.
+    fn diff_keyed_children(
+        &self,
+        old: &'a VElement,
+        new: &'a VElement,
+        old_keys: &HashMap<&'a str, usize>,
+        new_keys: &HashMap<&'a str, usize>,
+        patch: &mut Patch<'a>,
+    ) -> bool {
+        let mut ret = false;
+
+        // simulates the children of the DOM node, each entry is the index
+        // of the old child or None if the child was newly inserted
+        let mut current: Vec<Option<usize>> = (0..old.children.len()).map(Some).collect();
+
+        // remove children from the back such that indices remain valid
+        for (k, child) in old.children.iter().enumerate().rev() {
+            if !new_keys.contains_key(key_of(child)) {
+                patch.push(PatchItem::RemoveChild(k as u32));
+                current.remove(k);
+                ret = true;
+            }
+        }
+
+        // all nodes before `k` are in place, thus an old node is
+        // always found at or after `k`
+        for (k, child) in new.children.iter().enumerate() {
+            if let Some(&old_idx) = old_keys.get(key_of(child)) {
+                let pos = k + current[k..]
+                    .iter()
+                    .position(|x| *x == Some(old_idx))
+                    .unwrap();
+                if pos != k {
+                    patch.push(PatchItem::MoveChild(pos as u32, k as u32));
+                    let moved = current.remove(pos);
+                    current.insert(k, moved);
+                    ret = true;
+                }
+            } else {
+                patch.push(PatchItem::InsertChild(k as u32, child));
+                current.insert(k, None);
+                ret = true;
+            }
+        }
+
+        // diff common items
+        let start = patch.len();
+        patch.push(PatchItem::Descend());
+        let mut skips = 0;
+        let mut changed = false;
+        for (k, old_idx) in current.iter().enumerate() {
+            if let Some(old_idx) = old_idx {
+                let len_before = patch.len();
+                if skips > 0 {
+                    patch.push(PatchItem::NextNode(skips));
+                }
+                let old_node = &old.children[*old_idx];
+                let new_node = &new.children[k];
+                if self.diff_recursive(old_node, new_node, patch) {
+                    changed = true;
+                    skips = 0;
+                } else {
+                    // nothing emitted, thus there is no need to move either
+                    patch.items.truncate(len_before);
+                }
+            }
+            skips += 1;
+        }
+
+        if changed {
+            patch.push(PatchItem::Ascend());
+        } else {
+            patch.items.truncate(start);
+        }
+
+        ret || changed
+    }
+
     /// Diffs the registered event handlers and returns true in case
     /// the registered handlers have changed.
     fn diff_events(&self, old: &'a VElement, new: &'a VElement) -> bool {
@@ -222,14 +317,39 @@ impl<'a, A: App> Differ<'a, A> {
         true
     }
 
+    /// Registers the translation of the id of `new` to the id
+    /// the corresponding DOM node was created with.
+    fn translate(&self, old: &VElement, new: &VElement, patch: &mut Patch<'a>) {
+        if !old.id.is_empty() {
+            let very_old_id = self.old.translations.get(&old.id).unwrap_or(&old.id);
+            patch.translations.remove(&old.id);
+            patch.translate(new.id, *very_old_id);
+        }
+    }
+
+    /// Registers id translations for two subtrees with equal structural hash.
+    /// No patch items are emitted.
+    fn translate_subtree(&self, old: &VElement, new: &VElement, patch: &mut Patch<'a>) {
+        self.translate(old, new, patch);
+        for (old, new) in old.children.iter().zip(new.children.iter()) {
+            if let (VNode::Element(old), VNode::Element(new)) = (old, new) {
+                self.translate_subtree(old, new, patch);
+            }
+        }
+    }
+
     /// Recursively diff to vdoms and compute a patch to update `old` to `new`.
     /// Returns whether a change was detected
     fn diff_recursive(&self, old: &'a VNode, new: &'a VNode, patch: &mut Patch<'a>) -> bool {
         let mut ret = false;
         match (old, new) {
             (VNode::Element(elem_old), VNode::Element(elem_new)) => {
-                if elem_old.tag != elem_new.tag
+                if elem_old.hash.is_some() && elem_old.hash == elem_new.hash {
+                    // structurally identical subtree, only ids must be translated
+                    self.translate_subtree(elem_old, elem_new, patch);
+                } else if elem_old.tag != elem_new.tag
                     || elem_old.namespace != elem_new.namespace
+                    || elem_old.key != elem_new.key
                     || !self.diff_events(elem_old, elem_new)
                 {
                     ret = true;
@@ -238,15 +358,7 @@ impl<'a, A: App> Differ<'a, A> {
                     ret |= self.diff_attrs(elem_old, elem_new, patch);
                     ret |= self.diff_js_events(elem_old, elem_new, patch);
                     ret |= self.diff_children(elem_old, elem_new, patch);
-                    if !elem_old.id.is_empty() {
-                        let very_old_id = self
-                            .old
-                            .translations
-                            .get(&elem_old.id)
-                            .unwrap_or(&elem_old.id);
-                        patch.translations.remove(&elem_old.id);
-                        patch.translate(elem_new.id, *very_old_id);
-                    }
+                    self.translate(elem_old, elem_new, patch);
                 }
             }
             (VNode::Text(elem_old), VNode::Text(elem_new)) => {
@@ -301,3 +413,30 @@ impl<'a, A: App> Differ<'a, A> {
         ret
     }
 }
+
+/// Returns the key of a child node. Only call this for children
+/// that were checked with `child_keys()`.
+fn key_of(node: &VNode) -> &str {
+    match node {
+        VNode::Element(elem) => elem.key.as_deref().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+/// Maps the keys of all children of an element to their index.
+///
+/// Returns `None` if any child is not a keyed element or if keys are not unique.
+/// In this case children must be diffed by position.
+fn child_keys(elem: &VElement) -> Option<HashMap<&str, usize>> {
+    let mut ret = HashMap::with_capacity(elem.children.len());
+    for (k, child) in elem.children.iter().enumerate() {
+        let key = match child {
+            VNode::Element(VElement { key: Some(key), .. }) => key.as_str(),
+            _ => return None,
+        };
+        if ret.insert(key, k).is_some() {
+            return None;
+        }
+    }
+    Some(ret)
+}
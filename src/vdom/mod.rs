@@ -5,10 +5,13 @@ mod tests;
 
 use crate::blob::Blob;
 use crate::listener::Listener;
+use crate::patch::Checksum;
 use crate::runtime::RenderResult;
 use crate::{App, Id};
 pub(crate) use diff::Differ;
 pub(crate) use serialize::serialize as patch_serialize;
+pub(crate) use serialize::PATCH_FORMAT_VERSION;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
@@ -98,6 +101,55 @@ pub(crate) struct VElement {
     pub(crate) events: Vec<EventHandler>,
     pub(crate) children: Vec<VNode>,
     pub(crate) namespace: Option<String>,
+    pub(crate) key: Option<String>,
+    /// Structural hash of this element and its subtree, excluding ids.
+    /// `None` if the subtree contains a component placeholder, since
+    /// the content of a component is not known at this point.
+    pub(crate) hash: Option<u64>,
+}
+
+impl VElement {
+    /// Computes the structural hash of this element based on its own
+    /// fields and the hashes of its children.
+    ///
+    /// Children must already carry their hash, thus this is not recursive.
+    pub(crate) fn structural_hash(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.tag.hash(&mut hasher);
+        self.namespace.hash(&mut hasher);
+        self.key.hash(&mut hasher);
+        for attr in &self.attr {
+            attr.key.hash(&mut hasher);
+            attr.value.hash(&mut hasher);
+        }
+        self.attr.len().hash(&mut hasher);
+        for evt in &self.js_events {
+            evt.key.hash(&mut hasher);
+            evt.value.hash(&mut hasher);
+        }
+        self.js_events.len().hash(&mut hasher);
+        for evt in &self.events {
+            evt.name.hash(&mut hasher);
+            evt.no_propagate.hash(&mut hasher);
+            evt.prevent_default.hash(&mut hasher);
+        }
+        self.events.len().hash(&mut hasher);
+        for child in &self.children {
+            match child {
+                VNode::Element(elem) => {
+                    0u8.hash(&mut hasher);
+                    elem.hash?.hash(&mut hasher);
+                }
+                VNode::Text(text) => {
+                    1u8.hash(&mut hasher);
+                    text.hash(&mut hasher);
+                }
+                VNode::Placeholder(_, _) => return None,
+            }
+        }
+        self.children.len().hash(&mut hasher);
+        Some(hasher.finish())
+    }
 }
 
 /// Represents a node in the virtual DOM
@@ -157,6 +209,18 @@ pub(crate) enum PatchItem<'a> {
     ReplaceJsEvent(&'a str, &'a str),
 
     AddChildren(&'a Vec<VNode>),
+
+    // The following instructions operate on the children
+    // of the current node and do not move the pointer
+    InsertChild(u32, &'a VNode), // inserts before the child at the given index
+    MoveChild(u32, u32),         // (from, to), moves the child at index `from` to index `to`
+    RemoveChild(u32),            // removes the child at the given index
+
+    // Attaches to the current node if it was rendered by the server, replaces it otherwise
+    Hydrate(&'a VNode),
+
+    // Verifies the structure of the DOM after applying the patch
+    Checksum(u32),
 }
 
 impl<'a> PatchItem<'a> {
@@ -176,6 +240,7 @@ impl<'a> PatchItem<'a> {
 pub(crate) struct Patch<'a> {
     pub(crate) items: Vec<PatchItem<'a>>,
     pub(crate) translations: HashMap<Id, Id>,
+    pub(crate) frame: u64, // number of the frame, acknowledged by the frontend once applied
 }
 
 impl<'a> Patch<'a> {
@@ -183,6 +248,7 @@ impl<'a> Patch<'a> {
         Patch {
             items: vec![],
             translations: HashMap::new(),
+            frame: 0,
         }
     }
 
@@ -195,6 +261,22 @@ impl<'a> Patch<'a> {
         patch
     }
 
+    /// Same as `new_from_dom()`, but allows the frontend to attach to server-rendered
+    /// HTML instead of replacing it.
+    pub(crate) fn new_for_hydration<A: App>(rendered: &'a RenderResult<A>) -> Self {
+        let mut patch = Self::new_from_dom(rendered);
+        patch.items[0] = PatchItem::Hydrate(&rendered.vdom);
+        patch
+    }
+
+    /// Appends a checksum of the DOM the frontend shows after applying this patch,
+    /// which allows the frontend to detect that it has diverged from the backend.
+    pub(crate) fn push_checksum<A: App>(&mut self, rendered: &RenderResult<A>) {
+        let mut checksum = Checksum::new();
+        hash_vnode(&rendered.vdom, rendered, &self.translations, &mut checksum);
+        self.push(PatchItem::Checksum(checksum.value()));
+    }
+
     pub(crate) fn push_path(&mut self, path: &Path) {
         for n in &path.inner {
             let n = *n;
@@ -269,3 +351,28 @@ impl<'a> Patch<'a> {
         self.items.len()
     }
 }
+
+/// Adds the DOM the frontend shows after applying `vnode` to `checksum`, such that it equals
+/// `Node::checksum()` of the resulting document without creating it.
+fn hash_vnode<A: App>(
+    vnode: &VNode,
+    rendered: &RenderResult<A>,
+    translations: &HashMap<Id, Id>,
+    checksum: &mut Checksum,
+) {
+    match vnode {
+        VNode::Element(elem) => {
+            let id = translations.get(&elem.id).unwrap_or(&elem.id);
+            checksum.element(if id.is_empty() { None } else { Some(id.data()) });
+            for child in &elem.children {
+                hash_vnode(child, rendered, translations, checksum);
+            }
+            checksum.end();
+        }
+        VNode::Text(_) => checksum.text(),
+        VNode::Placeholder(id, _) => {
+            let vdom = rendered.get_component_vdom(*id).unwrap();
+            hash_vnode(vdom, rendered, translations, checksum);
+        }
+    }
+}
@@ -4,30 +4,91 @@
 //! and emits bytearray. The employed data format is optimized for maximum
 //! decode speed in javascript.
 //!
+//! # Format
+//!
+//! A patch starts with a single byte containing `PATCH_FORMAT_VERSION`, followed by the
+//! frame number of the patch as varint and the serialized patch items. Lengths, counts and
+//! child indices are encoded as unsigned LEB128 varints. Ids and hashes are encoded as 8-byte
+//! little endian integers.
+//!
+//! Strings which tend to repeat, such as tag names, attribute keys and event names, are
+//! interned in a per-patch string table: A varint `0` is followed by the string itself, which is
+//! then appended to the table. Any other varint `n` refers to the `n-1`-th entry of the table.
+//! All other strings are encoded as varint length followed by the UTF-8 data.
+//!
 
 use crate::runtime::RenderResult;
 use crate::vdom::{EventHandler, Patch, PatchItem, VNode};
 use crate::{App, Id};
+use std::collections::HashMap;
+
+/// Version of the binary patch format. Must match `PATCH_FORMAT_VERSION` in `js/app.js`.
+pub(crate) const PATCH_FORMAT_VERSION: u8 = 5;
+
+/// Output buffer of a serialized patch along with its string table.
+#[derive(Default)]
+struct Output {
+    data: Vec<u8>,
+    strings: HashMap<String, u64>,
+}
+
+impl Output {
+    fn push(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn extend_from_slice(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// Writes `value` as unsigned LEB128 varint.
+    fn varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.data.push(byte);
+                return;
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    /// Writes a string which is likely to repeat within a patch.
+    fn intern(&mut self, value: &str) {
+        if let Some(idx) = self.strings.get(value) {
+            self.varint(idx + 1);
+        } else {
+            let idx = self.strings.len() as u64;
+            self.strings.insert(value.to_string(), idx);
+            self.varint(0);
+            value.serialize(self);
+        }
+    }
+}
 
 trait PatchSerialize {
-    fn serialize(&self, output: &mut Vec<u8>);
+    fn serialize(&self, output: &mut Output);
 }
 
 trait NodeSerialize {
-    fn serialize<A: App>(&self, rendered: &RenderResult<A>, output: &mut Vec<u8>);
+    fn serialize<A: App>(&self, rendered: &RenderResult<A>, output: &mut Output);
 }
 
 impl NodeSerialize for VNode {
-    fn serialize<A: App>(&self, rendered: &RenderResult<A>, output: &mut Vec<u8>) {
+    fn serialize<A: App>(&self, rendered: &RenderResult<A>, output: &mut Output) {
         match self {
             VNode::Element(elem) => {
                 output.push(0);
-                elem.tag.serialize(output);
-                elem.namespace.serialize(output);
+                output.intern(&elem.tag);
+                output.push(elem.namespace.is_some().into());
+                if let Some(namespace) = &elem.namespace {
+                    output.intern(namespace);
+                }
                 elem.id.serialize(output);
                 (elem.attr.len() as u32).serialize(output);
                 for attr in elem.attr.iter() {
-                    attr.key.serialize(output);
+                    output.intern(&attr.key);
                     attr.value.serialize(output);
                 }
                 (elem.events.len() as u32).serialize(output);
@@ -36,7 +97,7 @@ impl NodeSerialize for VNode {
                 }
                 (elem.js_events.len() as u32).serialize(output);
                 for evt in &elem.js_events {
-                    evt.key.serialize(output);
+                    output.intern(&evt.key);
                     evt.value.serialize(output);
                 }
                 (elem.children.len() as u32).serialize(output);
@@ -57,7 +118,7 @@ impl NodeSerialize for VNode {
 }
 
 impl PatchSerialize for Id {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         if self.is_empty() {
             output.push(0);
         } else {
@@ -68,50 +129,48 @@ impl PatchSerialize for Id {
 }
 
 impl PatchSerialize for u32 {
-    fn serialize(&self, output: &mut Vec<u8>) {
-        let data = self.to_le_bytes();
-        output.extend_from_slice(&data);
+    fn serialize(&self, output: &mut Output) {
+        output.varint(*self as u64);
     }
 }
 
 impl PatchSerialize for u64 {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         let data = self.to_le_bytes();
         output.extend_from_slice(&data);
     }
 }
 
 impl PatchSerialize for Vec<u8> {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         (self.len() as u32).serialize(output);
         output.extend_from_slice(self);
     }
 }
 
 impl PatchSerialize for String {
-    fn serialize(&self, output: &mut Vec<u8>) {
-        (self.len() as u32).serialize(output);
-        output.extend_from_slice(self.as_bytes());
+    fn serialize(&self, output: &mut Output) {
+        self.as_str().serialize(output);
     }
 }
 
 impl PatchSerialize for &str {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         (self.len() as u32).serialize(output);
         output.extend_from_slice(self.as_bytes());
     }
 }
 
 impl PatchSerialize for EventHandler {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         output.push(self.no_propagate.into());
         output.push(self.prevent_default.into());
-        self.name.serialize(output);
+        output.intern(&self.name);
     }
 }
 
 impl<T: PatchSerialize> PatchSerialize for Option<T> {
-    fn serialize(&self, output: &mut Vec<u8>) {
+    fn serialize(&self, output: &mut Output) {
         output.push(self.is_some().into());
         if let Some(x) = self {
             x.serialize(output);
@@ -120,7 +179,9 @@ impl<T: PatchSerialize> PatchSerialize for Option<T> {
 }
 
 pub(crate) fn serialize<A: App>(rendered: &RenderResult<A>, patch: &Patch) -> Vec<u8> {
-    let mut output = Vec::new();
+    let mut output = Output::default();
+    output.push(PATCH_FORMAT_VERSION);
+    output.varint(patch.frame);
     for patch in &patch.items {
         match patch {
             PatchItem::AppendSibling(node) => {
@@ -153,23 +214,23 @@ pub(crate) fn serialize<A: App>(rendered: &RenderResult<A>, patch: &Patch) -> Ve
             }
             PatchItem::RemoveAttribute(key) => {
                 output.push(10);
-                key.serialize(&mut output);
+                output.intern(key);
             }
             PatchItem::AddAtrribute(key, value) => {
                 output.push(11);
-                key.serialize(&mut output);
+                output.intern(key);
                 value.serialize(&mut output);
             }
             PatchItem::ReplaceAttribute(key, value) => {
                 output.push(12);
-                key.serialize(&mut output);
+                output.intern(key);
                 value.serialize(&mut output);
             }
             PatchItem::AddBlob(blob) => {
                 output.push(13);
                 blob.id().serialize(&mut output);
                 blob.hash().serialize(&mut output);
-                blob.mime_type().serialize(&mut output);
+                output.intern(blob.mime_type());
                 blob.data().serialize(&mut output);
                 blob.on_add().serialize(&mut output);
                 blob.on_change().serialize(&mut output);
@@ -180,16 +241,16 @@ pub(crate) fn serialize<A: App>(rendered: &RenderResult<A>, patch: &Patch) -> Ve
             }
             PatchItem::RemoveJsEvent(key) => {
                 output.push(15);
-                key.serialize(&mut output);
+                output.intern(key);
             }
             PatchItem::AddJsEvent(key, value) => {
                 output.push(16);
-                key.serialize(&mut output);
+                output.intern(key);
                 value.serialize(&mut output);
             }
             PatchItem::ReplaceJsEvent(key, value) => {
                 output.push(17);
-                key.serialize(&mut output);
+                output.intern(key);
                 value.serialize(&mut output);
             }
             PatchItem::AddChildren(children) => {
@@ -199,7 +260,29 @@ pub(crate) fn serialize<A: App>(rendered: &RenderResult<A>, patch: &Patch) -> Ve
                     child.serialize(rendered, &mut output);
                 }
             }
+            PatchItem::InsertChild(idx, node) => {
+                output.push(19);
+                idx.serialize(&mut output);
+                node.serialize(rendered, &mut output);
+            }
+            PatchItem::MoveChild(from, to) => {
+                output.push(20);
+                from.serialize(&mut output);
+                to.serialize(&mut output);
+            }
+            PatchItem::RemoveChild(idx) => {
+                output.push(21);
+                idx.serialize(&mut output);
+            }
+            PatchItem::Hydrate(node) => {
+                output.push(22);
+                node.serialize(rendered, &mut output);
+            }
+            PatchItem::Checksum(checksum) => {
+                output.push(23);
+                checksum.serialize(&mut output);
+            }
         }
     }
-    output
+    output.data
 }
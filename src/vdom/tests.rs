@@ -4,6 +4,7 @@ use crate::context::Context;
 use crate::node::Node;
 use crate::runtime::{Frame, RenderResult};
 use crate::{App, Render, Updated};
+use assert_matches::assert_matches;
 use std::fs;
 
 struct DummyApp;
@@ -39,6 +40,8 @@ fn test_remove_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -49,6 +52,8 @@ fn test_remove_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
     let new = RenderResult::<DummyApp>::new_from_vnode(elem_b);
@@ -76,6 +81,8 @@ fn test_add_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -86,6 +93,8 @@ fn test_add_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
@@ -115,6 +124,8 @@ fn test_change_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -125,6 +136,8 @@ fn test_change_attr() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
@@ -152,6 +165,8 @@ fn test_change_tag() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -162,6 +177,8 @@ fn test_change_tag() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
@@ -188,6 +205,8 @@ fn test_add_event() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -202,6 +221,8 @@ fn test_add_event() {
         }],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
@@ -225,6 +246,8 @@ fn test_add_child() {
         events: vec![],
         children: vec![],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let elem_b = VNode::element(VElement {
@@ -241,8 +264,12 @@ fn test_add_child() {
             events: vec![],
             children: vec![],
             namespace: None,
+            key: None,
+            hash: None,
         })],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let old = Frame::<DummyApp>::new_from_vnode(elem_a);
@@ -292,8 +319,12 @@ fn test_output_patch() {
             events: vec![],
             children: vec![VNode::text("Hello, World")],
             namespace: None,
+            key: None,
+            hash: None,
         })],
         namespace: None,
+        key: None,
+        hash: None,
     });
 
     let new = RenderResult::<DummyApp>::new_from_vnode(elem_b);
@@ -301,3 +332,359 @@ fn test_output_patch() {
     let serialized = serialize(&new, &patch);
     fs::write("test_patch.bin", serialized).expect("Unable to write file!");
 }
+
+#[test]
+fn test_serialize_v2() {
+    use crate::vdom::serialize::{serialize, PATCH_FORMAT_VERSION};
+
+    let patch = Patch {
+        items: vec![
+            PatchItem::NextNode(300),
+            PatchItem::AddAtrribute("class", "a"),
+            PatchItem::ReplaceAttribute("class", "b"),
+            PatchItem::RemoveChild(2),
+        ],
+        translations: Default::default(),
+        frame: 130,
+    };
+    let rendered = RenderResult::<DummyApp>::new_empty();
+    let serialized = serialize(&rendered, &patch);
+    #[rustfmt::skip]
+    let expected = vec![
+        PATCH_FORMAT_VERSION,
+        0x82, 0x01,
+        9, 0xac, 0x02,
+        // first occurrence of "class" is added to the string table
+        11, 0, 5, b'c', b'l', b'a', b's', b's', 1, b'a',
+        // second occurrence refers to the first entry
+        12, 1, 1, b'b',
+        21, 2,
+    ];
+    assert_eq!(serialized, expected);
+}
+
+fn keyed_list(keys: &[&str]) -> VNode {
+    let children = keys
+        .iter()
+        .map(|key| {
+            VNode::element(VElement {
+                id: Id::new(),
+                tag: "li".into(),
+                attr: vec![],
+                js_events: vec![],
+                events: vec![],
+                children: vec![VNode::text(*key)],
+                namespace: None,
+                key: Some(key.to_string()),
+                hash: None,
+            })
+        })
+        .collect();
+    VNode::element(VElement {
+        id: Id::new(),
+        tag: "ul".into(),
+        attr: vec![],
+        js_events: vec![],
+        events: vec![],
+        children,
+        namespace: None,
+        key: None,
+        hash: None,
+    })
+}
+
+#[test]
+fn test_keyed_insert_front() {
+    let old = Frame::<DummyApp>::new_from_vnode(keyed_list(&["b", "c", "d"]));
+    let new = RenderResult::<DummyApp>::new_from_vnode(keyed_list(&["a", "b", "c", "d"]));
+    let patch = diff(&old, &new);
+
+    assert_eq!(patch.items.len(), 1);
+    if let PatchItem::InsertChild(0, VNode::Element(elem)) = &patch.items[0] {
+        assert_eq!(elem.key.as_deref(), Some("a"));
+    } else {
+        panic!()
+    }
+}
+
+#[test]
+fn test_keyed_remove() {
+    let old = Frame::<DummyApp>::new_from_vnode(keyed_list(&["a", "b", "c", "d"]));
+    let new = RenderResult::<DummyApp>::new_from_vnode(keyed_list(&["a", "d"]));
+    let patch = diff(&old, &new);
+
+    assert_eq!(patch.items.len(), 2);
+    assert_matches!(patch.items[0], PatchItem::RemoveChild(2));
+    assert_matches!(patch.items[1], PatchItem::RemoveChild(1));
+}
+
+#[test]
+fn test_keyed_reorder() {
+    let old = Frame::<DummyApp>::new_from_vnode(keyed_list(&["a", "b", "c"]));
+    let new = RenderResult::<DummyApp>::new_from_vnode(keyed_list(&["c", "a", "b"]));
+    let patch = diff(&old, &new);
+
+    assert_eq!(patch.items.len(), 1);
+    assert_matches!(patch.items[0], PatchItem::MoveChild(2, 0));
+    // moved nodes keep their DOM node, thus their ids must be translated
+    assert_eq!(patch.translations.len(), 4);
+}
+
+#[test]
+fn test_keyed_diff_moved_child() {
+    let old = Frame::<DummyApp>::new_from_vnode(keyed_list(&["a", "b", "c"]));
+    let mut new_vdom = keyed_list(&["x", "c", "a"]);
+    if let VNode::Element(elem) = &mut new_vdom {
+        if let VNode::Element(child) = &mut elem.children[2] {
+            child.children = vec![VNode::text("changed")];
+        }
+    }
+    let new = RenderResult::<DummyApp>::new_from_vnode(new_vdom);
+    let patch = diff(&old, &new);
+
+    let items = &patch.items;
+    assert_eq!(items.len(), 7);
+    assert_matches!(items[0], PatchItem::RemoveChild(1));
+    assert_matches!(items[1], PatchItem::InsertChild(0, _));
+    assert_matches!(items[2], PatchItem::MoveChild(2, 1));
+    assert_matches!(items[3], PatchItem::Descend());
+    assert_matches!(items[4], PatchItem::NextNode(2));
+    assert_matches!(items[5], PatchItem::Descend());
+    assert_matches!(items[6], PatchItem::ChangeText("changed"));
+}
+
+#[test]
+fn test_mixed_keys_diff_by_index() {
+    let old = Frame::<DummyApp>::new_from_vnode(keyed_list(&["a", "b"]));
+    let mut new_vdom = keyed_list(&["a", "b"]);
+    if let VNode::Element(elem) = &mut new_vdom {
+        elem.children.push(VNode::text("unkeyed"));
+    }
+    let new = RenderResult::<DummyApp>::new_from_vnode(new_vdom);
+    let patch = diff(&old, &new);
+
+    assert_matches!(
+        patch.items.last(),
+        Some(PatchItem::AppendSibling(VNode::Text(_)))
+    );
+}
+
+/// Computes the structural hashes of a test vdom bottom-up, as done while rendering.
+fn with_hashes(node: VNode) -> VNode {
+    match node {
+        VNode::Element(mut elem) => {
+            elem.children = elem.children.drain(..).map(with_hashes).collect();
+            elem.hash = elem.structural_hash();
+            VNode::Element(elem)
+        }
+        node => node,
+    }
+}
+
+#[test]
+fn test_hash_skips_unchanged_subtree() {
+    let old_vdom = with_hashes(keyed_list(&["a", "b", "c"]));
+    let new_vdom = with_hashes(keyed_list(&["a", "b", "c"]));
+    let (old_ids, new_ids) = match (&old_vdom, &new_vdom) {
+        (VNode::Element(old), VNode::Element(new)) => {
+            assert!(old.hash.is_some());
+            assert_eq!(old.hash, new.hash);
+            let ids = |elem: &VElement| elem.children.iter().map(|x| x.id()).collect::<Vec<_>>();
+            (ids(old), ids(new))
+        }
+        _ => panic!(),
+    };
+    let old = Frame::<DummyApp>::new_from_vnode(old_vdom);
+    let new = RenderResult::<DummyApp>::new_from_vnode(new_vdom);
+    let patch = diff(&old, &new);
+
+    assert!(patch.items.is_empty());
+    assert_eq!(patch.translations.len(), 4);
+    for (old_id, new_id) in old_ids.iter().zip(new_ids.iter()) {
+        assert_eq!(patch.translations.get(new_id).unwrap(), old_id);
+    }
+}
+
+#[test]
+fn test_hash_detects_changed_subtree() {
+    let old_vdom = with_hashes(keyed_list(&["a", "b", "c"]));
+    let mut new_vdom = keyed_list(&["a", "b", "c"]);
+    if let VNode::Element(elem) = &mut new_vdom {
+        if let VNode::Element(child) = &mut elem.children[1] {
+            child.children = vec![VNode::text("changed")];
+        }
+    }
+    let new_vdom = with_hashes(new_vdom);
+    let old = Frame::<DummyApp>::new_from_vnode(old_vdom);
+    let new = RenderResult::<DummyApp>::new_from_vnode(new_vdom);
+    let patch = diff(&old, &new);
+
+    assert_matches!(patch.items.last(), Some(PatchItem::ChangeText("changed")));
+}
+
+#[test]
+fn test_hash_with_placeholder() {
+    let mut elem = VElement {
+        id: Id::new(),
+        tag: "div".into(),
+        attr: vec![],
+        js_events: vec![],
+        events: vec![],
+        children: vec![VNode::text("foo")],
+        namespace: None,
+        key: None,
+        hash: None,
+    };
+    assert!(elem.structural_hash().is_some());
+    elem.children
+        .push(VNode::Placeholder(Id::new(), Path::new()));
+    assert!(elem.structural_hash().is_none());
+}
+
+/// Applies the initial patch of `old` and the diff to `new` to a `Document` and
+/// verifies that the resulting DOM matches `new`, also by means of its checksum.
+fn assert_diff_applies(old: VNode, new: VNode) {
+    use crate::patch::{DecodedPatch, Document, Node};
+    use crate::vdom::serialize::serialize;
+
+    let apply = |document: &mut Document, data: Vec<u8>| {
+        let patch = DecodedPatch::decode(&data).unwrap();
+        document.apply(&patch).unwrap();
+    };
+
+    let old = Frame::<DummyApp>::new_from_vnode(old);
+    let mut document = Document::new();
+    let mut patch = Patch::new_from_dom(&old.rendered);
+    patch.push_checksum(&old.rendered);
+    apply(&mut document, serialize(&old.rendered, &patch));
+    let expected = Node::from_vnode(&old.rendered.vdom, &old.rendered, &old.translations);
+    assert_eq!(document.children, vec![expected]);
+
+    let new = RenderResult::<DummyApp>::new_from_vnode(new);
+    let mut patch = diff(&old, &new);
+    patch.push_checksum(&new);
+    apply(&mut document, serialize(&new, &patch));
+    let expected = Node::from_vnode(&new.vdom, &new, &patch.translations);
+    assert_eq!(document.children, vec![expected]);
+}
+
+fn element(tag: &str, attr: Vec<Attr>, children: Vec<VNode>) -> VNode {
+    VNode::element(VElement {
+        id: Id::new(),
+        tag: tag.into(),
+        attr,
+        js_events: vec![],
+        events: vec![],
+        children,
+        namespace: None,
+        key: None,
+        hash: None,
+    })
+}
+
+#[test]
+fn test_diff_applies() {
+    let pairs = vec![
+        (
+            keyed_list(&["b", "c", "d"]),
+            keyed_list(&["a", "b", "c", "d"]),
+        ),
+        (keyed_list(&["a", "b", "c", "d"]), keyed_list(&["a", "d"])),
+        (keyed_list(&["a", "b", "c"]), keyed_list(&["c", "a", "b"])),
+        (keyed_list(&["a", "b", "c"]), keyed_list(&["x", "c", "a"])),
+        (keyed_list(&["a", "b"]), keyed_list(&[])),
+        (
+            with_hashes(keyed_list(&["a", "b", "c"])),
+            with_hashes(keyed_list(&["a", "b", "c"])),
+        ),
+        (
+            element(
+                "div",
+                vec![Attr::new("a", "1"), Attr::new("b", "2")],
+                vec![],
+            ),
+            element(
+                "div",
+                vec![Attr::new("c", "3"), Attr::new("a", "4")],
+                vec![],
+            ),
+        ),
+        (
+            element("div", vec![], vec![VNode::text("a"), VNode::text("b")]),
+            element(
+                "div",
+                vec![],
+                vec![
+                    VNode::text("a"),
+                    element("span", vec![], vec![VNode::text("c")]),
+                ],
+            ),
+        ),
+        (
+            element("div", vec![], vec![VNode::text("a"), VNode::text("b")]),
+            element("div", vec![], vec![VNode::text("c")]),
+        ),
+        (
+            element("div", vec![], vec![]),
+            element("span", vec![], vec![VNode::text("a")]),
+        ),
+    ];
+    for (old, new) in pairs {
+        assert_diff_applies(old, new);
+    }
+}
+
+/// Renders a table of `rows` x `cols` cells, of which the cell at `changed` shows another text.
+fn table(rows: usize, cols: usize, changed: (usize, usize)) -> VNode {
+    let rows = (0..rows)
+        .map(|row| {
+            let cells = (0..cols)
+                .map(|col| {
+                    let text = if (row, col) == changed {
+                        "changed".to_string()
+                    } else {
+                        format!("{}:{}", row, col)
+                    };
+                    element(
+                        "td",
+                        vec![Attr::new("class", "cell")],
+                        vec![VNode::text(text)],
+                    )
+                })
+                .collect();
+            element("tr", vec![], cells)
+        })
+        .collect();
+    element("table", vec![], rows)
+}
+
+/// Compares the time to diff a large table with a single changed cell, with and without
+/// structural hashes. Run using `cargo test --release -- --ignored bench_diff_hashed_subtrees`.
+#[test]
+#[ignore]
+fn bench_diff_hashed_subtrees() {
+    use instant::Instant;
+    use std::time::Duration;
+
+    const ITERATIONS: usize = 20;
+    let measure = |hashed: bool| {
+        let prepare = |node| if hashed { with_hashes(node) } else { node };
+        let old = Frame::<DummyApp>::new_from_vnode(prepare(table(500, 20, (0, 0))));
+        let new = RenderResult::<DummyApp>::new_from_vnode(prepare(table(500, 20, (250, 10))));
+        let mut best = Duration::from_secs(3600);
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            let patch = diff(&old, &new);
+            best = best.min(start.elapsed());
+            assert_matches!(patch.items.last(), Some(PatchItem::ChangeText("changed")));
+        }
+        best
+    };
+    let plain = measure(false);
+    let hashed = measure(true);
+    println!(
+        "diff of 10000 cells: {:?} without hashes, {:?} with hashes",
+        plain, hashed
+    );
+    assert!(hashed < plain);
+}
@@ -1,5 +1,6 @@
-use crate::pipe::{Pipe, RxMsg, TxMsg};
+use crate::pipe::{Handshake, Pipe, RxMsg, TxMsg};
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
 use futures::task::Poll;
 use futures::Sink;
 use futures::StreamExt;
@@ -17,6 +18,11 @@ use web_sys::console::log_1;
 ///
 /// This implementation uses `js_sys` and `wasm_bindgen` to create the binding to to transfer
 /// data from the `wasm` process to the js-frontend.
+///
+/// The first message received from the js-frontend must be a [Handshake](../pipe/struct.Handshake.html).
+/// Messages to the frontend are held back until the handshake has succeeded. If it fails,
+/// the frontend receives a JSON object with an `error` field and the receiver yields
+/// `RxMsg::HandshakeFailed`.
 pub struct WasmPipe;
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
@@ -42,12 +48,26 @@ impl Pipe for WasmPipe {
         let (txmsg_tx, txmsg_rx) = unbounded();
         let (rxmsg_tx, rxmsg_rx) = unbounded();
 
-        let endpoint = PipeJsEndpoint { rxmsg_tx };
+        let (handshake_tx, handshake_rx) = oneshot::channel();
+
+        let endpoint = PipeJsEndpoint {
+            rxmsg_tx,
+            handshake_tx: Some(handshake_tx),
+        };
         let mut locked = PIPE.lock().unwrap();
         assert!(!locked.is_some());
         *locked = Some(endpoint);
+        drop(locked);
+        // deliver messages received before the pipe was set up, e.g. the handshake
+        let pending: Vec<_> = PENDING.lock().unwrap().drain(..).collect();
+        for data in pending {
+            greenhorn_send_to_wasm(data);
+        }
 
         crate::platform::spawn(async move {
+            if handshake_rx.await.is_err() {
+                return;
+            }
             let mut txmsg_rx = txmsg_rx;
             while let Some(msg) = txmsg_rx.next().await {
                 match msg {
@@ -74,6 +94,8 @@ impl WasmPipe {
 
 struct PipeJsEndpoint {
     rxmsg_tx: UnboundedSender<RxMsg>,
+    /// Set until the handshake has been received from the frontend
+    handshake_tx: Option<oneshot::Sender<()>>,
 }
 
 #[derive(Clone)]
@@ -109,6 +131,7 @@ impl Sink<TxMsg> for WasmSender {
 
 lazy_static! {
     static ref PIPE: Mutex<Option<PipeJsEndpoint>> = Mutex::new(None);
+    static ref PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
 }
 
 #[wasm_bindgen]
@@ -119,11 +142,31 @@ extern "C" {
 
 #[wasm_bindgen]
 pub fn greenhorn_send_to_wasm(data: String) {
-    if let Ok(msg) = serde_json::from_str::<RxMsg>(&data) {
-        let borrowed = PIPE.lock().unwrap();
-        if let Some(pipe) = &*borrowed {
-            let _ = pipe.rxmsg_tx.unbounded_send(msg);
+    let mut borrowed = PIPE.lock().unwrap();
+    let pipe = match &mut *borrowed {
+        Some(pipe) => pipe,
+        None => {
+            PENDING.lock().unwrap().push(data);
+            return;
         }
+    };
+    if let Some(handshake_tx) = pipe.handshake_tx.take() {
+        match Handshake::negotiate(&data) {
+            Ok(handshake) => {
+                greenhorn_push_string(serde_json::to_string(&handshake).unwrap());
+                let _ = handshake_tx.send(());
+            }
+            Err(err) => {
+                log!("Handshake with frontend failed: {}", err);
+                let reply = serde_json::json!({ "error": err.to_string() });
+                greenhorn_push_string(reply.to_string());
+                let _ = pipe.rxmsg_tx.unbounded_send(RxMsg::HandshakeFailed(err));
+            }
+        }
+        return;
+    }
+    if let Ok(msg) = serde_json::from_str::<RxMsg>(&data) {
+        let _ = pipe.rxmsg_tx.unbounded_send(msg);
     } else {
         log!("Garbage received: {}", data);
     }
@@ -1,26 +1,62 @@
 //! This modules adds a [Pipe](../pipe/trait.Pipe.html) implementation based on WebSockets.
 //!
+//! Right after a frontend has connected, it performs a [Handshake](../pipe/struct.Handshake.html).
+//! In case the handshake fails, the connection is closed with a `CloseCode::Protocol` close frame,
+//! whose reason describes the error.
+//!
+//! Which frontends may connect can be restricted using [Access](struct.Access.html).
+//! Connections may be encrypted (i.e. `wss://`) by passing a [TlsConfig](struct.TlsConfig.html).
+//! Messages exceeding a size threshold are compressed, given the frontend supports it.
+//!
 
-use crate::pipe::{Pipe, RxMsg, TxMsg};
+use crate::pipe::{
+    Handshake, HandshakeError, Pipe, RxMsg, TxMsg, CAPABILITY_DEFLATE, FRAME_DEFLATE_JSON,
+    FRAME_DEFLATE_PATCH, FRAME_RAW_PATCH,
+};
+use async_native_tls::{TlsAcceptor, TlsStream};
 use async_std::net::{TcpListener, TcpStream};
 use async_std::task;
-use async_tungstenite::{accept_async, WebSocketStream};
+use async_tungstenite::{accept_hdr_async, WebSocketStream};
+use flate2::write::DeflateEncoder;
 use futures::channel::mpsc::SendError;
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::io::{AsyncRead, AsyncWrite};
 use futures::prelude::*;
 use futures::select;
+use futures::stream::FuturesUnordered;
 use futures::task::{Context, Poll};
 use futures::Sink;
 use futures::Stream;
+use instant::Instant;
 use log::error;
+use percent_encoding::percent_decode_str;
+use rand::Rng;
+use std::borrow::Cow;
 use std::error::Error;
+use std::io::{self, Write};
 use std::net::SocketAddr;
+use std::path::Path;
 use std::pin::Pin;
-use tungstenite::protocol::Message;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::{CloseFrame, Message};
+
+/// Maximum time until the frontend must have sent its handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum time until the TLS and the WebSocket handshake of a connection must have completed,
+/// each of them
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// The `WebSocketPipe` type implements a [Pipe](../pipe/trait.Pipe.html) on top of WebSockets.
 ///
 /// The `WebSocketPipe` acts as a async server only accepting a single connection.
+/// To serve multiple connections, each running its own application, refer to
+/// [Server](../server/struct.Server.html).
 /// Once created, the `WebSocketPipe` can be `.split()` using the method on the [Pipe](../pipe/trait.Pipe.html)
 /// trait. The resulting [WebSocketSender](struct.WebSocketSender.html) and
 /// [WebSocketReceiver](struct.WebSocketReceiver.html) structs may be used to send and receive
@@ -53,6 +89,7 @@ pub struct WebSocketPipe {
     resp_rx: UnboundedReceiver<Message>,
     req_tx: UnboundedSender<Message>,
     addr: SocketAddr,
+    compression: Arc<Compression>,
 }
 
 impl WebSocketPipe {
@@ -65,19 +102,71 @@ impl WebSocketPipe {
 
     /// Start listening to `TcpListener`.
     pub fn listen_to_socket(listener: TcpListener) -> WebSocketPipe {
+        Self::listen_to_socket_with_access(listener, Access::new())
+    }
+
+    /// Start listening to `TcpListener` and only accept a frontend which is granted `access`.
+    ///
+    /// Rejected connections are closed and the pipe keeps waiting for a frontend.
+    pub fn listen_to_socket_with_access(listener: TcpListener, access: Access) -> WebSocketPipe {
+        Self::listen(listener, access, None)
+    }
+
+    /// Start listening to `TcpListener` and only accept TLS encrypted connections of a frontend
+    /// which is granted `access`.
+    ///
+    /// The TLS handshake is performed before the WebSocket handshake, thus the frontend
+    /// must connect using a `wss://` URL. Connections failing the TLS handshake are closed
+    /// and the pipe keeps waiting for a frontend.
+    pub fn listen_to_socket_with_tls(
+        listener: TcpListener,
+        tls: TlsConfig,
+        access: Access,
+    ) -> WebSocketPipe {
+        Self::listen(listener, access, Some(tls))
+    }
+
+    /// Accepts the first connection on `listener` which passes the TLS handshake, if any,
+    /// and is granted `access`.
+    fn listen(listener: TcpListener, access: Access, tls: Option<TlsConfig>) -> WebSocketPipe {
         let (req_tx, req_rx) = unbounded();
         let (resp_tx, resp_rx) = unbounded();
+        let compression = Arc::new(Compression::new());
+        let compression_cloned = compression.clone();
         let local_addr = listener.local_addr().unwrap();
         let local_addr_cloned = local_addr;
         task::spawn(async move {
-            if let Ok((stream, _)) = listener.accept().await {
-                let ws = accept_async(stream).await.expect("Error during handshake");
+            // handshakes are performed concurrently, such that a stalled client does not
+            // prevent other clients from connecting
+            let mut pending = FuturesUnordered::new();
+            let mut incoming = listener.incoming();
+            let ws = loop {
+                select! {
+                    stream = incoming.next().fuse() => match stream {
+                        Some(Ok(stream)) => pending.push(accept(stream, &access, tls.as_ref())),
+                        Some(Err(err)) => error!("Could not accept connection: {}", err),
+                        None => break None,
+                    },
+                    accepted = pending.select_next_some() => match accepted {
+                        Ok(accepted) => break Some(accepted),
+                        Err(err) => error!("Error during handshake: {}", err),
+                    },
+                }
+            };
+            if let Some(mut ws) = ws {
+                if let Err(err) = handshake(&mut ws, &compression_cloned).await {
+                    report_handshake_error(&resp_tx, err);
+                    resp_tx.close_channel();
+                    return;
+                }
                 let mut handler = ConnectionHandler {
                     ws,
                     resp_tx,
                     req_rx,
+                    idle_timeout: None,
                 };
                 handler.run().await;
+                handler.resp_tx.close_channel();
             } else {
                 error!("Could not accept connection on: {}", local_addr_cloned);
             }
@@ -86,6 +175,213 @@ impl WebSocketPipe {
             resp_rx,
             req_tx,
             addr: local_addr,
+            compression,
+        }
+    }
+
+    /// Starts listening to a given `SocketAddr` and accepts reconnecting frontends.
+    ///
+    /// Refer to [listen_to_socket_with_reconnect()](#method.listen_to_socket_with_reconnect).
+    pub fn listen_to_addr_with_reconnect(addr: SocketAddr, access: Access) -> WebSocketPipe {
+        let try_socket = task::block_on(async { TcpListener::bind(&addr).await });
+        let listener = try_socket.expect("Failed to bind");
+        Self::listen_to_socket_with_reconnect(listener, access)
+    }
+
+    /// Start listening to `TcpListener` and keep accepting connections after
+    /// a connection has been closed.
+    ///
+    /// Only a single connection is served at a time. Unlike with `listen_to_socket()`, the
+    /// `WebSocketReceiver` is not closed once the connection drops. Instead, it yields
+    /// `RxMsg::Disconnected()` and, once a frontend has (re-)connected, `RxMsg::Connected()`.
+    /// Messages sent while no frontend is connected are dropped.
+    /// Only frontends which are granted `access` are connected.
+    pub fn listen_to_socket_with_reconnect(listener: TcpListener, access: Access) -> WebSocketPipe {
+        Self::listen_with_reconnect(listener, access, None)
+    }
+
+    /// Start listening to `TcpListener`, keep accepting connections after a connection has been
+    /// closed and only accept TLS encrypted connections.
+    ///
+    /// Refer to [listen_to_socket_with_reconnect()](#method.listen_to_socket_with_reconnect)
+    /// and [listen_to_socket_with_tls()](#method.listen_to_socket_with_tls).
+    pub fn listen_to_socket_with_reconnect_and_tls(
+        listener: TcpListener,
+        tls: TlsConfig,
+        access: Access,
+    ) -> WebSocketPipe {
+        Self::listen_with_reconnect(listener, access, Some(tls))
+    }
+
+    /// Accepts connections on `listener`, performs the TLS handshake, if any, and serves them
+    /// one after another.
+    fn listen_with_reconnect(
+        listener: TcpListener,
+        access: Access,
+        tls: Option<TlsConfig>,
+    ) -> WebSocketPipe {
+        let local_addr = listener.local_addr().unwrap();
+        let (connections_tx, connections_rx) = unbounded();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(stream) = incoming.next().await {
+                match stream {
+                    Ok(stream) => {
+                        if connections_tx.is_closed() {
+                            return;
+                        }
+                        let connections_tx = connections_tx.clone();
+                        let tls = tls.clone();
+                        task::spawn(async move {
+                            match accept_tls(stream, tls.as_ref()).await {
+                                Ok(connection) => {
+                                    let _ = connections_tx.unbounded_send(connection);
+                                }
+                                Err(err) => error!("Error during TLS handshake: {}", err),
+                            }
+                        });
+                    }
+                    Err(err) => error!("Could not accept connection: {}", err),
+                }
+            }
+        });
+        Self::from_connections(connections_rx, local_addr, access)
+    }
+
+    /// Create a `WebSocketPipe` which serves a sequence of already accepted connections.
+    ///
+    /// Connections are served one after another, as described in
+    /// [listen_to_socket_with_reconnect()](#method.listen_to_socket_with_reconnect).
+    /// Encrypting the connections, if required, is up to the caller.
+    pub(crate) fn from_connections(
+        mut connections: UnboundedReceiver<Connection>,
+        local_addr: SocketAddr,
+        access: Access,
+    ) -> WebSocketPipe {
+        let (req_tx, mut req_rx) = unbounded();
+        let (mut resp_tx, resp_rx) = unbounded();
+        let compression = Arc::new(Compression::new());
+        let compression_cloned = compression.clone();
+        let connected = serde_json::to_string(&RxMsg::Connected()).unwrap();
+        let disconnected = serde_json::to_string(&RxMsg::Disconnected()).unwrap();
+        task::spawn(async move {
+            loop {
+                // wait for the next connection and drop outgoing messages in the meantime
+                let connection = loop {
+                    select! {
+                        connection = connections.next().fuse() => match connection {
+                            Some(connection) => break connection,
+                            None => {
+                                resp_tx.close_channel();
+                                return;
+                            }
+                        },
+                        msg = req_rx.next().fuse() => {
+                            if msg.is_none() {
+                                resp_tx.close_channel();
+                                return;
+                            }
+                        }
+                    }
+                };
+                let mut ws = match accept_websocket(connection, &access).await {
+                    Ok(ws) => ws,
+                    Err(err) => {
+                        error!("Error during handshake: {}", err);
+                        continue;
+                    }
+                };
+                // a stale frontend must not stop the application, thus just wait for the next one
+                if let Err(err) = handshake(&mut ws, &compression_cloned).await {
+                    error!("Rejected frontend: {}", err);
+                    continue;
+                }
+                if resp_tx
+                    .unbounded_send(Message::Text(connected.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+                let mut handler = ConnectionHandler {
+                    ws,
+                    resp_tx,
+                    req_rx,
+                    idle_timeout: None,
+                };
+                let closed = handler.run().await;
+                let ConnectionHandler {
+                    resp_tx: tx,
+                    req_rx: rx,
+                    ..
+                } = handler;
+                if closed == Closed::Backend {
+                    tx.close_channel();
+                    return;
+                }
+                compression_cloned.deflate.store(false, Ordering::SeqCst);
+                if tx
+                    .unbounded_send(Message::Text(disconnected.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+                resp_tx = tx;
+                req_rx = rx;
+            }
+        });
+        WebSocketPipe {
+            resp_rx,
+            req_tx,
+            addr: local_addr,
+            compression,
+        }
+    }
+
+    /// Create a `WebSocketPipe` from an already accepted connection.
+    ///
+    /// The WebSocket handshake is performed in the background and rejected unless the frontend
+    /// is granted `access`. If `tls` is given, the TLS handshake is performed first.
+    /// If an `idle_timeout` is given, the connection is closed once
+    /// no message was received from the frontend for that duration.
+    pub(crate) fn from_stream(
+        stream: TcpStream,
+        idle_timeout: Option<Duration>,
+        access: Access,
+        tls: Option<TlsConfig>,
+    ) -> WebSocketPipe {
+        let (req_tx, req_rx) = unbounded();
+        let (resp_tx, resp_rx) = unbounded();
+        let compression = Arc::new(Compression::new());
+        let compression_cloned = compression.clone();
+        let local_addr = stream.local_addr().unwrap();
+        task::spawn(async move {
+            match accept(stream, &access, tls.as_ref()).await {
+                Ok(mut ws) => {
+                    if let Err(err) = handshake(&mut ws, &compression_cloned).await {
+                        report_handshake_error(&resp_tx, err);
+                        resp_tx.close_channel();
+                        return;
+                    }
+                    let mut handler = ConnectionHandler {
+                        ws,
+                        resp_tx,
+                        req_rx,
+                        idle_timeout,
+                    };
+                    handler.run().await;
+                    handler.resp_tx.close_channel();
+                }
+                Err(err) => {
+                    error!("Error during handshake: {}", err);
+                    resp_tx.close_channel();
+                }
+            }
+        });
+        WebSocketPipe {
+            resp_rx,
+            req_tx,
+            addr: local_addr,
+            compression,
         }
     }
 
@@ -101,71 +397,529 @@ impl WebSocketPipe {
     pub fn port(&self) -> u16 {
         self.addr.port()
     }
+
+    /// Sets the size in bytes above which messages are compressed.
+    ///
+    /// Compression is only applied if the frontend supports it, which is negotiated
+    /// during the [Handshake](../pipe/struct.Handshake.html). Passing `None` disables compression.
+    /// Defaults to [DEFAULT_COMPRESSION_THRESHOLD](constant.DEFAULT_COMPRESSION_THRESHOLD.html).
+    pub fn compression_threshold(self, threshold: Option<usize>) -> Self {
+        let threshold = threshold.unwrap_or(usize::MAX);
+        self.compression
+            .threshold
+            .store(threshold, Ordering::SeqCst);
+        self
+    }
+}
+
+/// Size in bytes above which messages are compressed by default.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Compression settings shared between the `WebSocketSender` and the connection task.
+struct Compression {
+    /// Whether the connected frontend supports `CAPABILITY_DEFLATE`
+    deflate: AtomicBool,
+    threshold: AtomicUsize,
+}
+
+impl Compression {
+    fn new() -> Self {
+        Self {
+            deflate: AtomicBool::new(false),
+            threshold: AtomicUsize::new(DEFAULT_COMPRESSION_THRESHOLD),
+        }
+    }
+
+    /// Encodes a message to be sent to the frontend.
+    ///
+    /// Messages exceeding the threshold are compressed if the frontend supports it.
+    fn encode(&self, msg: TxMsg) -> Message {
+        let deflate = self.deflate.load(Ordering::SeqCst);
+        let threshold = self.threshold.load(Ordering::SeqCst);
+        match msg {
+            TxMsg::Patch(data) if !deflate => Message::Binary(data),
+            TxMsg::Patch(data) if data.len() < threshold => {
+                let mut framed = Vec::with_capacity(data.len() + 1);
+                framed.push(FRAME_RAW_PATCH);
+                framed.extend_from_slice(&data);
+                Message::Binary(framed)
+            }
+            TxMsg::Patch(data) => Message::Binary(compress(FRAME_DEFLATE_PATCH, &data)),
+            msg => {
+                // for performance notes regarding serialization and underlying transport, refer to index.js
+                // tldr: JSON.parse() in the browser is very fast
+                let msg = serde_json::to_string(&msg).unwrap();
+                if deflate && msg.len() >= threshold {
+                    Message::Binary(compress(FRAME_DEFLATE_JSON, msg.as_bytes()))
+                } else {
+                    Message::Text(msg)
+                }
+            }
+        }
+    }
+}
+
+/// Compresses `data` using raw deflate and prefixes it with the `header` byte.
+fn compress(header: u8, data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(vec![header], flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Restricts which frontends may connect to a [WebSocketPipe](struct.WebSocketPipe.html).
+///
+/// By default, any connection is accepted. Since a `WebSocketPipe` usually listens on a
+/// local port, this allows any local process or any web page open in a browser to drive
+/// the application. Thus, a secret token should be required, which is then passed to the
+/// frontend, e.g. by embedding it in the page loading the frontend.
+/// Additionally, the `Origin` of connecting web pages may be restricted to an allowlist.
+///
+/// # Example
+///
+/// ```
+/// # use greenhorn::websockets::Access;
+/// let token = Access::generate_token();
+/// let access = Access::new()
+///     .require_token(token)
+///     .allow_origin("http://localhost:8080");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    token: Option<String>,
+    origins: Vec<String>,
+}
+
+impl Access {
+    /// Creates an `Access` object, which grants access to any frontend.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Requires frontends to present the given secret token.
+    ///
+    /// The token is either passed as percent-encoded `token` query parameter of the WebSocket
+    /// URL, e.g. `ws://127.0.0.1:44132/?token=...`, or as `Authorization: Bearer ...` header.
+    pub fn require_token<T: Into<String>>(mut self, token: T) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Adds an origin, such as `http://localhost:8080`, to the allowlist.
+    ///
+    /// Once an origin has been added, connections without or with a different
+    /// `Origin` header are rejected.
+    pub fn allow_origin<T: Into<String>>(mut self, origin: T) -> Self {
+        self.origins.push(origin.into());
+        self
+    }
+
+    /// Returns the token required from frontends, if any.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Generates a random token suitable for `require_token()`.
+    pub fn generate_token() -> String {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    /// Checks whether the WebSocket handshake `request` is granted access.
+    fn check(&self, request: &Request) -> Result<(), String> {
+        if !self.origins.is_empty() {
+            let origin = request
+                .headers()
+                .get("Origin")
+                .and_then(|x| x.to_str().ok());
+            match origin {
+                Some(origin) if self.origins.iter().any(|x| x == origin) => {}
+                Some(origin) => return Err(format!("Origin not allowed: {}", origin)),
+                None => return Err("Origin missing".to_string()),
+            }
+        }
+        if let Some(token) = &self.token {
+            let from_query = request.uri().query().and_then(|query| {
+                query
+                    .split('&')
+                    .find_map(|x| x.strip_prefix("token="))
+                    .map(|x| percent_decode_str(x).decode_utf8_lossy().into_owned())
+            });
+            let from_header = request
+                .headers()
+                .get("Authorization")
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| x.strip_prefix("Bearer "))
+                .map(|x| x.to_string());
+            let presented = from_query.or(from_header).unwrap_or_default();
+            if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+                return Err("Invalid token".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares two byte strings in constant time, such that the token cannot be
+/// guessed by timing the response.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Certificate and private key used to encrypt WebSocket connections.
+///
+/// Refer to [listen_to_socket_with_tls()](struct.WebSocketPipe.html#method.listen_to_socket_with_tls).
+///
+/// # Example
+///
+/// ```no_run
+/// # use greenhorn::websockets::TlsConfig;
+/// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsConfig {
+    /// Creates a `TlsConfig` from a PEM encoded certificate (chain) and PKCS #8 private key.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, native_tls::Error> {
+        let identity = native_tls::Identity::from_pkcs8(cert, key)?;
+        let acceptor = native_tls::TlsAcceptor::new(identity)?;
+        Ok(Self {
+            acceptor: acceptor.into(),
+        })
+    }
+
+    /// Reads a PEM encoded certificate (chain) and PKCS #8 private key from the given files.
+    ///
+    /// Refer to [from_pem()](#method.from_pem).
+    pub fn from_pem_files<C: AsRef<Path>, K: AsRef<Path>>(cert: C, key: K) -> io::Result<Self> {
+        let cert = std::fs::read(cert)?;
+        let key = std::fs::read(key)?;
+        Self::from_pem(&cert, &key).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// A connection, which is optionally encrypted using TLS.
+enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_close(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+/// An accepted connection, which is optionally encrypted using TLS.
+///
+/// Data which has already been read from the connection, e.g. the head of an HTTP request
+/// routed by the [HttpServer](../http/struct.HttpServer.html), is replayed before reading
+/// from the underlying stream.
+pub(crate) struct Connection {
+    stream: MaybeTlsStream,
+    replay: Vec<u8>,
+    replayed: usize,
+}
+
+impl Connection {
+    /// Creates an unencrypted connection.
+    pub(crate) fn plain(stream: TcpStream) -> Self {
+        Self {
+            stream: MaybeTlsStream::Plain(stream),
+            replay: vec![],
+            replayed: 0,
+        }
+    }
+
+    /// Replays `data` before reading from the connection.
+    pub(crate) fn replay(mut self, data: Vec<u8>) -> Self {
+        self.replay = data;
+        self.replayed = 0;
+        self
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let remaining = &this.replay[this.replayed..];
+        if !remaining.is_empty() {
+            let len = remaining.len().min(buf.len());
+            buf[..len].copy_from_slice(&remaining[..len]);
+            this.replayed += len;
+            return Poll::Ready(Ok(len));
+        }
+        Pin::new(&mut this.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_close(cx)
+    }
+}
+
+/// Performs the WebSocket handshake on an accepted connection, given the frontend is granted `access`.
+///
+/// If `tls` is given, the connection is encrypted before.
+// the error response type is defined by tungstenite
+#[allow(clippy::result_large_err)]
+pub(crate) async fn accept(
+    stream: TcpStream,
+    access: &Access,
+    tls: Option<&TlsConfig>,
+) -> Result<WebSocketStream<Connection>, tungstenite::Error> {
+    let connection = accept_tls(stream, tls).await?;
+    accept_websocket(connection, access).await
+}
+
+/// Performs the TLS handshake on an accepted connection if `tls` is given.
+///
+/// Fails if the handshake has not completed within `ACCEPT_TIMEOUT`.
+pub(crate) async fn accept_tls(
+    stream: TcpStream,
+    tls: Option<&TlsConfig>,
+) -> io::Result<Connection> {
+    let tls = match tls {
+        Some(tls) => tls,
+        None => return Ok(Connection::plain(stream)),
+    };
+    // `io::Error::other()` requires Rust 1.74
+    #[allow(clippy::io_other_error)]
+    let handshake = async {
+        tls.acceptor
+            .accept(stream)
+            .await
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    };
+    let stream = async_std::io::timeout(ACCEPT_TIMEOUT, handshake).await?;
+    Ok(Connection {
+        stream: MaybeTlsStream::Tls(Box::new(stream)),
+        replay: vec![],
+        replayed: 0,
+    })
+}
+
+/// Performs the WebSocket handshake on a connection, given the frontend is granted `access`.
+///
+/// Fails if the handshake has not completed within `ACCEPT_TIMEOUT`.
+// the error response type is defined by tungstenite
+#[allow(clippy::result_large_err)]
+async fn accept_websocket(
+    connection: Connection,
+    access: &Access,
+) -> Result<WebSocketStream<Connection>, tungstenite::Error> {
+    let callback = |request: &Request, response: Response| match access.check(request) {
+        Ok(()) => Ok(response),
+        Err(reason) => {
+            error!("Rejected connection: {}", reason);
+            let mut response = ErrorResponse::new(Some(reason));
+            *response.status_mut() = StatusCode::FORBIDDEN;
+            Err(response)
+        }
+    };
+    match async_std::future::timeout(ACCEPT_TIMEOUT, accept_hdr_async(connection, callback)).await {
+        Ok(result) => result,
+        Err(_) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "WebSocket handshake timed out").into())
+        }
+    }
+}
+
+/// Performs the protocol handshake on a newly accepted connection.
+///
+/// Waits for the handshake of the frontend and replies with the negotiated protocol.
+/// Enables `compression` if negotiated.
+/// On failure, the connection is closed with a `CloseCode::Protocol` close frame.
+async fn handshake(
+    ws: &mut WebSocketStream<Connection>,
+    compression: &Compression,
+) -> Result<Handshake, HandshakeError> {
+    let result = match async_std::future::timeout(HANDSHAKE_TIMEOUT, ws.next()).await {
+        Ok(Some(Ok(Message::Text(data)))) => {
+            Handshake::negotiate_with(&data, &[CAPABILITY_DEFLATE])
+        }
+        Ok(Some(Ok(_))) => Err(HandshakeError::Invalid("Expected a text message".into())),
+        Ok(Some(Err(err))) => Err(HandshakeError::Invalid(err.to_string())),
+        Ok(None) => Err(HandshakeError::Invalid("Connection closed".into())),
+        Err(_) => Err(HandshakeError::Timeout),
+    };
+    match &result {
+        Ok(handshake) => {
+            let deflate = handshake.supports(CAPABILITY_DEFLATE);
+            compression.deflate.store(deflate, Ordering::SeqCst);
+            let data = serde_json::to_string(handshake).unwrap();
+            if let Err(err) = ws.send(Message::Text(data)).await {
+                return Err(HandshakeError::Invalid(err.to_string()));
+            }
+        }
+        Err(err) => {
+            let frame = CloseFrame {
+                code: CloseCode::Protocol,
+                reason: Cow::from(err.to_string()),
+            };
+            let _ = ws.close(Some(frame)).await;
+        }
+    }
+    result
+}
+
+/// Logs a failed handshake and forwards it to the `WebSocketReceiver`.
+fn report_handshake_error(resp_tx: &UnboundedSender<Message>, err: HandshakeError) {
+    error!("Handshake with frontend failed: {}", err);
+    let msg = serde_json::to_string(&RxMsg::HandshakeFailed(err)).unwrap();
+    let _ = resp_tx.unbounded_send(Message::Text(msg));
 }
 
 /// Relays incoming WebSocket messages to the `WebSocketReceiver` and
 /// receives messages from the `WebSocketSender` and sends them to the WebSocket connection.
 struct ConnectionHandler {
-    ws: WebSocketStream<TcpStream>,
+    ws: WebSocketStream<Connection>,
     resp_tx: UnboundedSender<Message>,
     req_rx: UnboundedReceiver<Message>,
+    idle_timeout: Option<Duration>,
+}
+
+/// Reason why a `ConnectionHandler` stopped running.
+#[derive(Debug, PartialEq)]
+enum Closed {
+    /// The backend, i.e. the `WebSocketSender` or `WebSocketReceiver`, was dropped or closed
+    Backend,
+    /// The WebSocket connection was closed, failed or timed out
+    Connection,
 }
 
 impl ConnectionHandler {
     /// Task which only returns once the connection of either socket or receiver has been closed.
-    async fn run(&mut self) {
+    ///
+    /// The connection is also closed if the idle timeout expires.
+    /// Note that the `resp_tx` channel is left open, such that the caller may decide whether to
+    /// close it.
+    async fn run(&mut self) -> Closed {
+        let mut last_rx = Instant::now();
         loop {
+            let idle_timeout = self.idle_timeout;
+            let timeout = async move {
+                match idle_timeout {
+                    Some(timeout) => {
+                        let remaining = timeout.checked_sub(last_rx.elapsed()).unwrap_or_default();
+                        task::sleep(remaining).await
+                    }
+                    None => future::pending().await,
+                }
+            };
             select! {
                 msg = self.req_rx.next().fuse() => {
-                    if !self.tx_msg(msg).await {
-                        return;
+                    if let Some(closed) = self.tx_msg(msg).await {
+                        return closed;
                     }
                 },
                 msg = self.ws.next().fuse() => {
-                    if !self.rx_msg(msg).await {
-                        return;
+                    last_rx = Instant::now();
+                    if let Some(closed) = self.rx_msg(msg).await {
+                        return closed;
                     }
+                },
+                _ = timeout.fuse() => {
+                    log::info!("Closing idle connection");
+                    let _ = self.ws.close(None).await;
+                    return Closed::Connection;
                 }
             }
         }
     }
 
     /// Handles a message from the `WebSocketSender`.
-    async fn tx_msg(&mut self, msg: Option<Message>) -> bool {
+    async fn tx_msg(&mut self, msg: Option<Message>) -> Option<Closed> {
         match msg {
             None => {
-                self.resp_tx.close_channel();
-                self.ws.close(None).await.unwrap();
-                false
+                let _ = self.ws.close(None).await;
+                Some(Closed::Backend)
             }
             Some(msg) => {
                 if let Err(_e) = self.ws.send(msg).await {
-                    self.resp_tx.close_channel();
-                    self.ws.close(None).await.unwrap();
-                    return false;
+                    let _ = self.ws.close(None).await;
+                    return Some(Closed::Connection);
                 }
-                true
+                None
             }
         }
     }
 
     /// Handles a message from the websocket connection.
-    async fn rx_msg(&mut self, msg: Option<Result<Message, tungstenite::Error>>) -> bool {
+    async fn rx_msg(&mut self, msg: Option<Result<Message, tungstenite::Error>>) -> Option<Closed> {
         if let Some(msg) = msg {
             match msg {
                 Ok(msg) => {
-                    self.resp_tx.unbounded_send(msg).unwrap();
-                    true
+                    if self.resp_tx.unbounded_send(msg).is_err() {
+                        // receiver is gone, e.g. because the application has quit
+                        let _ = self.ws.close(None).await;
+                        return Some(Closed::Backend);
+                    }
+                    None
                 }
                 Err(err) => {
-                    self.resp_tx.close_channel();
                     log::error!("Websocket Error Occured: {}", err);
-                    false
+                    Some(Closed::Connection)
                 }
             }
         } else {
-            self.resp_tx.close_channel();
-            false
+            Some(Closed::Connection)
         }
     }
 }
@@ -176,6 +930,8 @@ impl ConnectionHandler {
 /// It is created by `.split()`-ing a [WebSocketPipe](struct.WebSocketPipe.html).
 pub struct WebSocketSender {
     req_tx: UnboundedSender<Message>,
+    transmitted_tx: UnboundedSender<usize>,
+    compression: Arc<Compression>,
 }
 
 impl Sink<TxMsg> for WebSocketSender {
@@ -190,17 +946,14 @@ impl Sink<TxMsg> for WebSocketSender {
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: TxMsg) -> Result<(), Self::Error> {
-        let msg = match item {
-            TxMsg::Patch(p) => Message::Binary(p),
-            msg => {
-                // for performance notes regarding serialization and underlying transport, refer to index.js
-                // tldr: JSON.parse() in the browser is very fast
-                let msg = serde_json::to_string(&msg).unwrap();
-                Message::Text(msg)
-            }
-        };
-
-        UnboundedSender::start_send(&mut self.req_tx, msg).map_err(|x| Box::new(x).into())
+        let is_patch = matches!(item, TxMsg::Patch(_));
+        let msg = self.compression.encode(item);
+        let size = msg.len();
+        UnboundedSender::start_send(&mut self.req_tx, msg)?;
+        if is_patch {
+            let _ = self.transmitted_tx.unbounded_send(size);
+        }
+        Ok(())
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -219,6 +972,8 @@ impl Clone for WebSocketSender {
     fn clone(&self) -> Self {
         Self {
             req_tx: self.req_tx.clone(),
+            transmitted_tx: self.transmitted_tx.clone(),
+            compression: self.compression.clone(),
         }
     }
 }
@@ -229,6 +984,7 @@ impl Clone for WebSocketSender {
 /// It is created by `.split()`-ing a [WebSocketPipe](struct.WebSocketPipe.html).
 pub struct WebSocketReceiver {
     resp_rx: UnboundedReceiver<Message>,
+    transmitted_rx: UnboundedReceiver<usize>,
 }
 
 impl Pipe for WebSocketPipe {
@@ -236,12 +992,16 @@ impl Pipe for WebSocketPipe {
     type Receiver = WebSocketReceiver;
 
     fn split(self) -> (Self::Sender, Self::Receiver) {
+        let (transmitted_tx, transmitted_rx) = unbounded();
         (
             WebSocketSender {
                 req_tx: self.req_tx,
+                transmitted_tx,
+                compression: self.compression,
             },
             WebSocketReceiver {
                 resp_rx: self.resp_rx,
+                transmitted_rx,
             },
         )
     }
@@ -251,6 +1011,11 @@ impl Stream for WebSocketReceiver {
     type Item = RxMsg;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // the sender may be dropped before the connection is closed, thus only `resp_rx`
+        // determines whether this stream has terminated
+        if let Poll::Ready(Some(size)) = Pin::new(&mut self.transmitted_rx).poll_next(cx) {
+            return Poll::Ready(Some(RxMsg::PatchTransmitted(size)));
+        }
         let pin = Pin::new(&mut self.resp_rx);
         let ret: Poll<Option<Message>> = pin.poll_next(cx);
         match ret {
@@ -272,13 +1037,34 @@ impl Stream for WebSocketReceiver {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::pipe::PROTOCOL_VERSION;
     use assert_matches::assert_matches;
     use async_tungstenite::async_std::connect_async;
+    use async_tungstenite::client_async;
     use std::str::FromStr;
     use url::Url;
 
+    /// Performs the handshake on the client side of a connection
+    pub(crate) async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+        ws: &mut WebSocketStream<S>,
+    ) {
+        let handshake = Handshake {
+            version: PROTOCOL_VERSION,
+            capabilities: vec![],
+        };
+        let data = serde_json::to_string(&handshake).unwrap();
+        ws.send(Message::Text(data)).await.unwrap();
+        match ws.next().await {
+            Some(Ok(Message::Text(data))) => {
+                let reply: Handshake = serde_json::from_str(&data).unwrap();
+                assert_eq!(reply, handshake);
+            }
+            _ => panic!(),
+        }
+    }
+
     #[test]
     fn test_accept() {
         let addr = SocketAddr::from_str("127.0.0.1:5903").unwrap();
@@ -286,6 +1072,7 @@ mod tests {
         let handle = task::spawn(async move {
             let url = Url::parse("ws://127.0.0.1:5903").unwrap();
             let (mut stream, _) = connect_async(url).await.expect("Failed to connect!");
+            client_handshake(&mut stream).await;
             if let Some(msg) = stream.next().await {
                 match msg.unwrap() {
                     Message::Text(txt) => assert_eq!(txt, "Hello, World".to_string()),
@@ -315,6 +1102,7 @@ mod tests {
         let client = task::spawn(async move {
             let url = Url::parse("ws://127.0.0.1:5904").unwrap();
             let (mut stream, _) = connect_async(url).await.expect("Failed to connect!");
+            client_handshake(&mut stream).await;
             while let Some(msg) = stream.next().await {
                 // receive one message, then terminate
                 match msg {
@@ -347,6 +1135,7 @@ mod tests {
         let client = task::spawn(async move {
             let url = Url::parse("ws://127.0.0.1:5905").unwrap();
             let (mut stream, _) = connect_async(url).await.expect("Failed to connect!");
+            client_handshake(&mut stream).await;
             while let Some(msg) = stream.next().await {
                 // receive one message, then terminate
                 match msg.unwrap() {
@@ -368,4 +1157,254 @@ mod tests {
         });
         task::block_on(client);
     }
+
+    #[test]
+    fn test_handshake_version_mismatch() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipe = WebSocketPipe::listen_to_socket(TcpListener::from(listener));
+        let port = pipe.port();
+        let (_tx, mut rx) = pipe.split();
+        task::block_on(async move {
+            let url = Url::parse(&format!("ws://127.0.0.1:{}", port)).unwrap();
+            let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let (mut ws, _) = client_async(url, TcpStream::from(stream)).await.unwrap();
+            let handshake = "{\"version\": 0, \"capabilities\": []}";
+            ws.send(Message::Text(handshake.into())).await.unwrap();
+            match ws.next().await {
+                Some(Ok(Message::Close(Some(frame)))) => {
+                    assert_eq!(frame.code, CloseCode::Protocol)
+                }
+                _ => panic!(),
+            }
+            let err = HandshakeError::VersionMismatch {
+                backend: PROTOCOL_VERSION,
+                frontend: 0,
+            };
+            assert_matches!(rx.next().await, Some(RxMsg::HandshakeFailed(x)) if x == err);
+            assert!(rx.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_access_check() {
+        let request = |uri: &str, headers: &[(&str, &str)]| {
+            let mut builder = Request::builder().uri(uri);
+            for (key, value) in headers {
+                builder = builder.header(*key, *value);
+            }
+            builder.body(()).unwrap()
+        };
+        assert!(Access::new().check(&request("/", &[])).is_ok());
+
+        let access = Access::new().require_token("secret");
+        assert!(access.check(&request("/?token=secret", &[])).is_ok());
+        assert!(access.check(&request("/?a=b&token=secret", &[])).is_ok());
+        assert!(access
+            .check(&request("/", &[("Authorization", "Bearer secret")]))
+            .is_ok());
+        assert!(access.check(&request("/", &[])).is_err());
+        assert!(access.check(&request("/?token=wrong", &[])).is_err());
+
+        // tokens in the query are percent-encoded
+        let access = Access::new().require_token("a b&c%");
+        assert!(access.check(&request("/?token=a%20b%26c%25", &[])).is_ok());
+        assert!(access.check(&request("/?token=a%20b", &[])).is_err());
+
+        let access = Access::new().allow_origin("http://localhost:8080");
+        assert!(access
+            .check(&request("/", &[("Origin", "http://localhost:8080")]))
+            .is_ok());
+        assert!(access
+            .check(&request("/", &[("Origin", "http://evil.com")]))
+            .is_err());
+        assert!(access.check(&request("/", &[])).is_err());
+
+        let token = Access::generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, Access::generate_token());
+    }
+
+    #[test]
+    fn test_reject_without_token() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let access = Access::new().require_token("secret");
+        let pipe = WebSocketPipe::listen_to_socket_with_access(TcpListener::from(listener), access);
+        let port = pipe.port();
+        task::block_on(async move {
+            let connect = |query: &str| {
+                let url = Url::parse(&format!("ws://127.0.0.1:{}/{}", port, query)).unwrap();
+                let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+                client_async(url, TcpStream::from(stream))
+            };
+            assert!(connect("").await.is_err());
+            // the pipe keeps waiting for an authorized frontend
+            let (mut ws, _) = connect("?token=secret").await.unwrap();
+            client_handshake(&mut ws).await;
+        });
+    }
+
+    #[test]
+    fn test_reconnect_requires_access() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let access = Access::new().require_token("secret");
+        let pipe =
+            WebSocketPipe::listen_to_socket_with_reconnect(TcpListener::from(listener), access);
+        let port = pipe.port();
+        let (_tx, mut rx) = pipe.split();
+        task::block_on(async move {
+            let connect = |query: &str| {
+                let url = Url::parse(&format!("ws://127.0.0.1:{}/{}", port, query)).unwrap();
+                let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+                client_async(url, TcpStream::from(stream))
+            };
+            assert!(connect("").await.is_err());
+            let (mut ws, _) = connect("?token=secret").await.unwrap();
+            client_handshake(&mut ws).await;
+            assert_matches!(rx.next().await, Some(RxMsg::Connected()));
+        });
+    }
+
+    #[test]
+    fn test_stalled_client() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipe = WebSocketPipe::listen_to_socket(TcpListener::from(listener));
+        let port = pipe.port();
+        task::block_on(async move {
+            // connects, but never starts the WebSocket handshake
+            let _stalled = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let url = Url::parse(&format!("ws://127.0.0.1:{}", port)).unwrap();
+            let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let (mut ws, _) = client_async(url, TcpStream::from(stream)).await.unwrap();
+            client_handshake(&mut ws).await;
+        });
+    }
+
+    /// Creates a `TlsConfig` using a self-signed certificate for `localhost`.
+    pub(crate) fn test_tls_config() -> TlsConfig {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        TlsConfig::from_pem(
+            cert.serialize_pem().unwrap().as_bytes(),
+            cert.serialize_private_key_pem().as_bytes(),
+        )
+        .unwrap()
+    }
+
+    /// Connects to `port` using TLS, accepting any certificate.
+    pub(crate) async fn connect_tls(port: u16) -> async_native_tls::TlsStream<TcpStream> {
+        let connector = async_native_tls::TlsConnector::new().danger_accept_invalid_certs(true);
+        let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        connector
+            .connect("localhost", TcpStream::from(stream))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reconnect_tls() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipe = WebSocketPipe::listen_to_socket_with_reconnect_and_tls(
+            TcpListener::from(listener),
+            test_tls_config(),
+            Access::new(),
+        );
+        let port = pipe.port();
+        let (_tx, mut rx) = pipe.split();
+        task::block_on(async move {
+            let url = Url::parse(&format!("wss://localhost:{}", port)).unwrap();
+            let (mut ws, _) = client_async(url, connect_tls(port).await).await.unwrap();
+            client_handshake(&mut ws).await;
+            assert_matches!(rx.next().await, Some(RxMsg::Connected()));
+        });
+    }
+
+    #[test]
+    fn test_tls() {
+        let tls = test_tls_config();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipe = WebSocketPipe::listen_to_socket_with_tls(
+            TcpListener::from(listener),
+            tls,
+            Access::new(),
+        );
+        let port = pipe.port();
+        let (mut tx, _rx) = pipe.split();
+        task::block_on(async move {
+            let url = Url::parse(&format!("wss://localhost:{}", port)).unwrap();
+            let (mut ws, _) = client_async(url, connect_tls(port).await).await.unwrap();
+            client_handshake(&mut ws).await;
+            tx.send(TxMsg::Ping()).await.unwrap();
+            match ws.next().await {
+                Some(Ok(Message::Text(data))) => {
+                    let msg: TxMsg = serde_json::from_str(&data).unwrap();
+                    assert_matches!(msg, TxMsg::Ping());
+                }
+                _ => panic!(),
+            }
+        });
+    }
+
+    #[test]
+    fn test_compression() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipe = WebSocketPipe::listen_to_socket(TcpListener::from(listener))
+            .compression_threshold(Some(16));
+        let port = pipe.port();
+        let (mut tx, mut rx) = pipe.split();
+        task::block_on(async move {
+            let url = Url::parse(&format!("ws://127.0.0.1:{}", port)).unwrap();
+            let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let (mut ws, _) = client_async(url, TcpStream::from(stream)).await.unwrap();
+            let handshake = Handshake {
+                version: PROTOCOL_VERSION,
+                capabilities: vec![CAPABILITY_DEFLATE.to_string()],
+            };
+            let data = serde_json::to_string(&handshake).unwrap();
+            ws.send(Message::Text(data)).await.unwrap();
+            match ws.next().await {
+                Some(Ok(Message::Text(data))) => {
+                    let reply: Handshake = serde_json::from_str(&data).unwrap();
+                    assert!(reply.supports(CAPABILITY_DEFLATE));
+                }
+                _ => panic!(),
+            }
+
+            let decompress = |data: &[u8]| {
+                let mut ret = Vec::new();
+                let mut decoder = flate2::read::DeflateDecoder::new(data);
+                io::Read::read_to_end(&mut decoder, &mut ret).unwrap();
+                ret
+            };
+
+            // small messages are sent raw
+            tx.send(TxMsg::Patch(vec![1, 2, 3])).await.unwrap();
+            assert_matches!(ws.next().await, Some(Ok(Message::Binary(data))) if data == vec![FRAME_RAW_PATCH, 1, 2, 3]);
+            assert_matches!(rx.next().await, Some(RxMsg::PatchTransmitted(4)));
+            tx.send(TxMsg::Ping()).await.unwrap();
+            assert_matches!(ws.next().await, Some(Ok(Message::Text(_))));
+
+            // large messages are compressed
+            let patch = vec![7; 4096];
+            tx.send(TxMsg::Patch(patch.clone())).await.unwrap();
+            let size = match ws.next().await {
+                Some(Ok(Message::Binary(data))) => {
+                    assert_eq!(data[0], FRAME_DEFLATE_PATCH);
+                    assert_eq!(decompress(&data[1..]), patch);
+                    data.len()
+                }
+                _ => panic!(),
+            };
+            assert!(size < patch.len());
+            assert_matches!(rx.next().await, Some(RxMsg::PatchTransmitted(x)) if x == size);
+            let css = "a".repeat(1024);
+            tx.send(TxMsg::LoadCss(css.clone())).await.unwrap();
+            match ws.next().await {
+                Some(Ok(Message::Binary(data))) => {
+                    assert_eq!(data[0], FRAME_DEFLATE_JSON);
+                    let msg: TxMsg = serde_json::from_slice(&decompress(&data[1..])).unwrap();
+                    assert_matches!(msg, TxMsg::LoadCss(x) if x == css);
+                }
+                _ => panic!(),
+            }
+        });
+    }
 }
@@ -1 +1,1252 @@
-var greenhorn=function(e){var t={};function n(i){if(t[i])return t[i].exports;var r=t[i]={i:i,l:!1,exports:{}};return e[i].call(r.exports,r,r.exports,n),r.l=!0,r.exports}return n.m=e,n.c=t,n.d=function(e,t,i){n.o(e,t)||Object.defineProperty(e,t,{enumerable:!0,get:i})},n.r=function(e){"undefined"!=typeof Symbol&&Symbol.toStringTag&&Object.defineProperty(e,Symbol.toStringTag,{value:"Module"}),Object.defineProperty(e,"__esModule",{value:!0})},n.t=function(e,t){if(1&t&&(e=n(e)),8&t)return e;if(4&t&&"object"==typeof e&&e&&e.__esModule)return e;var i=Object.create(null);if(n.r(i),Object.defineProperty(i,"default",{enumerable:!0,value:e}),2&t&&"string"!=typeof e)for(var r in e)n.d(i,r,function(t){return e[t]}.bind(null,r));return i},n.n=function(e){var t=e&&e.__esModule?function(){return e.default}:function(){return e};return n.d(t,"a",t),t},n.o=function(e,t){return Object.prototype.hasOwnProperty.call(e,t)},n.p="/",n(n.s=3)}([function(module,__webpack_exports__,__webpack_require__){"use strict";function _createForOfIteratorHelper(e){if("undefined"==typeof Symbol||null==e[Symbol.iterator]){if(Array.isArray(e)||(e=_unsupportedIterableToArray(e))){var t=0,n=function(){};return{s:n,n:function(){return t>=e.length?{done:!0}:{done:!1,value:e[t++]}},e:function(e){throw e},f:n}}throw new TypeError("Invalid attempt to iterate non-iterable instance.\nIn order to be iterable, non-array objects must have a [Symbol.iterator]() method.")}var i,r,s=!0,a=!1;return{s:function(){i=e[Symbol.iterator]()},n:function(){var e=i.next();return s=e.done,e},e:function(e){a=!0,r=e},f:function(){try{s||null==i.return||i.return()}finally{if(a)throw r}}}}function _unsupportedIterableToArray(e,t){if(e){if("string"==typeof e)return _arrayLikeToArray(e,t);var n=Object.prototype.toString.call(e).slice(8,-1);return"Object"===n&&e.constructor&&(n=e.constructor.name),"Map"===n||"Set"===n?Array.from(e):"Arguments"===n||/^(?:Ui|I)nt(?:8|16|32)(?:Clamped)?Array$/.test(n)?_arrayLikeToArray(e,t):void 0}}function _arrayLikeToArray(e,t){(null==t||t>e.length)&&(t=e.length);for(var n=0,i=new Array(t);n<t;n++)i[n]=e[n];return i}function _defineProperties(e,t){for(var n=0;n<t.length;n++){var i=t[n];i.enumerable=i.enumerable||!1,i.configurable=!0,"value"in i&&(i.writable=!0),Object.defineProperty(e,i.key,i)}}function _createClass(e,t,n){return t&&_defineProperties(e.prototype,t),n&&_defineProperties(e,n),e}function _classCallCheck(e,t){if(!(e instanceof t))throw new TypeError("Cannot call a class as a function")}__webpack_require__.d(__webpack_exports__,"a",(function(){return App}));var decoder=new TextDecoder;function loadCss(e){var t=document.createElement("style");t.innerHTML=e,document.getElementsByTagName("head")[0].appendChild(t)}function injectEvent(e,t,n){var i=deserializeEvent(e),r='[__id__="'+i.__id__+'"]';document.querySelector(r).dispatchEvent(i)}function deserializeEvent(e){if(e.hasOwnProperty("Keyboard")){var t=e.Keyboard,n=new KeyboardEvent(t.event_name,{code:t.code,ctrlKey:t.modifier_state.ctrl_key,key:t.key,location:t.location,altKey:t.modifier_state.alt_key,repeat:t.repeat,shiftKey:t.shift_key,metaKey:t.meta_key});return Object.defineProperty(n,"__dispatch__",{value:!0}),Object.defineProperty(n,"__id__",{value:t.target.id}),n}e.hasOwnProperty("Mouse")||e.hasOwnProperty("Wheel")||e.hasOwnProperty("Focus")||e.hasOwnProperty("Base")}function addEvent(e,t,n,i){n.addEventListener(i.name,(function(n){n.hasOwnProperty("__dispatch__")||(i.prevent_default&&n.preventDefault(),i.no_propagate&&n.stopPropagation(),e.sendEvent(t,i.name,n))}),{passive:!i.prevent_default})}var EventHandler=function e(t,n,i){_classCallCheck(this,e),this.name=t,this.no_propagate=n,this.prevent_default=i},Context=function(){function e(t,n){_classCallCheck(this,e),this.app=n,this.id=t}return _createClass(e,[{key:"send",value:function(e){this.app.pipe.sendServiceMsg(this.id,e)}}]),e}(),App=function(){function App(e,t,n){_classCallCheck(this,App),this.pipe=e,this.dialog_handler=n||function(e,t){};var i=this;if(this.root_element=t,!this.root_element.firstElementChild){var r=document.createElement("div");t.appendChild(r)}this.pipe.onPatch=function(e){i.onPatch(e)},this.pipe.onRunJsMsg=function(e,t){i.onRunJsMsg(e,t)},this.pipe.onLoadCss=loadCss,this.pipe.onInjectEvent=injectEvent,this.pipe.onDialog=function(e){i.onDialog(e)},this.afterRender=[],this.blobs={}}return _createClass(App,[{key:"onDialog",value:function(e){this.dialog_handler(this,e)}},{key:"getBlob",value:function(e){return this.blobs[e]}},{key:"registerAfterRender",value:function(e){this.afterRender.push(e)}},{key:"onRunJsMsg",value:function onRunJsMsg(id,js){var ctx=new Context(id,this);!function(ctx){eval(js)}(ctx)}},{key:"sendReturnMessage",value:function(e){var t=JSON.stringify(e);this.pipe.socket.send(t)}},{key:"onPatch",value:function(e){var t=new Patch(e,this.root_element.firstElementChild,this),n=this;window.requestAnimationFrame((function(){t.apply();var e,i=_createForOfIteratorHelper(n.afterRender);try{for(i.s();!(e=i.n()).done;){(0,e.value)(n)}}catch(e){i.e(e)}finally{i.f()}}))}},{key:"close",value:function(){this.pipe.close()}},{key:"sendEvent",value:function(e,t,n){this.pipe.sendEvent(e,t,n)}},{key:"send",value:function(e,t){var n=parseInt(e.getAttribute("__id__"));this.pipe.sendRpc(n,t)}}]),App}(),Patch=function(){function e(t,n,i){_classCallCheck(this,e),this.buffer=t,this.patch=new DataView(t),this.offset=0,this.element=n,this.app=i,this.current_elem_rendered=!1,this.elements_rendered=[],this.blobs_changed=[],this.blobs_added=[],this.patch_funs={1:e.prototype.appendSibling,3:e.prototype.replace,4:e.prototype.changeText,5:e.prototype.ascend,6:e.prototype.descend,7:e.prototype.removeChildren,8:e.prototype.truncateSiblings,9:e.prototype.nextNode,10:e.prototype.removeAttribute,11:e.prototype.addAttribute,12:e.prototype.addAttribute,13:e.prototype.addBlob,14:e.prototype.removeBlob,15:e.prototype.removeJsEvent,16:e.prototype.addJsEvent,17:e.prototype.replaceJsEvent,18:e.prototype.addChildren}}return _createClass(e,[{key:"popU8",value:function(){var e=this.patch.getUint8(this.offset);return this.offset+=1,e}},{key:"apply",value:function(){for(;this.offset<this.patch.byteLength;){var e=this.popU8();this.patch_funs[e].call(this)}this.addToRendered(),this.invokeRenderedEvent();for(var t=this.blobs_changed.length,n=0;n<t;++n){var i=this.blobs_changed[n];i.changed(i)}t=this.blobs_added.length;for(n=0;n<t;++n){var r=this.blobs_added[n];r.added(r)}}},{key:"invokeRenderedEvent",value:function(){for(var e=this.elements_rendered.length,t=new Event("render"),n=0;n<e;++n){this.elements_rendered[n].dispatchEvent(t)}}},{key:"addToRendered",value:function(){this.current_elem_rendered&&this.element.__has_render_event&&(this.elements_rendered.push(this.element),this.current_elem_rendered=!1)}},{key:"deserializeEventFunction",value:function(){var e=this.deserializeString();return new Function("event",e)}},{key:"deserializeNode",value:function(){var e=this.popU8();return 0===e?this.deserializeElement():1===e?this.deserializeText():void 0}},{key:"appendSibling",value:function(){var e=this.deserializeNode();this.element.parentNode.appendChild(e),this.element=e}},{key:"replace",value:function(){var e=this.deserializeNode();this.element.parentNode.replaceChild(e,this.element),this.element=e}},{key:"changeText",value:function(){var e=this.deserializeText();this.element.parentNode.replaceChild(e,this.element),this.element=e}},{key:"ascend",value:function(){this.addToRendered(),this.element=this.element.parentNode}},{key:"descend",value:function(){this.addToRendered(),this.element=this.element.firstChild}},{key:"removeChildren",value:function(){for(;this.element.firstChild;)this.element.removeChild(this.element.firstChild)}},{key:"truncateSiblings",value:function(){for(var e=this.element.nextSibling;null!=e;){var t=e;e=e.nextSibling,this.element.parentNode.removeChild(t)}}},{key:"nextNode",value:function(){this.addToRendered();var e=this.patch.getUint32(this.offset,!0);this.offset+=4;for(var t=0;t<e;++t)this.element=this.element.nextSibling}},{key:"removeAttribute",value:function(){var e=this.deserializeString();this.element.removeAttribute(e),this.current_elem_rendered=!0}},{key:"addAttribute",value:function(){var e=this.deserializeString(),t=this.deserializeString();this.updateAttribute(this.element,e,t),this.current_elem_rendered=!0}},{key:"removeJsEvent",value:function(){var e=this.deserializeString(),t="__"+e,n=this.element[t];this.element.removeEventListener(e,n),this.element[t]=void 0,this.current_elem_rendered=!0}},{key:"addJsEvent",value:function(){var e=this.deserializeString(),t=this.deserializeEventFunction();"render"==e&&(this.element.__has_render_event=!0),this.element["__"+e]=t,this.element.addEventListener(e,t),this.current_elem_rendered=!0}},{key:"replaceJsEvent",value:function(){var e=this.deserializeString(),t=this.deserializeEventFunction(),n="__"+e,i=this.element[n];this.element.removeEventListener(e,i),this.element[n]=t,this.element.addEventListener(e,t),this.current_elem_rendered=!0}},{key:"addChildren",value:function(){var e=this.patch.getUint32(this.offset,!0);this.offset+=4;for(var t=0;t<e;++t){var n=this.deserializeNode();this.element.appendChild(n)}}},{key:"updateAttribute",value:function(e,t,n){"checked"==t&&e instanceof HTMLInputElement?e.checked="true"==n:e.setAttribute(t,n)}},{key:"deserializeElement",value:function(){var e=this.deserializeString();if(this.popU8()>0)var t=document.createElementNS(this.deserializeString(),e);else t=document.createElement(e);var n=this.deserializeId();null!==n&&t.setAttribute("__id__",n);var i=this.patch.getUint32(this.offset,!0);this.offset+=4;for(var r=0;r<i;++r){var s=this.deserializeString(),a=this.deserializeString();this.updateAttribute(t,s,a)}var o=this.patch.getUint32(this.offset,!0);this.offset+=4;for(r=0;r<o;++r){var l=this.deserializeEventHandler();addEvent(this.app,n,t,l)}var c=this.patch.getUint32(this.offset,!0);this.offset+=4;var u=!1;for(r=0;r<c;++r){var d=this.deserializeString();"render"==d&&(t.__has_render_event=!0,u=!0);var h=this.deserializeEventFunction();t["__"+d]=h,t.addEventListener(d,h)}var f=this.patch.getUint32(this.offset,!0);this.offset+=4;for(r=0;r<f;++r)t.appendChild(this.deserializeNode());return u&&this.elements_rendered.push(t),t}},{key:"deserializeText",value:function(){var e=this.deserializeString();return document.createTextNode(e)}},{key:"deserializeOption",value:function(e){return this.popU8()>0?e():null}},{key:"deserializeId",value:function(){if(!(this.popU8()>0))return null;var e=this.patch.getUint32(this.offset,!0),t=this.patch.getUint32(this.offset+4,!0);return this.offset+=8,e+Math.pow(2,32)*t}},{key:"deserializeU64",value:function(){var e=this.patch.getUint32(this.offset,!0),t=this.patch.getUint32(this.offset+4,!0);return this.offset+=8,e+Math.pow(2,32)*t}},{key:"deserializeString",value:function(){var e=this.patch.getUint32(this.offset,!0),t=new Uint8Array(this.buffer,this.offset+4,e);return this.offset+=e+4,decoder.decode(t)}},{key:"deserializeEventHandler",value:function(){var e=this.patch.getUint8(this.offset)>0,t=this.patch.getUint8(this.offset+1)>0;this.offset+=2;var n=this.deserializeString();return new EventHandler(n,e,t)}},{key:"addBlob",value:function(){var e=this.deserializeId(),t=this.deserializeU64(),n=this.deserializeString(),i=this.patch.getUint32(this.offset,!0),r=new Uint8Array(this.buffer,this.offset+4,i);this.offset+=i+4;var s={blob:new Blob([r],{type:n}),hash:t,changed:null,added:null},a=this.app.blobs.hasOwnProperty(e);if(this.popU8()>0){var o=this.deserializeString(),l=new Function("blob",o);s.added=l,a||this.blobs_added.push(s)}if(this.popU8()>0){var c=this.deserializeString(),u=new Function("blob",c);s.changed=u,a&&this.blobs_changed.push(s)}this.app.blobs[e]=s}},{key:"removeBlob",value:function(){var e=this.deserializeId();delete this.app.blobs[e]}}]),e}()},function(module,__webpack_exports__,__webpack_require__){"use strict";__webpack_require__.d(__webpack_exports__,"a",(function(){return Pipe}));var _event_js__WEBPACK_IMPORTED_MODULE_0__=__webpack_require__(2);function _classCallCheck(e,t){if(!(e instanceof t))throw new TypeError("Cannot call a class as a function")}function _defineProperties(e,t){for(var n=0;n<t.length;n++){var i=t[n];i.enumerable=i.enumerable||!1,i.configurable=!0,"value"in i&&(i.writable=!0),Object.defineProperty(e,i.key,i)}}function _createClass(e,t,n){return t&&_defineProperties(e.prototype,t),n&&_defineProperties(e,n),e}var Pipe=function(){function Pipe(e){_classCallCheck(this,Pipe),this.url=e,this.setupSocket(),this.onPatch=function(e){},this.onServiceMsg=function(e,t){},this.onRunJsMsg=function(e,t){},this.onLoadCss=function(e){},this.onInjectEvent=function(e,t,n){},this.onDialog=function(e){}}return _createClass(Pipe,[{key:"setupSocket",value:function(){var e=this;this.connected=!1,this.socket=new WebSocket(this.url),this.socket.binaryType="arraybuffer",this.socket.onopen=function(t){e.connected=!0},this.socket.onerror=function(t){e.retryConnect()},this.socket.onclose=function(t){e.retryConnect()},this.socket.onmessage=function(t){e.onMessage(t)}}},{key:"retryConnect",value:function(){var e=this;null!=this.socket&&(this.connected=!1,this.socket=null,setTimeout((function(){e.setupSocket()}),30))}},{key:"sendApplied",value:function(){if(null!=this.socket&&this.connected){var e=JSON.stringify({FrameApplied:[]});this.socket.send(e)}}},{key:"onMessage",value:function onMessage(event){if(event.data instanceof ArrayBuffer){var data=new Uint8Array(event.data);return this.onPatch(data.buffer),void this.sendApplied()}var msg=JSON.parse(event.data);if(msg.hasOwnProperty("Patch")){var _data=new Uint8Array(msg.Patch);this.onPatch(_data.buffer),this.sendApplied()}else if(msg.hasOwnProperty("Service")){var service_msg=msg.Service,id=service_msg[0];if(service_msg[1].hasOwnProperty("Frontend")){var frontend_msg=service_msg[1].Frontend;this.onServiceMsg(id,frontend_msg)}else if(service_msg[1].hasOwnProperty("RunJs")){var run_js_msg=service_msg[1].RunJs;this.onRunJsMsg(id,run_js_msg)}else service_msg[1].hasOwnProperty("LoadCss")&&this.onLoadCss(service_msg[1].LoadCss)}else if(msg.hasOwnProperty("LoadCss"))this.onloadCss(msg.LoadCss);else if(msg.hasOwnProperty("RunJs"))!function(){eval(msg.RunJs)}();else if(msg.hasOwnProperty("Propagate")){var _event=msg.Propagate.event,prop=msg.Propagate.propagate,default_action=msg.Propagate.default_action;this.onInjectEvent(_event,prop,default_action)}else msg.hasOwnProperty("Dialog")&&this.onDialog(msg.Dialog)}},{key:"sendEvent",value:function(e,t,n){if(null!=this.socket&&this.connected){var i={Event:Object(_event_js__WEBPACK_IMPORTED_MODULE_0__.a)(e,t,n)},r=JSON.stringify(i);this.socket.send(r)}}},{key:"sendServiceMsg",value:function(e,t){if(null!=this.socket&&this.connected){var n={Service:[e,{Frontend:t}]},i=JSON.stringify(n);this.socket.send(i)}}},{key:"close",value:function(){this.socket.close(),this.socket=null,this.connected=!1}},{key:"sendRpc",value:function(e,t){var n={ElementRpc:[e,t]},i=JSON.stringify(n);this.socket.send(i)}}]),Pipe}()},function(e,t,n){"use strict";function i(e,t){var n=Object.keys(e);if(Object.getOwnPropertySymbols){var i=Object.getOwnPropertySymbols(e);t&&(i=i.filter((function(t){return Object.getOwnPropertyDescriptor(e,t).enumerable}))),n.push.apply(n,i)}return n}function r(e){for(var t=1;t<arguments.length;t++){var n=null!=arguments[t]?arguments[t]:{};t%2?i(Object(n),!0).forEach((function(t){s(e,t,n[t])})):Object.getOwnPropertyDescriptors?Object.defineProperties(e,Object.getOwnPropertyDescriptors(n)):i(Object(n)).forEach((function(t){Object.defineProperty(e,t,Object.getOwnPropertyDescriptor(n,t))}))}return e}function s(e,t,n){return t in e?Object.defineProperty(e,t,{value:n,enumerable:!0,configurable:!0,writable:!0}):e[t]=n,e}function a(e){return{alt_key:e.altKey,ctrl_key:e.ctrlKey,meta_key:e.metaKey,shift_key:e.shiftKey}}function o(e,t){return{x:e,y:t}}function l(e,t,n){return{target:{id:e},event_name:t,modifier_state:a(n),button:n.button,buttons:n.buttons,client:o(n.clientX,n.clientY),offset:o(n.offsetX,n.offsetY),page:o(n.pageX,n.pageY),screen:o(n.screenX,n.screenY),target_value:c(n.target)}}function c(e){var t=e.value;return"string"==typeof t?{Text:t}:"boolean"==typeof t?{Bool:t}:"number"==typeof t?{Number:t}:"NoValue"}function u(e,t,n){return n instanceof WheelEvent?{Wheel:r(r({},{delta_x:n.deltaX,delta_y:n.deltaY,delta_z:n.deltaZ,delta_mode:n.deltaMode}),l(e,t,n))}:n instanceof MouseEvent?{Mouse:l(e,t,n)}:n instanceof KeyboardEvent?{Keyboard:{target:{id:e},event_name:t,modifier_state:a(n),code:n.code,key:n.key,location:n.location,repeat:n.repeat,bubble:!0,target_value:c(n.target)}}:n instanceof FocusEvent?{Focus:{target:{id:e},event_name:t,target_value:c(n.target)}}:{Base:{target:{id:e},event_name:t,target_value:c(n.target)}}}n.d(t,"a",(function(){return u}))},function(e,t,n){"use strict";n.r(t),n.d(t,"App",(function(){return s})),n.d(t,"Pipe",(function(){return a}));var i=n(0),r=n(1),s=i.a,a=r.a}]);
\ No newline at end of file
+// Generated from js/ by scripts/bundle.js, do not edit.
+var greenhorn = (function () {
+"use strict";
+var __modules = {
+"web_view.js": function (__exports, __require) {
+const {default: AppImport} = __require("app.js");
+const {default: PipeImport} = __require("websocket.js");
+
+const App = AppImport;
+const Pipe = PipeImport;
+
+    __exports.App = App;
+    __exports.Pipe = Pipe;
+},
+"app.js": function (__exports, __require) {
+const HistoryPanel = __require("history.js").default;
+
+const decoder = new TextDecoder();
+
+// Version of the binary patch format.
+// Must match `PATCH_FORMAT_VERSION` in src/vdom/serialize.rs
+const PATCH_FORMAT_VERSION = 5;
+
+function loadCss(css) {
+    var s = document.createElement("style");
+    s.innerHTML = css;
+    document.getElementsByTagName("head")[0].appendChild(s);
+}
+
+function injectEvent(event, prop, default_action) {
+    // TODO: use prop, default_action
+    let evt = deserializeEvent(event);
+    let query = "[__id__=\"" + evt.__id__ + "\"]";
+    let elem = document.querySelector(query);
+    elem.dispatchEvent(evt);
+}
+
+function deserializeEvent(event) {
+    if (event.hasOwnProperty("Keyboard")) {
+        let evt = event.Keyboard;
+        let ret = new KeyboardEvent(evt.event_name, {
+            "code": evt.code,
+            "ctrlKey": evt.modifier_state.ctrl_key,
+            "key": evt.key,
+            "location": evt.location,
+            "altKey": evt.modifier_state.alt_key,
+            "repeat": evt.repeat,
+            "shiftKey": evt.shift_key,
+            "metaKey": evt.meta_key,
+        });
+        Object.defineProperty(ret, "__dispatch__", {value: true});
+        Object.defineProperty(ret, "__id__", {value: evt.target.id});
+        return ret;
+    } else if (event.hasOwnProperty("Mouse")) {
+        // TODO: 
+    } else if (event.hasOwnProperty("Wheel")) {
+        // TODO: 
+    } else if (event.hasOwnProperty("Focus")) {
+        // TODO: 
+    } else if (event.hasOwnProperty("Base")) {
+        // TODO: 
+    }
+}
+
+function addEvent(app, id, elem, evt) {
+    // TODO: also support once
+    // TODO: also support useCapture
+    elem.addEventListener(evt.name, function(e) {
+        if (e.hasOwnProperty("__dispatch__")) {
+            return;
+        }
+        if (evt.prevent_default) {
+            e.preventDefault();
+        }
+        if (evt.no_propagate) { 
+            e.stopPropagation();
+        }
+        app.sendEvent(id, evt.name, e);
+    }, {'passive': !evt.prevent_default});
+}
+
+// Hashes a 32-bit word into a FNV-1a hash
+function checksumWord(hash, word) {
+    return Math.imul(hash ^ word, 0x01000193) >>> 0;
+}
+
+// Computes the checksum of the structure of a DOM subtree in the same way as the backend:
+// text nodes, elements along with the lower 32 bits of their ids and their nesting are covered.
+// Other nodes such as comments are not part of the patched DOM and are skipped.
+function checksumNode(node, hash) {
+    if (node.nodeType === Node.TEXT_NODE) {
+        return checksumWord(hash, 1);
+    }
+    if (node.nodeType !== Node.ELEMENT_NODE) {
+        return hash;
+    }
+    hash = checksumWord(hash, 2);
+    if (node.hasAttribute("__id__")) {
+        let id = Number(node.getAttribute("__id__"));
+        hash = checksumWord(checksumWord(hash, 1), id % 2**32);
+    } else {
+        hash = checksumWord(hash, 0);
+    }
+    for (const child of node.childNodes) {
+        hash = checksumNode(child, hash);
+    }
+    return checksumWord(hash, 3);
+}
+
+class EventHandler {
+    constructor(name, no_propagate, prevent_default) {
+        this.name = name;
+        this.no_propagate = no_propagate;
+        this.prevent_default = prevent_default;
+    }
+}
+
+class Context {
+    constructor(id, app) {
+        this.app = app;
+        this.id = id;
+    }
+
+    send(data) {
+        this.app.pipe.sendServiceMsg(this.id, data);
+    }
+}
+
+class App {
+    constructor(pipe, root_element, dialog_handler) {
+        this.pipe = pipe;
+        if (dialog_handler) {
+            this.dialog_handler = dialog_handler;
+        } else {
+            this.dialog_handler = (app, dialog) => {};
+        }
+        
+        let self = this;
+        this.root_element = root_element;
+        if (!this.root_element.firstElementChild) {
+            let elem = document.createElement("div");
+            root_element.appendChild(elem);
+        }
+
+        this.pipe.onPatch = (e, received) => {
+            self.onPatch(e, received);
+        }
+        this.pipe.onRunJsMsg = (id, js) => {
+            self.onRunJsMsg(id, js);
+        }
+        this.pipe.onLoadCss = loadCss;
+        this.pipe.onInjectEvent = injectEvent;
+        this.pipe.onDialog = (dialog) => { self.onDialog(dialog); };
+        this.pipe.onHistory = (update) => { self.onHistory(update); };
+
+        this.afterRender = [];
+        this.blobs = {}
+        this.history = null;
+        // server-rendered nodes may only be hydrated by the first patch
+        this.patched = false;
+    }
+
+    onDialog(dialog) {
+        this.dialog_handler(this, dialog);
+    }
+
+    onHistory(update) {
+        // the panel is only created if the backend keeps a history
+        if (this.history == null) {
+            this.history = new HistoryPanel(this.pipe);
+        }
+        this.history.update(update);
+    }
+
+    getBlob(blob_id) {
+        return this.blobs[blob_id];
+    }
+
+    registerAfterRender(fun) {
+        this.afterRender.push(fun);
+    }
+
+    onRunJsMsg(id, js) {
+        let ctx = new Context(id, this);
+        (function(ctx) {
+            eval(js);
+        })(ctx);        
+    }
+
+    sendReturnMessage(ret_msg) {
+        let data = JSON.stringify(ret_msg);
+        this.pipe.socket.send(data);
+    }
+
+    // Applies a patch on the next animation frame and acknowledges it to the backend,
+    // which paces its renders using the reported durations.
+    // If the patch cannot be applied, the failure is reported instead and the backend
+    // renders the DOM from scratch.
+    // `received` is the time the pipe received the patch, before decompressing it.
+    // The instructions are parsed by `patch.apply()`, thus `decode_us` only covers
+    // decompression and `apply_us` includes parsing.
+    onPatch(patch_data, received) {
+        if (received === undefined) {
+            received = performance.now();
+        }
+        let patch = new Patch(patch_data, this.root_element.firstElementChild, this);
+        let prepared = performance.now();
+        let self = this;
+        window.requestAnimationFrame(() => {
+            let before = performance.now();
+            try {
+                patch.apply();
+            } catch (e) {
+                console.error("Failed to apply patch: " + e.message);
+                self.pipe.sendPatchFailed(patch.frame === null ? 0 : patch.frame, e.message);
+                return;
+            }
+            self.patched = true;
+            for (const cb of self.afterRender) {
+                cb(self);
+            }
+            let after = performance.now();
+            self.pipe.sendApplied(patch.frame, {
+                "decode_us": Math.round((prepared - received) * 1000),
+                "apply_us": Math.round((after - before) * 1000),
+            });
+        });
+    }
+
+    close() {
+        if (this.history != null) {
+            this.history.close();
+        }
+        this.pipe.close();
+    }
+
+    sendEvent(id, name, evt) {
+        this.pipe.sendEvent(id, name, evt);
+    }
+
+    send(elem, data) {
+        let id = parseInt(elem.getAttribute('__id__'));
+        this.pipe.sendRpc(id, data);
+    }
+}
+
+class Patch {
+    constructor(patch, element, app) {
+        this.buffer = patch;
+        this.patch = new DataView(patch);
+        this.offset = 0;
+        this.strings = [];
+        this.element = element;
+        this.app = app;
+        this.frame = null;
+        this.current_elem_rendered = false;
+        this.elements_rendered = [];
+        this.blobs_changed = [];
+        this.blobs_added = [];
+        this.patch_funs = {
+            1: Patch.prototype.appendSibling,
+            3: Patch.prototype.replace,
+            4: Patch.prototype.changeText,
+            5: Patch.prototype.ascend,
+            6: Patch.prototype.descend,
+            7: Patch.prototype.removeChildren,
+            8: Patch.prototype.truncateSiblings,
+            9: Patch.prototype.nextNode,
+            10: Patch.prototype.removeAttribute,
+            11: Patch.prototype.addAttribute,
+            12: Patch.prototype.addAttribute,
+            13: Patch.prototype.addBlob,
+            14: Patch.prototype.removeBlob,
+            15: Patch.prototype.removeJsEvent,
+            16: Patch.prototype.addJsEvent,
+            17: Patch.prototype.replaceJsEvent,
+            18: Patch.prototype.addChildren,
+            19: Patch.prototype.insertChild,
+            20: Patch.prototype.moveChild,
+            21: Patch.prototype.removeChild,
+            22: Patch.prototype.hydrate,
+            23: Patch.prototype.verifyChecksum,
+        }
+    }
+
+    popU8() {
+        let ret = this.patch.getUint8(this.offset);
+        this.offset += 1;
+        return ret;
+    }
+
+    // Reads an unsigned LEB128 varint
+    popVarint() {
+        let ret = 0;
+        let scale = 1;
+        while (true) {
+            let x = this.patch.getUint8(this.offset);
+            this.offset += 1;
+            ret += (x & 0x7f) * scale;
+            if (x < 0x80) {
+                return ret;
+            }
+            scale *= 128;
+        }
+    }
+
+    apply() {
+        let version = this.popU8();
+        if (version !== PATCH_FORMAT_VERSION) {
+            throw new Error("Unsupported patch format version: " + version);
+        }
+        this.frame = this.popVarint();
+        while (this.offset < this.patch.byteLength) {
+            let x = this.popU8();
+            let fun = this.patch_funs[x];
+            fun.call(this);
+        }
+        this.addToRendered();
+        this.invokeRenderedEvent();
+
+        let len = this.blobs_changed.length;
+        for (var k = 0; k < len; ++k) {
+            let blob = this.blobs_changed[k];
+            blob.changed(blob);
+        }
+
+        len = this.blobs_added.length;
+        for (var k = 0; k < len; ++k) {
+            let blob = this.blobs_added[k];
+            blob.added(blob);
+        }
+
+    }
+
+    invokeRenderedEvent() {
+        let len = this.elements_rendered.length;
+        let evt = new Event("render");
+        for (var k = 0; k < len; ++k) {
+            let elem = this.elements_rendered[k];
+            elem.dispatchEvent(evt);
+        }
+    }
+
+    addToRendered() {
+        if (this.current_elem_rendered && this.element["__has_render_event"]) {
+            this.elements_rendered.push(this.element);
+            this.current_elem_rendered = false;
+        }
+    }
+
+    deserializeEventFunction() {
+        let code = this.deserializeString();
+        return new Function("event", code);
+    }
+
+    deserializeNode() {
+        return this.createNode(this.readNode());
+    }
+
+    appendSibling() {
+        let new_elem = this.deserializeNode();
+        this.element.parentNode.appendChild(new_elem);
+        this.element = new_elem;
+    }
+
+
+    replace() {
+        let new_elem = this.deserializeNode();
+        this.element.parentNode.replaceChild(new_elem, this.element);
+        this.element = new_elem;
+    }
+
+    changeText() {
+        let text = this.deserializeText();
+        this.element.parentNode.replaceChild(text, this.element);
+        this.element = text;
+    }
+
+    ascend() {
+        this.addToRendered();
+        this.element = this.element.parentNode;
+    }
+
+    descend() {
+        this.addToRendered();
+        this.element = this.element.firstChild;
+    }
+
+    removeChildren() {
+        while (this.element.firstChild) {
+            this.element.removeChild(this.element.firstChild);
+        }
+    }
+
+    truncateSiblings() {
+        let next = this.element.nextSibling;
+        while (next != null) {
+            let to_remove = next;
+            next = next.nextSibling;
+            this.element.parentNode.removeChild(to_remove);
+        }
+    }
+
+    nextNode() {
+        this.addToRendered();
+        let len = this.popVarint();
+        for (let k = 0; k < len; ++k) {
+            this.element = this.element.nextSibling;
+        }
+    }
+
+    removeAttribute() {
+        let attr = this.deserializeInterned();
+        this.element.removeAttribute(attr);
+        this.current_elem_rendered = true;
+    }
+
+    addAttribute() {
+        let key = this.deserializeInterned();
+        let value = this.deserializeString();
+        this.updateAttribute(this.element, key, value);
+        this.current_elem_rendered = true;
+    }
+
+    removeJsEvent() {
+        let attr = this.deserializeInterned();
+        let attr_key = '__' + attr;
+        let attr_value = this.element[attr_key];
+        this.element.removeEventListener(attr, attr_value);
+        this.element[attr_key] = undefined;
+        this.current_elem_rendered = true;
+    }
+
+    addJsEvent() {
+        let key = this.deserializeInterned();
+        let fun = this.deserializeEventFunction();
+        if (key == "render") {
+            this.element["__has_render_event"] = true;
+        }
+        this.element['__' + key] = fun;
+        this.element.addEventListener(key, fun);
+        this.current_elem_rendered = true;
+    }
+
+    replaceJsEvent() {
+        let key = this.deserializeInterned();
+        let fun = this.deserializeEventFunction();
+        let key_attr = '__' + key;
+        let attr_value = this.element[key_attr];
+        this.element.removeEventListener(key, attr_value);
+        this.element[key_attr] = fun;
+        this.element.addEventListener(key, fun);
+        this.current_elem_rendered = true;
+    }
+
+    addChildren() {
+        let len = this.popVarint();
+        for (var k = 0; k < len; ++k) {
+            let elem = this.deserializeNode();
+            this.element.appendChild(elem);
+        }
+    }
+
+    insertChild() {
+        let idx = this.popVarint();
+        let new_elem = this.deserializeNode();
+        let before = this.element.childNodes[idx];
+        this.element.insertBefore(new_elem, before ? before : null);
+    }
+
+    moveChild() {
+        let from = this.popVarint();
+        let to = this.popVarint();
+        let child = this.element.childNodes[from];
+        this.element.removeChild(child);
+        let before = this.element.childNodes[to];
+        this.element.insertBefore(child, before ? before : null);
+    }
+
+    removeChild() {
+        let idx = this.popVarint();
+        this.element.removeChild(this.element.childNodes[idx]);
+    }
+
+    // Compares the structure of the DOM with the checksum computed by the backend,
+    // see `Node::checksum()` in src/patch/model.rs.
+    verifyChecksum() {
+        let expected = this.popVarint();
+        let actual = checksumNode(this.app.root_element.firstElementChild, 0x811c9dc5);
+        if (actual !== expected) {
+            throw new Error("Checksum mismatch: expected " + expected.toString(16)
+                + ", found " + actual.toString(16));
+        }
+    }
+
+    // Attaches to server-rendered nodes instead of replacing them.
+    // Only the first patch of an application hydrates, afterwards this is the same as `replace()`.
+    hydrate() {
+        let desc = this.readNode();
+        if (this.app.patched) {
+            let new_elem = this.createNode(desc);
+            this.element.parentNode.replaceChild(new_elem, this.element);
+            this.element = new_elem;
+        } else {
+            this.element = this.hydrateNode(desc, this.element);
+        }
+    }
+
+    // Matches a node description against an existing node, assigns ids and installs listeners.
+    // Nodes which do not match are replaced. Returns the resulting node.
+    hydrateNode(desc, node) {
+        if (desc.text !== undefined) {
+            if (node.nodeType !== Node.TEXT_NODE) {
+                return this.replaceNode(desc, node);
+            }
+            if (node.nodeValue !== desc.text) {
+                node.nodeValue = desc.text;
+            }
+            return node;
+        }
+        if (!this.matchesElement(desc, node)) {
+            return this.replaceNode(desc, node);
+        }
+        // comments separate adjacent text nodes, but must not be visited by later patches
+        for (const child of Array.from(node.childNodes)) {
+            if (child.nodeType === Node.COMMENT_NODE) {
+                node.removeChild(child);
+            }
+        }
+        if (node.childNodes.length !== desc.children.length) {
+            return this.replaceNode(desc, node);
+        }
+        for (const attr of Array.from(node.attributes)) {
+            if (!desc.attrs.some((x) => x.key === attr.name)) {
+                node.removeAttribute(attr.name);
+            }
+        }
+        this.initElement(node, desc);
+        let children = Array.from(node.childNodes);
+        for (var k = 0; k < children.length; ++k) {
+            this.hydrateNode(desc.children[k], children[k]);
+        }
+        this.pushRendered(node);
+        return node;
+    }
+
+    matchesElement(desc, node) {
+        if (node.nodeType !== Node.ELEMENT_NODE) {
+            return false;
+        }
+        if (desc.namespace === null) {
+            return node.namespaceURI === "http://www.w3.org/1999/xhtml"
+                && node.localName === desc.tag.toLowerCase();
+        }
+        return node.namespaceURI === desc.namespace && node.localName === desc.tag;
+    }
+
+    replaceNode(desc, node) {
+        let new_elem = this.createNode(desc);
+        node.parentNode.replaceChild(new_elem, node);
+        return new_elem;
+    }
+
+    // Reads a node without creating it, such that it can be matched against existing nodes.
+    readNode() {
+        let x = this.popU8();
+        if (x === 1) {
+            return {"text": this.deserializeString()};
+        }
+        let desc = {"tag": this.deserializeInterned()};
+        let hasNamespace = this.popU8() > 0;
+        desc.namespace = hasNamespace ? this.deserializeInterned() : null;
+        desc.id = this.deserializeId();
+        desc.attrs = [];
+        let attr_len = this.popVarint();
+        for (var k = 0; k < attr_len; ++k) {
+            let key = this.deserializeInterned();
+            desc.attrs.push({"key": key, "value": this.deserializeString()});
+        }
+        desc.events = [];
+        let events_len = this.popVarint();
+        for (var k = 0; k < events_len; ++k) {
+            desc.events.push(this.deserializeEventHandler());
+        }
+        desc.js_events = [];
+        let js_events_len = this.popVarint();
+        for (var k = 0; k < js_events_len; ++k) {
+            let key = this.deserializeInterned();
+            desc.js_events.push({"key": key, "fun": this.deserializeEventFunction()});
+        }
+        desc.children = [];
+        let children_len = this.popVarint();
+        for (var k = 0; k < children_len; ++k) {
+            desc.children.push(this.readNode());
+        }
+        return desc;
+    }
+
+    // Creates a node from a description returned by `readNode()`
+    createNode(desc) {
+        if (desc.text !== undefined) {
+            return document.createTextNode(desc.text);
+        }
+        let elem;
+        if (desc.namespace !== null) {
+            elem = document.createElementNS(desc.namespace, desc.tag);
+        } else {
+            elem = document.createElement(desc.tag);
+        }
+        this.initElement(elem, desc);
+        for (const child of desc.children) {
+            elem.appendChild(this.createNode(child));
+        }
+        this.pushRendered(elem);
+        return elem;
+    }
+
+    // Assigns the id, attributes and listeners of a node description to an element
+    initElement(elem, desc) {
+        if (desc.id !== null) {
+            elem.setAttribute("__id__", desc.id);
+        }
+        for (const attr of desc.attrs) {
+            this.updateAttribute(elem, attr.key, attr.value);
+        }
+        for (const evt of desc.events) {
+            addEvent(this.app, desc.id, elem, evt);
+        }
+        for (const evt of desc.js_events) {
+            if (evt.key == "render") {
+                elem["__has_render_event"] = true;
+            }
+            elem['__' + evt.key] = evt.fun;
+            elem.addEventListener(evt.key, evt.fun);
+        }
+    }
+
+    // Schedules the `render` event of an element, once its children have been created
+    pushRendered(elem) {
+        if (elem["__has_render_event"]) {
+            this.elements_rendered.push(elem);
+        }
+    }
+
+    updateAttribute(elem, key, value) {
+        if (key == "checked" && elem instanceof HTMLInputElement) {
+            elem.checked = (value == 'true');
+        } else {
+            elem.setAttribute(key, value);
+        }
+    }
+
+    deserializeText() {
+        let text = this.deserializeString();
+        return document.createTextNode(text); 
+    }
+
+    deserializeOption(deserializer) {
+        let available = this.popU8() > 0;
+        if (available) {
+            return deserializer();
+        }
+        return null;
+    }
+
+    deserializeId() {
+        let available = this.popU8() > 0;
+        if (!available) {
+            return null;
+        }
+        let lo = this.patch.getUint32(this.offset, true);
+        let hi = this.patch.getUint32(this.offset + 4, true);
+        this.offset += 8;
+        return lo + (2**32)*hi;
+    }
+
+    deserializeU64() {
+        let lo = this.patch.getUint32(this.offset, true);
+        let hi = this.patch.getUint32(this.offset + 4, true);
+        this.offset += 8;
+        return lo + (2**32)*hi;
+    }
+
+    deserializeString() {
+        let len = this.popVarint();
+        let view = new Uint8Array(this.buffer, this.offset, len);
+        this.offset += len;
+        return decoder.decode(view);
+    }
+
+    // Reads a string from the string table of this patch.
+    // A reference of 0 denotes a new string, which is added to the table.
+    deserializeInterned() {
+        let idx = this.popVarint();
+        if (idx === 0) {
+            let ret = this.deserializeString();
+            this.strings.push(ret);
+            return ret;
+        }
+        return this.strings[idx - 1];
+    }
+
+    deserializeEventHandler() {
+        let no_prop = this.patch.getUint8(this.offset) > 0;
+        let prevent_default = this.patch.getUint8(this.offset + 1) > 0;
+        this.offset += 2;
+        let name = this.deserializeInterned();
+        return new EventHandler(name, no_prop, prevent_default);
+    }
+
+    addBlob() {
+        let id = this.deserializeId();
+        let hash = this.deserializeU64();
+        let mime_type = this.deserializeInterned();
+        let len = this.popVarint();
+        let view = new Uint8Array(this.buffer, this.offset, len);
+        this.offset += len;
+        let blob = {'blob': new Blob([view], {"type": mime_type}), 'hash': hash, 'changed': null, 'added': null};
+
+        let changed = this.app.blobs.hasOwnProperty(id);
+
+        let add_available = this.popU8() > 0;
+        if (add_available) {
+            let code = this.deserializeString();
+            let fun = new Function("blob", code);
+            blob.added = fun;
+            if (!changed) {
+                this.blobs_added.push(blob);
+            }            
+        }
+
+        let changed_available = this.popU8() > 0;
+        if (changed_available) {
+            let code = this.deserializeString();
+            let fun = new Function("blob", code);
+            blob.changed = fun;
+            if (changed) {
+                this.blobs_changed.push(blob);
+            }            
+        }
+        this.app.blobs[id] = blob;
+    }
+
+    removeBlob() {
+        let id = this.deserializeId();
+        delete this.app.blobs[id];
+    }
+}
+
+    __exports.default = App;
+    __exports.Patch = Patch;
+},
+"websocket.js": function (__exports, __require) {
+const serializeEvent = __require("event.js").default;
+const {serializeHandshake, parseHandshake, supports, supportsInflate, inflate, CLOSE_PROTOCOL_ERROR, CAPABILITY_DEFLATE, FRAME_RAW_PATCH, FRAME_DEFLATE_PATCH, FRAME_DEFLATE_JSON} = __require("protocol.js");
+
+
+class Pipe {
+    constructor(url) {
+        this.url = url;
+        this.setupSocket();
+        this.onPatch = (patch_data, received) => {};
+        this.onServiceMsg = (id, service_msg) => {};
+        this.onRunJsMsg = (id, run_js_msg) => {};
+        this.onLoadCss = (css) => {};
+        this.onInjectEvent = (event, prop, default_action) => {};
+        this.onDialog = (dialog) => {}
+        this.onHistory = (update) => {};
+    }
+
+    setupSocket() {
+        let self = this;
+        this.connected = false;
+        this.handshake = null;
+        this.deflate = false;
+        // decompression is asynchronous, thus messages are queued to retain their order
+        this.queue = Promise.resolve();
+        this.socket = new WebSocket(this.url);
+        this.socket.binaryType = "arraybuffer";
+        this.socket.onopen = (e) => { 
+            let capabilities = supportsInflate() ? [CAPABILITY_DEFLATE] : [];
+            self.socket.send(serializeHandshake(capabilities));
+        };
+        this.socket.onerror = (e) => {
+            self.retryConnect();
+        }
+        this.socket.onclose = (e) => { 
+            if (e.code === CLOSE_PROTOCOL_ERROR) {
+                // reconnecting does not help, the frontend must be reloaded
+                console.error("Greenhorn protocol error: " + e.reason);
+                self.socket = null;
+                self.connected = false;
+                return;
+            }
+            self.retryConnect();
+        };
+        this.socket.onmessage = (e) => { self.onMessage(e); };
+    }
+
+    retryConnect() {
+        let self = this;
+        
+        if (this.socket == null) {
+            return;
+        }
+        this.connected = false;
+        this.socket = null;
+        setTimeout(() => {
+            self.setupSocket();
+        }, 30);
+    }
+
+    // Acknowledges the patch of the given frame along with its decode and apply durations in microseconds
+    sendApplied(frame, timing) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let reply = JSON.stringify({"FrameApplied": [frame, timing]});
+        this.socket.send(reply);
+    }
+
+    // Reports that the patch of the given frame could not be applied
+    sendPatchFailed(frame, reason) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let reply = JSON.stringify({"PatchFailed": [frame, reason]});
+        this.socket.send(reply);
+    }
+
+    onMessage(event) {
+        // the first message is the reply to our handshake
+        if (this.handshake == null) {
+            try {
+                this.handshake = parseHandshake(event.data);
+                this.deflate = supports(this.handshake, CAPABILITY_DEFLATE);
+                this.connected = true;
+            } catch (e) {
+                console.error(e.message);
+                this.close();
+            }
+            return;
+        }
+
+        let received = performance.now();
+        if (this.deflate) {
+            let socket = this.socket;
+            this.queue = this.queue
+                .then(() => this.decompress(event.data))
+                .then((data) => {
+                    // drop messages of a closed connection
+                    if (this.socket === socket) {
+                        this.dispatch(data, received);
+                    }
+                })
+                .catch((e) => console.error(e));
+        } else {
+            this.dispatch(event.data, received);
+        }
+    }
+
+    // Strips the frame header of binary messages and decompresses them if required.
+    // Resolves to either a patch as ArrayBuffer or a JSON string.
+    decompress(data) {
+        if (!(data instanceof ArrayBuffer)) {
+            return Promise.resolve(data);
+        }
+        let header = new Uint8Array(data, 0, 1)[0];
+        let payload = data.slice(1);
+        if (header === FRAME_RAW_PATCH) {
+            return Promise.resolve(payload);
+        } else if (header === FRAME_DEFLATE_PATCH) {
+            return inflate(payload);
+        } else if (header === FRAME_DEFLATE_JSON) {
+            return inflate(payload).then((x) => new TextDecoder().decode(x));
+        }
+        return Promise.reject(new Error("Invalid frame header: " + header));
+    }
+
+    dispatch(data, received) {
+
+        // conclusion on performance testing:
+        // JSON.parse is much faster then msgpack.decode()
+        // json serialization on server is approx 2x slower
+        // however, since serialization may be run in parallel
+        // on server but must be run on a single thread here
+        // we are better off just using json
+
+        // in case we get binary data it must be a Patch
+        if (data instanceof ArrayBuffer) {
+            this.onPatch(data, received);
+            return;
+        }
+
+        // in case we get text data in can be any type of message
+        let msg = JSON.parse(data);
+        if (msg.hasOwnProperty("Patch")) {
+            let data = new Uint8Array(msg.Patch);
+            this.onPatch(data.buffer, received);
+        } else if (msg.hasOwnProperty("Service")) {
+            let service_msg = msg.Service;
+            let id = service_msg[0];
+            if (service_msg[1].hasOwnProperty("Frontend")) {
+                let frontend_msg = service_msg[1].Frontend;
+                this.onServiceMsg(id, frontend_msg);
+            } else if (service_msg[1].hasOwnProperty("RunJs")) {
+                let run_js_msg = service_msg[1].RunJs;
+                this.onRunJsMsg(id, run_js_msg);
+            } else if (service_msg[1].hasOwnProperty("LoadCss")) {
+                this.onLoadCss(service_msg[1].LoadCss);
+            }
+        } else if (msg.hasOwnProperty("LoadCss")) {
+            this.onLoadCss(msg.LoadCss);
+        } else if (msg.hasOwnProperty("RunJs")) {
+            (function() {
+                eval(msg.RunJs);
+            })();
+        } else if (msg.hasOwnProperty("Propagate")) {
+            let event = msg.Propagate.event;
+            let prop = msg.Propagate.propagate;
+            let default_action = msg.Propagate.default_action;
+            this.onInjectEvent(event, prop, default_action);
+        } else if (msg.hasOwnProperty("Dialog")) {
+            this.onDialog(msg.Dialog);
+        } else if (msg.hasOwnProperty("History")) {
+            this.onHistory(msg.History);
+        }
+    }
+
+    sendEvent(id, name, evt) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let serialized = serializeEvent(id, name, evt);
+        let msg = {
+            "Event": serialized
+        };
+        let data = JSON.stringify(msg);
+        this.socket.send(data);
+    }
+
+    sendServiceMsg(id, data) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let msg = {
+            "Service": [id, {"Frontend": data}]
+        };
+        let serialized = JSON.stringify(msg);
+        this.socket.send(serialized);
+    }
+
+    close() {
+        this.socket.close();
+        this.socket = null;
+        this.connected = false;
+    }
+
+    sendRpc(id, data) {
+        let msg = {
+            "ElementRpc": [id, data]  
+        };
+        let serialized = JSON.stringify(msg);
+        this.socket.send(serialized);
+    }
+
+    sendTimeTravel(id) {
+        if (this.socket == null || !this.connected) {
+            return;
+        }
+        let msg = {
+            "TimeTravel": id
+        };
+        this.socket.send(JSON.stringify(msg));
+    }
+}
+
+    __exports.default = Pipe;
+},
+"history.js": function (__exports, __require) {
+// Overlay panel listing the message history of the backend.
+// Only shown if the runtime keeps a history, see `Runtime::history()`.
+// Toggled by pressing Ctrl+Shift+H. Clicking an entry with a snapshot
+// restores the application state after processing its message.
+class HistoryPanel {
+    constructor(pipe) {
+        this.pipe = pipe;
+        this.entries = [];
+        this.current = null;
+
+        this.element = document.createElement("div");
+        this.element.style.cssText = "position: fixed; top: 0; right: 0; bottom: 0; width: 360px;"
+            + "overflow-y: auto; z-index: 2147483647; background: rgba(30, 30, 30, 0.92);"
+            + "color: #ddd; font: 12px monospace; display: none;";
+        document.body.appendChild(this.element);
+
+        let self = this;
+        this.onKeyDown = (e) => {
+            if (e.ctrlKey && e.shiftKey && e.code === "KeyH") {
+                e.preventDefault();
+                self.toggle();
+            }
+        };
+        window.addEventListener("keydown", this.onKeyDown);
+    }
+
+    // Applies a `HistoryUpdate` as sent by the backend
+    update(update) {
+        this.entries = this.entries.filter((x) => x.id >= update.first);
+        for (const entry of update.entries) {
+            this.entries.push(entry);
+        }
+        this.current = update.current;
+        if (this.isVisible()) {
+            this.render();
+        }
+    }
+
+    isVisible() {
+        return this.element.style.display !== "none";
+    }
+
+    toggle() {
+        this.element.style.display = this.isVisible() ? "none" : "block";
+        if (this.isVisible()) {
+            this.render();
+        }
+    }
+
+    render() {
+        this.element.innerHTML = "";
+        let title = document.createElement("div");
+        title.style.cssText = "padding: 6px; font-weight: bold; border-bottom: 1px solid #555;";
+        title.textContent = "Message history (" + this.entries.length + ")";
+        this.element.appendChild(title);
+        let self = this;
+        for (const entry of this.entries) {
+            let row = document.createElement("div");
+            row.style.cssText = "padding: 4px 6px; border-bottom: 1px solid #333; white-space: pre-wrap;"
+                + "word-break: break-all;";
+            if (entry.id === this.current) {
+                row.style.background = "#264f78";
+            }
+            let updated = entry.render ? "render" : "no render";
+            if (entry.invalidated > 0) {
+                updated += ", " + entry.invalidated + " component(s)";
+            }
+            row.textContent = "#" + entry.id + " +" + entry.time_ms + "ms [" + updated + "]\n" + entry.message;
+            if (entry.snapshot) {
+                row.style.cursor = "pointer";
+                row.title = "Restore the state after this message";
+                row.onclick = () => { self.pipe.sendTimeTravel(entry.id); };
+            }
+            this.element.appendChild(row);
+        }
+    }
+
+    close() {
+        window.removeEventListener("keydown", this.onKeyDown);
+        this.element.remove();
+    }
+}
+
+    __exports.default = HistoryPanel;
+},
+"event.js": function (__exports, __require) {
+function serializeModifierState(evt) {
+    return {
+        "alt_key": evt.altKey,
+        "ctrl_key": evt.ctrlKey,
+        "meta_key": evt.metaKey,
+        "shift_key": evt.shiftKey
+    };
+}
+
+function serializePoint(x,y) {
+    return {
+        "x": x,
+        "y": y
+    };
+}
+
+function serializeMouseEvent(id, name, evt) {
+    return {
+        "target": {"id": id},
+        "event_name": name,
+        "modifier_state": serializeModifierState(evt),
+        "button": evt.button,
+        "buttons": evt.buttons,
+        "client": serializePoint(evt.clientX, evt.clientY),
+        "offset": serializePoint(evt.offsetX, evt.offsetY),
+        "page": serializePoint(evt.pageX, evt.pageY),
+        "screen": serializePoint(evt.screenX, evt.screenY),
+        "target_value": serializeTargetValue(evt.target)
+    };
+}
+
+function serializeTargetValue(target) {
+    let v =  target.value;
+    if (typeof v === "string") {
+        return {"Text": v};
+    } else if (typeof v === "boolean") {
+        return {"Bool": v};
+    } else if (typeof v === "number") {
+        return {"Number": v};
+    } else {
+        return "NoValue";
+    }    
+}
+
+function serializeEvent(id, name, evt) {
+    if (evt instanceof WheelEvent) {
+        let wheel =  {
+            "delta_x": evt.deltaX,
+            "delta_y": evt.deltaY,
+            "delta_z": evt.deltaZ,
+            "delta_mode": evt.deltaMode
+        };
+        return {
+            "Wheel": { ...wheel, ...serializeMouseEvent(id, name, evt) }
+        }
+    } else if (evt instanceof MouseEvent) {
+        return {
+            "Mouse": serializeMouseEvent(id, name, evt)
+        }
+    } else if (evt instanceof KeyboardEvent) {
+        return {
+            "Keyboard": {
+                "target": {"id": id},
+                "event_name": name,            
+                "modifier_state": serializeModifierState(evt),
+                "code": evt.code,
+                "key": evt.key,
+                "location": evt.location,
+                "repeat": evt.repeat,
+                "bubble": true,
+                "target_value": serializeTargetValue(evt.target)
+            }
+        }
+    } else if (evt instanceof FocusEvent) {
+        return {
+            "Focus": {
+                "target": {"id": id},
+                "event_name": name,            
+                "target_value": serializeTargetValue(evt.target)
+            }
+        }
+    } else {
+        return {
+            "Base": {
+                "target": {"id": id},
+                "event_name": name,            
+                "target_value": serializeTargetValue(evt.target)
+            }
+        }
+    }
+}
+
+    __exports.default = serializeEvent;
+},
+"protocol.js": function (__exports, __require) {
+// Version of the protocol spoken between frontend and backend.
+// Must match `PROTOCOL_VERSION` in src/pipe.rs
+const PROTOCOL_VERSION = 7;
+
+// Optional protocol capabilities supported by this frontend
+const CAPABILITIES = [];
+
+// Capability to compress messages using raw deflate.
+// Must match `CAPABILITY_DEFLATE` in src/pipe.rs
+const CAPABILITY_DEFLATE = "deflate";
+
+// Once deflate has been negotiated, binary messages start with one of these header bytes
+const FRAME_RAW_PATCH = 0;
+const FRAME_DEFLATE_PATCH = 1;
+const FRAME_DEFLATE_JSON = 2;
+
+// Close code used by the backend if the handshake has failed
+const CLOSE_PROTOCOL_ERROR = 1002;
+
+// Serializes the handshake of the frontend.
+// Pipes may pass additional capabilities they support.
+function serializeHandshake(capabilities = []) {
+    return JSON.stringify({
+        "version": PROTOCOL_VERSION,
+        "capabilities": CAPABILITIES.concat(capabilities)
+    });
+}
+
+// Returns true if the backend agreed on using the given capability
+function supports(handshake, capability) {
+    return handshake.capabilities.indexOf(capability) >= 0;
+}
+
+// Returns true if the browser is able to decompress raw deflate data.
+// Some browsers only support the "gzip" and "deflate" formats of `DecompressionStream`.
+function supportsInflate() {
+    if (typeof DecompressionStream === "undefined") {
+        return false;
+    }
+    try {
+        new DecompressionStream("deflate-raw");
+        return true;
+    } catch (e) {
+        return false;
+    }
+}
+
+// Decompresses raw deflate data and resolves to an ArrayBuffer
+function inflate(data) {
+    let stream = new Blob([data]).stream().pipeThrough(new DecompressionStream("deflate-raw"));
+    return new Response(stream).arrayBuffer();
+}
+
+// Parses the handshake reply of the backend.
+// Throws an error in case frontend and backend do not agree on the protocol.
+function parseHandshake(data) {
+    let handshake = JSON.parse(data);
+    if (handshake.hasOwnProperty("error")) {
+        throw new Error("Greenhorn handshake failed: " + handshake.error);
+    }
+    if (handshake.version !== PROTOCOL_VERSION) {
+        throw new Error("Greenhorn protocol version mismatch: frontend speaks version "
+            + PROTOCOL_VERSION + ", backend speaks version " + handshake.version);
+    }
+    return handshake;
+}
+
+    __exports.PROTOCOL_VERSION = PROTOCOL_VERSION;
+    __exports.CAPABILITIES = CAPABILITIES;
+    __exports.CAPABILITY_DEFLATE = CAPABILITY_DEFLATE;
+    __exports.FRAME_RAW_PATCH = FRAME_RAW_PATCH;
+    __exports.FRAME_DEFLATE_PATCH = FRAME_DEFLATE_PATCH;
+    __exports.FRAME_DEFLATE_JSON = FRAME_DEFLATE_JSON;
+    __exports.CLOSE_PROTOCOL_ERROR = CLOSE_PROTOCOL_ERROR;
+    __exports.serializeHandshake = serializeHandshake;
+    __exports.supports = supports;
+    __exports.supportsInflate = supportsInflate;
+    __exports.inflate = inflate;
+    __exports.parseHandshake = parseHandshake;
+}
+};
+var __cache = {};
+function __require(name) {
+    if (!__cache.hasOwnProperty(name)) {
+        __cache[name] = {};
+        __modules[name](__cache[name], __require);
+    }
+    return __cache[name];
+}
+return __require("web_view.js");
+})();
@@ -7,6 +7,7 @@ use async_std::net::TcpListener;
 use async_std::task;
 use greenhorn::pipe::{TxMsg, RxMsg};
 use greenhorn::dialog::native_dialogs;
+use greenhorn::websockets::Access;
 
 pub struct ViewBuilder {
     pub css: Vec<String>,
@@ -62,15 +63,32 @@ impl<'a> ViewBuilder {
     }
 
     pub fn format_html(&self, port: u16) -> String {
+        self.format_page(port, None)
+    }
+
+    /// Same as `format_html()`, but the frontend presents `token` when connecting,
+    /// as required by `Access::require_token()`.
+    pub fn format_html_with_token(&self, port: u16, token: &str) -> String {
+        self.format_page(port, Some(token))
+    }
+
+    fn format_page(&self, port: u16, token: Option<&str>) -> String {
+        // a JSON string is a valid JS string literal, but must not terminate the script element
+        let token = serde_json::to_string(&token).unwrap().replace("</", "<\\/");
         let js_main = format!("window.onload = function() {{ \
-            let pipe = new greenhorn.Pipe(\"ws://127.0.0.1:\" + {});
+            let url = \"ws://127.0.0.1:\" + {};
+            let token = {};
+            if (token !== null) {{
+                url += \"/?token=\" + encodeURIComponent(token);
+            }}
+            let pipe = new greenhorn.Pipe(url);
             let dialog_handler = (app, dialog) => {{
                 let in_msg = {{ 'Dialog': dialog }};
                 external.invoke(JSON.stringify(in_msg));
             }};
             let app = new greenhorn.App(pipe, document.body, dialog_handler);
             window.app = app;
-        }}", port);
+        }}", port, token);
         let js_lib = include_str!("../res/bundle.js");
         let mut additional = Vec::new();
         for x in &self.js {
@@ -104,10 +122,12 @@ impl<'a> ViewBuilder {
         }).expect("Failed to bind");
 
         let port = socket.local_addr().unwrap().port();
+        // only the page loaded into the web view may connect to the application
+        let token = Access::generate_token();
 
         let ret = web_view::builder()
             .title(&self.title)
-            .content(Content::Html(self.format_html(port)))
+            .content(Content::Html(self.format_html_with_token(port, &token)))
             .size(self.width, self.height)
             .debug(self.debug)
             .resizable(true)
@@ -120,7 +140,8 @@ impl<'a> ViewBuilder {
             .unwrap();
 
 
-        let pipe = WebSocketPipe::listen_to_socket(socket);
+        let access = Access::new().require_token(token);
+        let pipe = WebSocketPipe::listen_to_socket_with_access(socket, access);
 
         let thread = thread::spawn(move || fun(pipe) );
 